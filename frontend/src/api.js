@@ -1,9 +1,62 @@
 class Api {
   constructor() {}
 
-  async searchKitsu(text) {
+  async getLibrary(sort = "title", order = "asc", offset = 0, limit = 50) {
+    let params = new URLSearchParams();
+    params.set("sort", sort);
+    params.set("order", order);
+    params.set("offset", offset);
+    params.set("limit", limit);
+
+    let response = await fetch(`/api/anime?${params}`);
+    let json = await response.json();
+    if (response.status != 200) throw convertToError(json);
+    return json;
+  }
+
+  async getLibraryAnime(id, animeType = "sub") {
+    let params = new URLSearchParams();
+    params.set("type", animeType);
+
+    let response = await fetch(`/api/anime/${id}?${params}`);
+    let json = await response.json();
+    if (response.status != 200) throw convertToError(json);
+    return json;
+  }
+
+  async addLibraryAnime(id) {
+    let response = await fetch(`/api/anime/${id}`, {
+      method: "PUT",
+    });
+    let json = await response.json();
+    if (response.status != 200) throw convertToError(json);
+    return json;
+  }
+
+  async removeLibraryAnime(id) {
+    let response = await fetch(`/api/anime/${id}`, {
+      method: "DELETE",
+    });
+    let json = await response.json();
+    if (response.status != 200) throw convertToError(json);
+    return json;
+  }
+
+  async searchKitsu(text, mode) {
     let params = new URLSearchParams();
     if (text !== null && text !== undefined) params.set("text", text);
+    if (mode !== null && mode !== undefined) params.set("mode", mode);
+
+    let response = await fetch(`/api/kitsu/anime?${params}`);
+    let json = await response.json();
+    if (response.status != 200) throw convertToError(json);
+    return json;
+  }
+
+  // `site` is one of "mal", "anilist", or "anidb".
+  async getKitsuAnimeByExternalId(site, id) {
+    let params = new URLSearchParams();
+    params.set(site, id);
 
     let response = await fetch(`/api/kitsu/anime?${params}`);
     let json = await response.json();
@@ -32,15 +85,169 @@ class Api {
     return json;
   }
 
-  async getVidstreamingEpisode(id) {
-    let response = await fetch(`/api/vidstreaming/${id}`);
+  async getVidstreamingEpisode(id, animeType = "sub") {
+    let params = new URLSearchParams();
+    params.set("type", animeType);
+
+    let response = await fetch(`/api/vidstreaming/${id}?${params}`);
+    let json = await response.json();
+    if (response.status != 200) throw convertToError(json);
+    return json;
+  }
+
+  async getVidstreamingDownloadQueue() {
+    let response = await fetch(`/api/vidstreaming/queue`);
+    let json = await response.json();
+    if (response.status != 200) throw convertToError(json);
+    return json;
+  }
+
+  async moveVidstreamingDownloadQueueEntry(id, position, animeType = "sub") {
+    let params = new URLSearchParams();
+    params.set("position", position);
+    params.set("type", animeType);
+
+    let response = await fetch(`/api/vidstreaming/queue/${id}?${params}`, {
+      method: "PUT",
+    });
+    let json = await response.json();
+    if (response.status != 200) throw convertToError(json);
+    return json;
+  }
+
+  async cancelVidstreamingDownloadQueueEntry(id, animeType = "sub") {
+    let params = new URLSearchParams();
+    params.set("type", animeType);
+
+    let response = await fetch(`/api/vidstreaming/queue/${id}?${params}`, {
+      method: "DELETE",
+    });
     let json = await response.json();
     if (response.status != 200) throw convertToError(json);
     return json;
   }
 
-  async *downloadVidstreamingEpisode(id) {
-    let source = new EventSource(`/api/vidstreaming/${id}/download`);
+  async cancelVidstreamingEpisodeDownload(id, animeType = "sub") {
+    let params = new URLSearchParams();
+    params.set("type", animeType);
+
+    let response = await fetch(`/api/vidstreaming/${id}/download?${params}`, {
+      method: "DELETE",
+    });
+    let json = await response.json();
+    if (response.status != 200) throw convertToError(json);
+    return json;
+  }
+
+  async pauseVidstreamingEpisodeDownload(id, animeType = "sub") {
+    let params = new URLSearchParams();
+    params.set("type", animeType);
+
+    let response = await fetch(`/api/vidstreaming/${id}/download/pause?${params}`, {
+      method: "POST",
+    });
+    let json = await response.json();
+    if (response.status != 200) throw convertToError(json);
+    return json;
+  }
+
+  async resumeVidstreamingEpisodeDownload(id, animeType = "sub") {
+    let params = new URLSearchParams();
+    params.set("type", animeType);
+
+    let response = await fetch(`/api/vidstreaming/${id}/download/resume?${params}`, {
+      method: "POST",
+    });
+    let json = await response.json();
+    if (response.status != 200) throw convertToError(json);
+    return json;
+  }
+
+  async getVidstreamingAnimeMapping(animeId, animeType = "sub") {
+    let params = new URLSearchParams();
+    params.set("type", animeType);
+
+    let response = await fetch(`/api/vidstreaming/mappings/${animeId}?${params}`);
+    let json = await response.json();
+    if (response.status != 200) throw convertToError(json);
+    return json;
+  }
+
+  async setVidstreamingAnimeMapping(animeId, slug, animeType = "sub") {
+    let params = new URLSearchParams();
+    params.set("slug", slug);
+    params.set("type", animeType);
+
+    let response = await fetch(`/api/vidstreaming/mappings/${animeId}?${params}`, {
+      method: "PUT",
+    });
+    let json = await response.json();
+    if (response.status != 200) throw convertToError(json);
+    return json;
+  }
+
+  async removeVidstreamingAnimeMapping(animeId, animeType = "sub") {
+    let params = new URLSearchParams();
+    params.set("type", animeType);
+
+    let response = await fetch(`/api/vidstreaming/mappings/${animeId}?${params}`, {
+      method: "DELETE",
+    });
+    let json = await response.json();
+    if (response.status != 200) throw convertToError(json);
+    return json;
+  }
+
+  async getDownloads() {
+    let response = await fetch(`/api/downloads`);
+    let json = await response.json();
+    if (response.status != 200) throw convertToError(json);
+    return json;
+  }
+
+  async *subscribeDownloads() {
+    let source = new EventSource(`/api/downloads/events`);
+    let store = {
+      resolve: () => {},
+      reject: () => {},
+    };
+    let shouldExit = false;
+
+    source.addEventListener("message", (event) => {
+      let data = JSON.parse(event.data);
+      store.resolve(data);
+    });
+    source.addEventListener("error", (event) => {
+      console.error(event);
+      store.reject(event);
+    });
+    source.addEventListener("close", (event) => {
+      shouldExit = true;
+      source.close();
+    });
+
+    while (!shouldExit) {
+      yield new Promise((resolve, reject) => {
+        store.resolve = resolve;
+        store.reject = reject;
+      });
+    }
+  }
+
+  async *downloadVidstreamingEpisode(id, animeType = "sub", quality = {}) {
+    let params = new URLSearchParams();
+    params.set("type", animeType);
+    if (quality.maxResolution !== null && quality.maxResolution !== undefined)
+      params.set("max-resolution", quality.maxResolution);
+    if (quality.maxBandwidth !== null && quality.maxBandwidth !== undefined)
+      params.set("max-bandwidth", quality.maxBandwidth);
+    if (
+      quality.preferredCodecs !== null &&
+      quality.preferredCodecs !== undefined
+    )
+      params.set("preferred-codecs", quality.preferredCodecs.join(","));
+
+    let source = new EventSource(`/api/vidstreaming/${id}/download?${params}`);
     let store = {
       resolve: () => {},
       reejct: () => {},
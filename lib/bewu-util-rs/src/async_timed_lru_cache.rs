@@ -51,11 +51,8 @@ where
                             return cell_value.value.clone();
                         }
                     }
-                    None => {
-                        let entry = Arc::new(tokio::sync::OnceCell::new());
-                        cache.put(key, entry.clone());
-                        entry
-                    }
+                    // The entry is being initialized, so wait for it.
+                    None => entry.clone(),
                 },
                 None => {
                     let entry = Arc::new(tokio::sync::OnceCell::new());
@@ -99,3 +96,28 @@ struct Entry<V> {
     /// The value of the entry.
     value: V,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    #[tokio::test]
+    async fn parallel_gets_share_value() {
+        let cache = AsyncTimedLruCache::new(1, Duration::from_secs(0));
+        let calls = AtomicUsize::new(0);
+
+        let get = || {
+            cache.get(1, || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                Arc::new(())
+            })
+        };
+        let (first, second) = tokio::join!(get(), get());
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,11 +1,28 @@
 use crate::AsyncLockFile;
+use crate::HostLimiter;
+use crate::HostLimiterPermit;
+use crate::QualityPreference;
 use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::ensure;
 use anyhow::Context;
+use cbc::cipher::KeyIvInit;
+use cipher::block_padding::Pkcs7;
+use cipher::BlockModeDecrypt;
+use hls_parser::ByteRange;
+use hls_parser::InitializationSection;
 use hls_parser::MasterPlaylist;
 use hls_parser::MediaPlaylist;
+use hls_parser::MediaSegment;
+use hls_parser::MediaType;
+use hls_parser::Rendition;
+use hls_parser::UriReferenceStr;
 use reqwest::Url;
+use std::collections::HashMap;
+use std::ffi::OsString;
 use std::fmt::Write;
 use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
@@ -13,14 +30,33 @@ use tokio::task::JoinSet;
 use tokio_stream::Stream;
 use tokio_stream::StreamExt;
 
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
 /// A message about the state of a hls download.
 #[derive(Debug)]
 pub enum DownloadHlsMessage {
     /// Downloaded the given media playlist
     DownloadedMediaPlaylist { media_playlist: Arc<MediaPlaylist> },
 
+    /// Downloaded the media playlist of a selected audio or subtitles rendition.
+    ///
+    /// Its media segments are downloaded and concatenated along with those of the main media playlist.
+    DownloadedRenditionPlaylist {
+        /// The media type of the rendition
+        media_type: MediaType,
+
+        /// The media playlist of the rendition
+        media_playlist: Arc<MediaPlaylist>,
+    },
+
     /// Downloaded a single media segment
-    DownloadedMediaSegment,
+    DownloadedMediaSegment {
+        /// The size of the media segment, in bytes.
+        size: u64,
+
+        /// Whether the media segment was already downloaded by an earlier, interrupted download.
+        resumed: bool,
+    },
 
     /// Downloaded all media segments
     DownloadedAllMediaSegments,
@@ -40,6 +76,46 @@ pub enum DownloadHlsMessage {
     Done,
 }
 
+/// Options for a hls download.
+#[derive(Debug, Clone, Default)]
+pub struct DownloadHlsOptions {
+    /// A limiter for the number of concurrent requests made to a single host.
+    ///
+    /// If this is `None`, media segments are downloaded with no limit.
+    pub host_limiter: Option<HostLimiter>,
+
+    /// The preference used to select a variant stream from a master playlist.
+    pub quality: QualityPreference,
+
+    /// Languages to prefer for audio and subtitles renditions, from most to least preferred.
+    ///
+    /// These are BCP 47 language tags, and match more specific tags,
+    /// so `en` matches `en-US`.
+    /// If no rendition is in a preferred language, the default rendition is used.
+    pub preferred_languages: Vec<String>,
+}
+
+/// A media playlist being downloaded
+#[derive(Debug)]
+struct Track {
+    /// The media type of the rendition.
+    ///
+    /// This is `None` for the media playlist of the variant stream.
+    media_type: Option<MediaType>,
+
+    /// The url of the media playlist, used to resolve relative uris.
+    url: Url,
+
+    /// The media playlist
+    media_playlist: Arc<MediaPlaylist>,
+
+    /// The path of the downloaded media initialization section, if there is one.
+    initialization_section_path: Option<PathBuf>,
+
+    /// The paths of the downloaded media segments, in order.
+    media_segment_paths: Vec<PathBuf>,
+}
+
 /// Perform a hls download.
 ///
 /// Returns a stream of events from the download.
@@ -48,6 +124,7 @@ pub fn download_hls<P>(
     client: reqwest::Client,
     url: &str,
     out_path: P,
+    options: DownloadHlsOptions,
 ) -> anyhow::Result<impl Stream<Item = Result<DownloadHlsMessage, anyhow::Error>>>
 where
     P: AsRef<Path>,
@@ -96,30 +173,42 @@ where
         // Since we "own" it at this point, we should clean it up if we fail.
 
         // Get the playlist
-        let mut playlist_text = get_text(&client, url.as_str())
+        let mut playlist_text = get_text(&client, &url, options.host_limiter.as_ref())
             .await
             .context("failed to download playlist")?;
 
         // Try to parse a master playlist
+        let mut rendition_urls = Vec::new();
         match playlist_text.parse::<MasterPlaylist>() {
             Ok(master_playlist) => {
                 // Select the best variant stream
-                // TODO: Make user configurable
                 // TODO: Report selected stream.
-                let best_variant_stream = master_playlist
-                    .variant_streams
-                    .iter()
-                    .max_by_key(|stream| stream.bandwidth)
+                let best_variant_stream = options
+                    .quality
+                    .select_variant_stream(&master_playlist.variant_streams)
                     .context("failed to select a variant stream")?;
 
+                // Select renditions.
+                // Renditions without a uri are already part of the variant stream.
+                let audio_renditions = master_playlist
+                    .get_variant_stream_renditions(best_variant_stream, MediaType::Audio);
+                let audio_rendition =
+                    select_rendition(audio_renditions, &options.preferred_languages, true);
+                let subtitles_renditions = master_playlist
+                    .get_variant_stream_renditions(best_variant_stream, MediaType::Subtitles);
+                let subtitles_rendition =
+                    select_rendition(subtitles_renditions, &options.preferred_languages, false);
+                for rendition in [audio_rendition, subtitles_rendition].into_iter().flatten() {
+                    if let Some(uri) = rendition.uri.as_ref() {
+                        rendition_urls.push((rendition.media_type, resolve_uri(&url, uri)?));
+                    }
+                }
+
                 // Overwrite url with media playlist url, so relative media segments will work later.
-                url = match best_variant_stream.uri.to_iri() {
-                    Ok(absolute_uri) => Url::parse(absolute_uri.into())?,
-                    Err(relative_uri) => url.join(relative_uri.into())?,
-                };
+                url = resolve_uri(&url, &best_variant_stream.uri)?;
 
                 // Overwrite playlist text
-                playlist_text = get_text(&client, url.as_str())
+                playlist_text = get_text(&client, &url, options.host_limiter.as_ref())
                     .await
                     .context("failed to download playlist")?;
             }
@@ -139,45 +228,138 @@ where
             media_playlist: media_playlist.clone(),
         };
 
-        // Download media segments, in parallel
-        let mut media_segment_paths = Vec::with_capacity(media_playlist.media_segments.len());
-        let mut join_set = JoinSet::new();
-        let base_url = &url;
-        for segment in media_playlist.media_segments.iter() {
-            // We only support mgpeg2-ts streams for now,
-            // since we know we can concat them.
-            // TODO: Improve codec detection or add support for more codecs.
-            if !segment.uri.path_str().ends_with(".ts") {
-                Err(anyhow!("media segment path does not end with \".ts\""))?;
-            }
+        let mut tracks = Vec::with_capacity(1 + rendition_urls.len());
+        tracks.push(Track {
+            media_type: None,
+            url: url.clone(),
+            media_playlist,
+            initialization_section_path: None,
+            media_segment_paths: Vec::new(),
+        });
+
+        // Get rendition media playlists
+        for (media_type, rendition_url) in rendition_urls {
+            let media_playlist = get_text(&client, &rendition_url, options.host_limiter.as_ref())
+                .await
+                .context("failed to download rendition playlist")?
+                .parse::<MediaPlaylist>()
+                .map(Arc::new)
+                .context("invalid rendition media playlist")?;
 
-            let client = client.clone();
-            let url = segment.uri.to_iri();
-            let url = match url {
-                Ok(absolute_uri) => Url::parse(absolute_uri.into())?,
-                Err(relative_uri) => base_url.join(relative_uri.into())?,
+            yield DownloadHlsMessage::DownloadedRenditionPlaylist {
+                media_type,
+                media_playlist: media_playlist.clone(),
             };
 
-            // Generated to be unique for segment uri.
-            let file_name = url_to_file_name(url.as_str());
+            tracks.push(Track {
+                media_type: Some(media_type),
+                url: rendition_url,
+                media_playlist,
+                initialization_section_path: None,
+                media_segment_paths: Vec::new(),
+            });
+        }
 
-            let out_path = temp_dir_path.join(file_name);
+        // Download media segments of all tracks, in parallel
+        let mut join_set = JoinSet::new();
 
-            // Save out path for future concatenation
-            media_segment_paths.push(out_path.clone());
+        // Keys are usually shared by many media segments, so each is only fetched once.
+        let mut keys = HashMap::new();
+        for track in tracks.iter_mut() {
+            // Media segments like fMP4 need their media initialization section,
+            // which is written before them when concatenating.
+            // A new media initialization section would need to be written in the middle of the file,
+            // which we cannot do.
+            let media_segments = &track.media_playlist.media_segments;
+            let initialization_section = media_segments
+                .first()
+                .and_then(|segment| segment.initialization_section.as_ref());
+            if media_segments
+                .iter()
+                .any(|segment| segment.initialization_section.as_ref() != initialization_section)
+            {
+                Err(anyhow!("media segments with different initialization sections are not supported"))?;
+            }
+
+            if let Some(initialization_section) = initialization_section {
+                let url = resolve_uri(&track.url, &initialization_section.uri)?;
+                let byte_range = initialization_section.byte_range;
+                let out_path = temp_dir_path.join(get_media_segment_file_name(&url, byte_range));
 
-            join_set.spawn(async move {
-                if tokio::fs::try_exists(&out_path)
+                // A media initialization section is encrypted by the EXT-X-KEY tag before its EXT-X-MAP tag.
+                let encryption = get_initialization_section_encryption(initialization_section, &track.url)?;
+                let encryption = match encryption {
+                    Some((key_url, iv)) => {
+                        let key = get_cached_key(&client, &mut keys, key_url, options.host_limiter.as_ref()).await?;
+                        Some((key, iv))
+                    }
+                    None => None,
+                };
+
+                let resumed = tokio::fs::try_exists(&out_path)
                     .await
-                    .with_context(|| format!("failed to check if temp file at \"{}\" exists", out_path.display()))?
-                {
-                    return Ok(());
+                    .with_context(|| format!("failed to check if temp file at \"{}\" exists", out_path.display()))?;
+                if !resumed {
+                    let _permit = acquire_host_permit(options.host_limiter.as_ref(), &url).await;
+                    download_media_segment(&client, &url, byte_range, encryption, &out_path)
+                        .await
+                        .with_context(|| format!("failed to download media initialization section to \"{}\"", out_path.display()))?;
                 }
 
-                nd_util::download_to_path(&client, url.as_str(), &out_path)
-                    .await
-                    .with_context(|| format!("failed to download to media segment to \"{}\"", out_path.display()))
-            });
+                track.initialization_section_path = Some(out_path);
+            }
+
+            for segment in track.media_playlist.media_segments.iter() {
+                let encryption = get_media_segment_encryption(segment, &track.url)?;
+                let encryption = match encryption {
+                    Some((key_url, iv)) => {
+                        let key = get_cached_key(&client, &mut keys, key_url, options.host_limiter.as_ref()).await?;
+                        Some((key, iv))
+                    }
+                    None => None,
+                };
+
+                let client = client.clone();
+                let host_limiter = options.host_limiter.clone();
+                let url = resolve_uri(&track.url, &segment.uri)?;
+                let byte_range = segment.byte_range;
+
+                // Generated to be unique for segment uri and byte range.
+                let file_name = get_media_segment_file_name(&url, byte_range);
+
+                let out_path = temp_dir_path.join(file_name);
+
+                // Save out path for future concatenation
+                track.media_segment_paths.push(out_path.clone());
+
+                join_set.spawn(async move {
+                    let resumed = tokio::fs::try_exists(&out_path)
+                        .await
+                        .with_context(|| format!("failed to check if temp file at \"{}\" exists", out_path.display()))?;
+
+                    if !resumed {
+                        let _permit = acquire_host_permit(host_limiter.as_ref(), &url).await;
+                        match (encryption, byte_range) {
+                            (None, None) => {
+                                nd_util::download_to_path(&client, url.as_str(), &out_path)
+                                    .await
+                                    .with_context(|| format!("failed to download to media segment to \"{}\"", out_path.display()))?;
+                            }
+                            (encryption, byte_range) => {
+                                download_media_segment(&client, &url, byte_range, encryption, &out_path)
+                                    .await
+                                    .with_context(|| format!("failed to download to media segment to \"{}\"", out_path.display()))?;
+                            }
+                        }
+                    }
+
+                    let metadata = tokio::fs::metadata(&out_path)
+                        .await
+                        .with_context(|| format!("failed to get metadata for \"{}\"", out_path.display()))?;
+
+                    anyhow::Ok((metadata.len(), resumed))
+                });
+            }
         }
 
         // Process media segment download results
@@ -185,9 +367,9 @@ where
             let result = result
                 .context("failed to join task")
                 .and_then(std::convert::identity);
-            result?;
+            let (size, resumed) = result?;
 
-            yield DownloadHlsMessage::DownloadedMediaSegment;
+            yield DownloadHlsMessage::DownloadedMediaSegment { size, resumed };
         }
 
         yield DownloadHlsMessage::DownloadedAllMediaSegments;
@@ -198,15 +380,40 @@ where
         // This may even be done as part of the download process, avoiding the need of a second copy.
         // However, if the server does not send a content-length header, we must download everything to a seperate file.
         // A temp dir of some kind will always be required.
-        // Copy to intermediate ts file
-        let concat_file_name = url_to_file_name(url.as_str());
-        let concat_file_path = temp_dir_path.join(&concat_file_name);
+        // Copy each track to an intermediate file
+        let mut concat_file_paths = Vec::with_capacity(tracks.len());
+        for track in tracks.iter() {
+            let concat_file_name = url_to_file_name(track.url.as_str());
+            let concat_file_path = temp_dir_path.join(&concat_file_name);
 
-        {
             let mut dest_file = File::create(&concat_file_path).await?;
-            for path in media_segment_paths {
+            if let Some(path) = track.initialization_section_path.as_ref() {
                 let mut src_file = File::open(path).await?;
                 tokio::io::copy(&mut src_file, &mut dest_file).await?;
+            }
+
+            // WebVTT subtitles in fMP4 are concatenated like other media segments.
+            let is_webvtt = track.media_type == Some(MediaType::Subtitles)
+                && track.initialization_section_path.is_none();
+            for (i, path) in track.media_segment_paths.iter().enumerate() {
+                if is_webvtt {
+                    // Each WebVTT media segment is a complete file,
+                    // so only the header of the first is kept.
+                    let text = tokio::fs::read_to_string(path)
+                        .await
+                        .with_context(|| format!("failed to read \"{}\"", path.display()))?;
+                    let text = if i == 0 {
+                        &text
+                    } else {
+                        strip_webvtt_header(&text)
+                    };
+
+                    dest_file.write_all(text.as_bytes()).await?;
+                    dest_file.write_all(b"\n").await?;
+                } else {
+                    let mut src_file = File::open(path).await?;
+                    tokio::io::copy(&mut src_file, &mut dest_file).await?;
+                }
 
                 yield DownloadHlsMessage::ConcatenatedMediaSegment;
             }
@@ -214,7 +421,32 @@ where
             dest_file.flush().await?;
             dest_file.sync_all().await?;
 
-            yield DownloadHlsMessage::ConcatenatedAllMediaSegments;
+            concat_file_paths.push((track.media_type, concat_file_path));
+        }
+
+        yield DownloadHlsMessage::ConcatenatedAllMediaSegments;
+
+        // If there are renditions, the variant stream is remuxed to an intermediate file first,
+        // then muxed with the renditions.
+        let (_, concat_file_path) = concat_file_paths.remove(0);
+        let rendition_file_paths = concat_file_paths;
+        let remux_out_path = if rendition_file_paths.is_empty() {
+            temp_out_path.clone()
+        } else {
+            concat_file_path.with_added_extension("mp4")
+        };
+
+        // Remove temp files left by an interrupted remux,
+        // since ffmpeg will not overwrite them.
+        // We hold the lock, so nobody else can be using them.
+        for path in [&temp_out_path, &remux_out_path] {
+            match tokio::fs::remove_file(path).await {
+                Ok(()) => {}
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+                Err(error) => {
+                    Err(error).context("failed to remove temp file")?;
+                }
+            }
         }
 
         // Remux concatenated file
@@ -223,7 +455,7 @@ where
             .video_codec("copy")
             .input(concat_file_path)
             .output_format("mp4")
-            .output(&temp_out_path)
+            .output(&remux_out_path)
             .overwrite(false)
             .spawn()
             .context("failed to spawn ffmpeg")?;
@@ -264,6 +496,13 @@ where
             }
         }
 
+        // Mux renditions
+        if last_error.is_ok() && !rendition_file_paths.is_empty() {
+            last_error = mux_renditions(&remux_out_path, &rendition_file_paths, &temp_out_path)
+                .await
+                .context("failed to mux renditions");
+        }
+
         if let Err(error) = last_error {
             if let Err(error) = tokio::fs::remove_file(&temp_out_path)
                 .await
@@ -310,9 +549,27 @@ where
     Ok(())
 }
 
-async fn get_text(client: &reqwest::Client, url: &str) -> Result<String, reqwest::Error> {
+/// Wait for permission to make a request to the given url, if a limiter is present.
+async fn acquire_host_permit(
+    host_limiter: Option<&HostLimiter>,
+    url: &Url,
+) -> Option<HostLimiterPermit> {
+    let host_limiter = host_limiter?;
+    Some(
+        host_limiter
+            .acquire(url.host_str().unwrap_or_default())
+            .await,
+    )
+}
+
+async fn get_text(
+    client: &reqwest::Client,
+    url: &Url,
+    host_limiter: Option<&HostLimiter>,
+) -> Result<String, reqwest::Error> {
+    let _permit = acquire_host_permit(host_limiter, url).await;
     client
-        .get(url)
+        .get(url.as_str())
         .send()
         .await?
         .error_for_status()?
@@ -320,6 +577,371 @@ async fn get_text(client: &reqwest::Client, url: &str) -> Result<String, reqwest
         .await
 }
 
+/// Get the key url and IV of an encrypted media segment.
+///
+/// Returns `None` if the media segment is not encrypted.
+fn get_media_segment_encryption(
+    segment: &MediaSegment,
+    base_url: &Url,
+) -> anyhow::Result<Option<(Url, [u8; 16])>> {
+    let key_url = get_encryption_key_url(
+        segment.encryption_method.as_deref(),
+        segment.encryption_uri.as_deref(),
+        segment.encryption_key_format.as_deref(),
+        base_url,
+    )?;
+    let key_url = match key_url {
+        Some(key_url) => key_url,
+        None => return Ok(None),
+    };
+
+    // If there is no IV, the media sequence number is used as one.
+    let iv = segment
+        .encryption_iv
+        .unwrap_or_else(|| u128::from(segment.media_sequence_number).to_be_bytes());
+
+    Ok(Some((key_url, iv)))
+}
+
+/// Get the key url and IV of an encrypted media initialization section.
+///
+/// Returns `None` if the media initialization section is not encrypted.
+fn get_initialization_section_encryption(
+    initialization_section: &InitializationSection,
+    base_url: &Url,
+) -> anyhow::Result<Option<(Url, [u8; 16])>> {
+    let key_url = get_encryption_key_url(
+        initialization_section.encryption_method.as_deref(),
+        initialization_section.encryption_uri.as_deref(),
+        initialization_section.encryption_key_format.as_deref(),
+        base_url,
+    )?;
+    let key_url = match key_url {
+        Some(key_url) => key_url,
+        None => return Ok(None),
+    };
+
+    // A media initialization section has no media sequence number to fall back on.
+    let iv = initialization_section
+        .encryption_iv
+        .context("missing IV for encrypted media initialization section")?;
+
+    Ok(Some((key_url, iv)))
+}
+
+/// Get the key url of an EXT-X-KEY tag.
+///
+/// Returns `None` if the tag does not encrypt anything.
+fn get_encryption_key_url(
+    method: Option<&str>,
+    key_uri: Option<&str>,
+    key_format: Option<&str>,
+    base_url: &Url,
+) -> anyhow::Result<Option<Url>> {
+    let method = match method {
+        None | Some("NONE") => return Ok(None),
+        Some(method) => method,
+    };
+    match method {
+        "AES-128" => {}
+        "SAMPLE-AES" | "SAMPLE-AES-CTR" => {
+            bail!("\"{method}\" encryption is not supported")
+        }
+        _ => bail!("unknown encryption method \"{method}\""),
+    }
+
+    if let Some(key_format) = key_format {
+        ensure!(
+            key_format == "identity",
+            "unsupported key format \"{key_format}\""
+        );
+    }
+
+    let key_uri = key_uri.context("missing encryption key uri")?;
+    let key_url = base_url
+        .join(key_uri)
+        .with_context(|| format!("invalid encryption key uri \"{key_uri}\""))?;
+
+    Ok(Some(key_url))
+}
+
+/// Get an AES-128 key, downloading it if it is not in the given cache.
+async fn get_cached_key(
+    client: &reqwest::Client,
+    keys: &mut HashMap<Url, [u8; 16]>,
+    url: Url,
+    host_limiter: Option<&HostLimiter>,
+) -> anyhow::Result<[u8; 16]> {
+    if let Some(key) = keys.get(&url) {
+        return Ok(*key);
+    }
+
+    let key = get_key(client, &url, host_limiter)
+        .await
+        .with_context(|| format!("failed to download key \"{url}\""))?;
+    keys.insert(url, key);
+
+    Ok(key)
+}
+
+/// Download an AES-128 key.
+async fn get_key(
+    client: &reqwest::Client,
+    url: &Url,
+    host_limiter: Option<&HostLimiter>,
+) -> anyhow::Result<[u8; 16]> {
+    let _permit = acquire_host_permit(host_limiter, url).await;
+    let bytes = client
+        .get(url.as_str())
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+
+    let key = <[u8; 16]>::try_from(&*bytes)
+        .map_err(|_| anyhow!("key must be 16 bytes, but got {} bytes", bytes.len()))?;
+
+    Ok(key)
+}
+
+/// Download a media segment to the given path.
+///
+/// If a byte range is given, only that sub-range of the resource is downloaded.
+/// If a key and IV are given, the media segment is decrypted with AES-128.
+/// The media segment is written to a temporary file first,
+/// so a partial media segment is never left at the path.
+async fn download_media_segment(
+    client: &reqwest::Client,
+    url: &Url,
+    byte_range: Option<ByteRange>,
+    encryption: Option<([u8; 16], [u8; 16])>,
+    out_path: &Path,
+) -> anyhow::Result<()> {
+    let mut request = client.get(url.as_str());
+    if let Some(byte_range) = byte_range {
+        request = request.header(reqwest::header::RANGE, get_range_header_value(byte_range)?);
+    }
+    let response = request.send().await?.error_for_status()?;
+    let is_partial = response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    let mut data: Vec<u8> = response.bytes().await?.into();
+
+    if let Some(byte_range) = byte_range {
+        data = get_byte_range(data, byte_range, is_partial)?;
+    }
+    if let Some((key, iv)) = encryption {
+        data = decrypt_aes_128(&key, &iv, data)?;
+    }
+
+    let temp_out_path = out_path.with_added_extension("part");
+    tokio::fs::write(&temp_out_path, data)
+        .await
+        .with_context(|| format!("failed to write \"{}\"", temp_out_path.display()))?;
+    tokio::fs::rename(&temp_out_path, out_path)
+        .await
+        .with_context(|| format!("failed to rename \"{}\"", temp_out_path.display()))?;
+
+    Ok(())
+}
+
+/// Get the value of a HTTP Range header for a byte range.
+fn get_range_header_value(byte_range: ByteRange) -> anyhow::Result<String> {
+    ensure!(byte_range.length > 0, "the byte range is empty");
+
+    // The end of a HTTP range is inclusive.
+    Ok(format!(
+        "bytes={}-{}",
+        byte_range.offset,
+        byte_range.end() - 1
+    ))
+}
+
+/// Get the bytes of a byte range from a response to a ranged request.
+///
+/// `is_partial` is whether the server responded with only the byte range.
+/// Otherwise, the server ignored the range and sent the whole resource.
+fn get_byte_range(
+    mut data: Vec<u8>,
+    byte_range: ByteRange,
+    is_partial: bool,
+) -> anyhow::Result<Vec<u8>> {
+    if !is_partial {
+        let start = usize::try_from(byte_range.offset)?;
+        let end = usize::try_from(byte_range.end())?;
+        ensure!(
+            end <= data.len(),
+            "the byte range ends at {end}, but the resource is {} bytes",
+            data.len()
+        );
+
+        data.truncate(end);
+        data.drain(..start);
+    }
+
+    ensure!(
+        u64::try_from(data.len())? == byte_range.length,
+        "expected {} bytes, but got {} bytes",
+        byte_range.length,
+        data.len()
+    );
+
+    Ok(data)
+}
+
+/// Decrypt AES-128 CBC data with PKCS7 padding.
+fn decrypt_aes_128(key: &[u8; 16], iv: &[u8; 16], mut data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let cipher = Aes128CbcDec::new_from_slices(key, iv)?;
+    let decrypted_len = cipher
+        .decrypt_padded::<Pkcs7>(&mut data)
+        .map_err(|_| anyhow!("invalid padding"))?
+        .len();
+    data.truncate(decrypted_len);
+
+    Ok(data)
+}
+
+/// Resolve a uri from a playlist against the url of the playlist.
+fn resolve_uri(base_url: &Url, uri: &UriReferenceStr) -> anyhow::Result<Url> {
+    let url = match uri.to_iri() {
+        Ok(absolute_uri) => Url::parse(absolute_uri.into())?,
+        Err(relative_uri) => base_url.join(relative_uri.into())?,
+    };
+
+    Ok(url)
+}
+
+/// Select a rendition from a rendition group.
+///
+/// The rendition in the most preferred language is chosen,
+/// then the default rendition.
+/// If `is_required` is true, the first rendition is chosen if no other rendition is.
+fn select_rendition<'a>(
+    renditions: impl Iterator<Item = &'a Rendition>,
+    preferred_languages: &[String],
+    is_required: bool,
+) -> Option<&'a Rendition> {
+    let renditions: Vec<_> = renditions.collect();
+
+    preferred_languages
+        .iter()
+        .find_map(|preferred_language| {
+            renditions.iter().find(|rendition| {
+                rendition
+                    .language
+                    .as_deref()
+                    .is_some_and(|language| is_language_match(language, preferred_language))
+            })
+        })
+        .or_else(|| renditions.iter().find(|rendition| rendition.default))
+        .or_else(|| renditions.first().filter(|_| is_required))
+        .copied()
+}
+
+/// Check if a BCP 47 language tag is the preferred language, or a more specific form of it.
+fn is_language_match(language: &str, preferred_language: &str) -> bool {
+    match language.get(..preferred_language.len()) {
+        Some(prefix) => {
+            prefix.eq_ignore_ascii_case(preferred_language)
+                && matches!(
+                    language.as_bytes().get(preferred_language.len()),
+                    None | Some(b'-')
+                )
+        }
+        None => false,
+    }
+}
+
+/// Get the cues of a WebVTT file, without the header.
+///
+/// The header ends at the first blank line.
+fn strip_webvtt_header(text: &str) -> &str {
+    let mut rest = text;
+    while let Some((line, next)) = rest.split_once('\n') {
+        rest = next;
+        if line.trim_end_matches('\r').is_empty() {
+            return rest;
+        }
+    }
+
+    // A file that is only a header has no cues.
+    ""
+}
+
+/// Make the ffmpeg command to mux renditions into a remuxed variant stream.
+///
+/// Subtitles are converted to mov_text, since mp4 files cannot hold WebVTT.
+fn get_mux_command(
+    input_path: &Path,
+    renditions: &[(Option<MediaType>, PathBuf)],
+    out_path: &Path,
+) -> anyhow::Result<tokio::process::Command> {
+    let mut inputs = vec![OsString::from("-i"), input_path.into()];
+    let mut maps = vec![OsString::from("-map"), OsString::from("0")];
+    for (i, (media_type, path)) in renditions.iter().enumerate() {
+        let stream_specifier = match media_type {
+            Some(MediaType::Audio) => "a",
+            Some(MediaType::Subtitles) => "s",
+            _ => bail!("cannot mux a rendition with media type {media_type:?}"),
+        };
+
+        inputs.extend([OsString::from("-i"), path.into()]);
+        maps.extend([
+            OsString::from("-map"),
+            OsString::from(format!("{}:{stream_specifier}", i + 1)),
+        ]);
+    }
+
+    let mut command = tokio::process::Command::new("ffmpeg");
+    command
+        .arg("-nostdin")
+        .arg("-hide_banner")
+        .args(["-v", "error"])
+        .args(inputs)
+        .args(maps)
+        .args(["-c", "copy"])
+        .args(["-c:s", "mov_text"])
+        .args(["-f", "mp4"])
+        .arg("-n")
+        .arg(out_path);
+
+    Ok(command)
+}
+
+/// Mux renditions into a remuxed variant stream with ffmpeg.
+async fn mux_renditions(
+    input_path: &Path,
+    renditions: &[(Option<MediaType>, PathBuf)],
+    out_path: &Path,
+) -> anyhow::Result<()> {
+    let output = get_mux_command(input_path, renditions, out_path)?
+        .output()
+        .await
+        .context("failed to spawn \"ffmpeg\"")?;
+
+    ensure!(
+        output.status.success(),
+        "ffmpeg exited with \"{}\": {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    Ok(())
+}
+
+/// Get a file name that is unique for a media segment.
+///
+/// Media segments that are sub-ranges of the same resource share a url,
+/// so the byte range is part of the name.
+fn get_media_segment_file_name(url: &Url, byte_range: Option<ByteRange>) -> String {
+    match byte_range {
+        // The byte range goes first, so it is not cut off for long urls.
+        Some(byte_range) => {
+            url_to_file_name(&format!("{}-{}@{url}", byte_range.offset, byte_range.end()))
+        }
+        None => url_to_file_name(url.as_str()),
+    }
+}
+
 fn url_to_file_name(url: &str) -> String {
     const MAX_FILE_NAME_LEN: usize = 248;
 
@@ -346,3 +968,332 @@ fn url_to_file_name(url: &str) -> String {
 
     file_name
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test_data/download_hls/key.bin"
+    ));
+    const SEGMENT_0: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test_data/download_hls/segment-0.ts"
+    ));
+    const SEGMENT_0_ENCRYPTED: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test_data/download_hls/segment-0.ts.enc"
+    ));
+    const SEGMENT_1: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test_data/download_hls/segment-1.ts"
+    ));
+    const SEGMENT_1_ENCRYPTED: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test_data/download_hls/segment-1.ts.enc"
+    ));
+
+    /// The first segment has an explicit IV,
+    /// and the second uses its media sequence number.
+    const ENCRYPTED_PLAYLIST: &str = "#EXTM3U
+#EXT-X-TARGETDURATION:10
+#EXT-X-MEDIA-SEQUENCE:7793
+#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\",IV=0x000102030405060708090A0B0C0D0E0F
+#EXTINF:10.0,
+segment-0.ts
+#EXT-X-KEY:METHOD=AES-128,URI=\"https://example.com/key.bin\"
+#EXTINF:10.0,
+segment-1.ts
+#EXT-X-KEY:METHOD=NONE
+#EXTINF:10.0,
+segment-2.ts
+";
+
+    #[test]
+    fn decrypt_media_segments() {
+        let playlist: MediaPlaylist = ENCRYPTED_PLAYLIST.parse().expect("failed to parse");
+        let base_url = Url::parse("https://example.com/media/playlist.m3u8").unwrap();
+        let key: [u8; 16] = KEY.try_into().expect("invalid key");
+
+        let segments = &playlist.media_segments;
+        let encryptions = segments
+            .iter()
+            .map(|segment| get_media_segment_encryption(segment, &base_url))
+            .collect::<Result<Vec<_>, _>>()
+            .expect("failed to get encryption");
+
+        let (key_url, iv) = encryptions[0].clone().expect("missing encryption");
+        assert_eq!(key_url.as_str(), "https://example.com/media/key.bin");
+        let decrypted =
+            decrypt_aes_128(&key, &iv, SEGMENT_0_ENCRYPTED.into()).expect("failed to decrypt");
+        assert_eq!(decrypted, SEGMENT_0);
+
+        let (key_url, iv) = encryptions[1].clone().expect("missing encryption");
+        assert_eq!(key_url.as_str(), "https://example.com/key.bin");
+        let decrypted =
+            decrypt_aes_128(&key, &iv, SEGMENT_1_ENCRYPTED.into()).expect("failed to decrypt");
+        assert_eq!(decrypted, SEGMENT_1);
+
+        assert!(encryptions[2].is_none());
+
+        // A wrong key will not have valid padding.
+        let mut wrong_key = key;
+        wrong_key[0] ^= 1;
+        decrypt_aes_128(&wrong_key, &iv, SEGMENT_1_ENCRYPTED.into())
+            .expect_err("decrypted with wrong key");
+    }
+
+    #[test]
+    fn decrypt_initialization_section() {
+        let playlist: MediaPlaylist = "#EXTM3U
+#EXT-X-TARGETDURATION:10
+#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\",IV=0x000102030405060708090A0B0C0D0E0F
+#EXT-X-MAP:URI=\"init.mp4\"
+#EXT-X-KEY:METHOD=AES-128,URI=\"key-2.bin\"
+#EXTINF:10.0,
+segment-0.m4s
+"
+        .parse()
+        .expect("failed to parse");
+        let base_url = Url::parse("https://example.com/media/playlist.m3u8").unwrap();
+        let key: [u8; 16] = KEY.try_into().expect("invalid key");
+
+        // The media initialization section uses the key before its EXT-X-MAP tag.
+        let initialization_section = playlist.media_segments[0]
+            .initialization_section
+            .as_ref()
+            .expect("missing initialization section");
+        let (key_url, iv) =
+            get_initialization_section_encryption(initialization_section, &base_url)
+                .expect("failed to get encryption")
+                .expect("missing encryption");
+        assert_eq!(key_url.as_str(), "https://example.com/media/key.bin");
+        let decrypted =
+            decrypt_aes_128(&key, &iv, SEGMENT_0_ENCRYPTED.into()).expect("failed to decrypt");
+        assert_eq!(decrypted, SEGMENT_0);
+
+        // An encrypted media initialization section needs an explicit IV.
+        let playlist: MediaPlaylist = "#EXTM3U
+#EXT-X-TARGETDURATION:10
+#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"
+#EXT-X-MAP:URI=\"init.mp4\"
+#EXTINF:10.0,
+segment-0.m4s
+"
+        .parse()
+        .expect("failed to parse");
+        let initialization_section = playlist.media_segments[0]
+            .initialization_section
+            .as_ref()
+            .expect("missing initialization section");
+        let error = get_initialization_section_encryption(initialization_section, &base_url)
+            .expect_err("encryption without an IV was accepted");
+        assert!(error.to_string().contains("missing IV"), "{error}");
+    }
+
+    #[test]
+    fn reject_unsupported_encryption() {
+        let base_url = Url::parse("https://example.com/playlist.m3u8").unwrap();
+
+        for (key_tag, expected) in [
+            (
+                "METHOD=SAMPLE-AES,URI=\"key.bin\"",
+                "\"SAMPLE-AES\" encryption is not supported",
+            ),
+            (
+                "METHOD=UNKNOWN,URI=\"key.bin\"",
+                "unknown encryption method",
+            ),
+            (
+                "METHOD=AES-128,URI=\"skd://key\",KEYFORMAT=\"com.apple.streamingkeydelivery\"",
+                "unsupported key format",
+            ),
+            ("METHOD=AES-128", "missing encryption key uri"),
+        ] {
+            let playlist: MediaPlaylist =
+                format!("#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXT-X-KEY:{key_tag}\n#EXTINF:10.0,\nsegment.ts\n")
+                    .parse()
+                    .expect("failed to parse");
+            let error = get_media_segment_encryption(&playlist.media_segments[0], &base_url)
+                .expect_err("unsupported encryption was accepted");
+            assert!(
+                error.to_string().contains(expected),
+                "\"{error}\" does not contain \"{expected}\""
+            );
+        }
+    }
+
+    const MASTER_PLAYLIST_WITH_RENDITIONS: &str = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",LANGUAGE=\"ja\",NAME=\"Japanese\",DEFAULT=YES,URI=\"audio/ja.m3u8\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",LANGUAGE=\"en-US\",NAME=\"English\",URI=\"audio/en.m3u8\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"muxed\",LANGUAGE=\"ja\",NAME=\"Japanese\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"muxed\",LANGUAGE=\"en\",NAME=\"English\",URI=\"audio/en.m3u8\"
+#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID=\"subs\",LANGUAGE=\"en\",NAME=\"English\",URI=\"subtitles/en.m3u8\"
+#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID=\"subs\",LANGUAGE=\"es\",NAME=\"Spanish\",URI=\"subtitles/es.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,AUDIO=\"aac\",SUBTITLES=\"subs\"
+video.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=640000,AUDIO=\"muxed\"
+video-muxed.m3u8
+";
+
+    #[test]
+    fn select_renditions() {
+        let playlist: MasterPlaylist = MASTER_PLAYLIST_WITH_RENDITIONS
+            .parse()
+            .expect("failed to parse");
+        let variant_stream = &playlist.variant_streams[0];
+        let select = |media_type, preferred_languages: &[&str], is_required| {
+            let preferred_languages: Vec<String> = preferred_languages
+                .iter()
+                .map(|language| String::from(*language))
+                .collect();
+            let renditions = playlist.get_variant_stream_renditions(variant_stream, media_type);
+            select_rendition(renditions, &preferred_languages, is_required)
+                .map(|rendition| &*rendition.name)
+        };
+
+        // The default rendition is used if there is no preference.
+        assert_eq!(select(MediaType::Audio, &[], true), Some("Japanese"));
+        assert_eq!(select(MediaType::Audio, &["en"], true), Some("English"));
+        assert_eq!(
+            select(MediaType::Audio, &["fr", "en-us"], true),
+            Some("English")
+        );
+        assert_eq!(select(MediaType::Audio, &["fr"], true), Some("Japanese"));
+
+        // Subtitles are optional, and this group has no default.
+        assert_eq!(select(MediaType::Subtitles, &[], false), None);
+        assert_eq!(select(MediaType::Subtitles, &[], true), Some("English"));
+        assert_eq!(
+            select(MediaType::Subtitles, &["es"], false),
+            Some("Spanish")
+        );
+
+        // Renditions without a uri are part of the variant stream.
+        let variant_stream = &playlist.variant_streams[1];
+        let renditions = playlist.get_variant_stream_renditions(variant_stream, MediaType::Audio);
+        let rendition = select_rendition(renditions, &[], true).expect("missing rendition");
+        assert!(rendition.uri.is_none());
+    }
+
+    #[test]
+    fn match_languages() {
+        assert!(is_language_match("en", "en"));
+        assert!(is_language_match("en-US", "en"));
+        assert!(is_language_match("EN-us", "en-US"));
+        assert!(!is_language_match("eng", "en"));
+        assert!(!is_language_match("en", "en-US"));
+        assert!(!is_language_match("es", "en"));
+    }
+
+    #[test]
+    fn concat_webvtt() {
+        let segment = "WEBVTT\r\nX-TIMESTAMP-MAP=MPEGTS:900000,LOCAL:00:00:00.000\r\n\r\n00:00:10.000 --> 00:00:12.000\r\nHello\r\n";
+        assert_eq!(
+            strip_webvtt_header(segment),
+            "00:00:10.000 --> 00:00:12.000\r\nHello\r\n"
+        );
+
+        let segment = "WEBVTT\n\n1\n00:00:10.000 --> 00:00:12.000\nHello\n\n2\n00:00:12.000 --> 00:00:14.000\nWorld\n";
+        assert_eq!(
+            strip_webvtt_header(segment),
+            "1\n00:00:10.000 --> 00:00:12.000\nHello\n\n2\n00:00:12.000 --> 00:00:14.000\nWorld\n"
+        );
+
+        assert_eq!(strip_webvtt_header("WEBVTT\n"), "");
+        assert_eq!(strip_webvtt_header("WEBVTT"), "");
+    }
+
+    #[test]
+    fn mux_command() {
+        let renditions = [
+            (Some(MediaType::Audio), PathBuf::from("audio.ts")),
+            (Some(MediaType::Subtitles), PathBuf::from("subtitles.vtt")),
+        ];
+        let command = get_mux_command(Path::new("video.mp4"), &renditions, Path::new("out.mp4"))
+            .expect("failed to make command");
+        let args: Vec<_> = command
+            .as_std()
+            .get_args()
+            .map(|arg| arg.to_str().expect("invalid arg"))
+            .collect();
+        assert_eq!(
+            args.join(" "),
+            "-nostdin -hide_banner -v error -i video.mp4 -i audio.ts -i subtitles.vtt -map 0 -map 1:a -map 2:s -c copy -c:s mov_text -f mp4 -n out.mp4"
+        );
+
+        let renditions = [(None, PathBuf::from("video.ts"))];
+        get_mux_command(Path::new("video.mp4"), &renditions, Path::new("out.mp4"))
+            .expect_err("muxed a variant stream as a rendition");
+    }
+
+    #[test]
+    fn byte_ranges() {
+        let byte_range = ByteRange {
+            length: 4,
+            offset: 2,
+        };
+        assert_eq!(
+            get_range_header_value(byte_range).expect("invalid byte range"),
+            "bytes=2-5"
+        );
+        get_range_header_value(ByteRange {
+            length: 0,
+            offset: 2,
+        })
+        .expect_err("empty byte range was accepted");
+
+        // The server sent only the byte range.
+        let data = get_byte_range(vec![2, 3, 4, 5], byte_range, true).expect("invalid data");
+        assert_eq!(data, [2, 3, 4, 5]);
+        get_byte_range(vec![2, 3, 4], byte_range, true).expect_err("short data was accepted");
+
+        // The server ignored the range.
+        let data = get_byte_range((0..8).collect(), byte_range, false).expect("invalid data");
+        assert_eq!(data, [2, 3, 4, 5]);
+        get_byte_range((0..5).collect(), byte_range, false)
+            .expect_err("short resource was accepted");
+    }
+
+    #[test]
+    fn media_segment_file_names() {
+        let url = Url::parse("https://example.com/main.mp4").unwrap();
+        let first = get_media_segment_file_name(
+            &url,
+            Some(ByteRange {
+                length: 100,
+                offset: 0,
+            }),
+        );
+        let second = get_media_segment_file_name(
+            &url,
+            Some(ByteRange {
+                length: 100,
+                offset: 100,
+            }),
+        );
+        let whole = get_media_segment_file_name(&url, None);
+        assert_ne!(first, second);
+        assert_ne!(first, whole);
+        assert_ne!(second, whole);
+
+        // The byte range is kept for long urls.
+        let url = Url::parse(&format!("https://example.com/{}.mp4", "a".repeat(300))).unwrap();
+        let first = get_media_segment_file_name(
+            &url,
+            Some(ByteRange {
+                length: 100,
+                offset: 0,
+            }),
+        );
+        let second = get_media_segment_file_name(
+            &url,
+            Some(ByteRange {
+                length: 100,
+                offset: 100,
+            }),
+        );
+        assert_ne!(first, second);
+    }
+}
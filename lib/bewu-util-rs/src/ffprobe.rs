@@ -0,0 +1,119 @@
+use anyhow::ensure;
+use anyhow::Context;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Probe a url or path with ffprobe.
+pub async fn probe_url(url: &str) -> anyhow::Result<ProbeResult> {
+    let output = tokio::process::Command::new("ffprobe")
+        .args(["-v", "error"])
+        .arg("-hide_banner")
+        .arg(url)
+        .args(["-of", "default=noprint_wrappers=0"])
+        .args(["-print_format", "json"])
+        .arg("-show_format")
+        // .args(["-show_entries", "stream"])
+        // .arg("-show_programs")
+        .output()
+        .await
+        .context("failed to spawn \"ffprobe\"")?;
+
+    ensure!(
+        output.status.success(),
+        "ffprobe exited with \"{}\"",
+        output.status
+    );
+
+    let stdout = std::str::from_utf8(&output.stdout)?;
+
+    Ok(serde_json::from_str(stdout)?)
+}
+
+/// Result of probing
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct ProbeResult {
+    /// Format info
+    pub format: Format,
+
+    #[serde(flatten)]
+    pub unknown: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct Format {
+    /// The # of programs
+    pub nb_programs: u32,
+
+    /// The start time, as a string?
+    pub start_time: String,
+
+    /// The file name
+    pub filename: String,
+
+    /// The bit rate, as a string?
+    pub bit_rate: String,
+
+    /// The format name
+    pub format_name: String,
+
+    /// The # of streams, as a string
+    pub nb_streams: u32,
+
+    /// The long name of the format
+    pub format_long_name: String,
+
+    /// The size of the data?
+    pub size: String,
+
+    /// The duration, as a string?
+    pub duration: String,
+
+    /// ?
+    pub probe_score: u32,
+
+    /// Extra k/v
+    #[serde(flatten)]
+    pub unknown: HashMap<String, serde_json::Value>,
+}
+
+impl Format {
+    /// Parse the duration.
+    ///
+    /// The duration is in seconds, like `1420.032000`.
+    pub fn parse_duration(&self) -> anyhow::Result<Duration> {
+        let duration: f64 = self
+            .duration
+            .parse()
+            .with_context(|| format!("failed to parse duration \"{}\"", self.duration))?;
+        Duration::try_from_secs_f64(duration).context("invalid duration")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_duration() {
+        let format: Format = serde_json::from_str(
+            r#"{
+                "filename": "episode.mp4",
+                "nb_streams": 2,
+                "nb_programs": 0,
+                "format_name": "mov,mp4,m4a,3gp,3g2,mj2",
+                "format_long_name": "QuickTime / MOV",
+                "start_time": "0.000000",
+                "duration": "1420.032000",
+                "size": "155029487",
+                "bit_rate": "873374",
+                "probe_score": 100
+            }"#,
+        )
+        .expect("failed to parse format");
+
+        assert_eq!(
+            format.parse_duration().expect("failed to parse duration"),
+            Duration::from_micros(1_420_032_000)
+        );
+    }
+}
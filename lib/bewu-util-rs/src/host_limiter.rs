@@ -0,0 +1,132 @@
+use std::collections::hash_map::Entry as HashMapEntry;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::Semaphore;
+
+/// A type to limit the number of concurrent requests made to a single host.
+#[derive(Debug, Clone)]
+pub struct HostLimiter {
+    map: Arc<Mutex<HashMap<Box<str>, Arc<Semaphore>>>>,
+    limit: usize,
+}
+
+impl HostLimiter {
+    /// Create a new [`HostLimiter`], allowing `limit` concurrent requests per host.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the limit is 0.
+    pub fn new(limit: usize) -> Self {
+        assert!(limit != 0, "limit is 0");
+
+        Self {
+            map: Arc::new(Mutex::new(HashMap::new())),
+            limit,
+        }
+    }
+
+    /// Get the maximum number of concurrent requests per host.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Wait for permission to make a request to the given host.
+    ///
+    /// The permission is released when the returned permit is dropped.
+    pub async fn acquire(&self, host: &str) -> HostLimiterPermit {
+        let semaphore = {
+            // We want poisioning to panic, for extra safety.
+            let mut map = self.map.lock().unwrap();
+
+            map.entry(host.into())
+                .or_insert_with(|| Arc::new(Semaphore::new(self.limit)))
+                .clone()
+        };
+
+        let mut permit = HostLimiterPermit {
+            limiter: self.clone(),
+            host: Some(host.into()),
+            permit: None,
+        };
+
+        // We never close the semaphore,
+        // so we can unwrap.
+        permit.permit = Some(semaphore.acquire_owned().await.unwrap());
+
+        permit
+    }
+}
+
+/// A permit to make a request to a host.
+#[derive(Debug)]
+pub struct HostLimiterPermit {
+    limiter: HostLimiter,
+
+    host: Option<Box<str>>,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for HostLimiterPermit {
+    fn drop(&mut self) {
+        // Extract the key.
+        //
+        // This will only be extracted on drop.
+        // If this is missing, this is a bug.
+        // Therefore, we unwrap.
+        let host = self.host.take().unwrap();
+
+        // We want poisioning to panic, for extra safety.
+        let mut map = self.limiter.map.lock().unwrap();
+
+        // Drop the permit while holding the map lock,
+        // to ensure our Arc uniqueness testing does not race.
+        //
+        // Note that the permit may be absent.
+        // This signifies that the future was aborted before a permit could be acquired.
+        drop(self.permit.take());
+
+        if let HashMapEntry::Occupied(mut entry) = map.entry(host) {
+            // If the map holds the last reference,
+            // nobody is using or waiting on this host.
+            // Remove it to save memory.
+            if Arc::get_mut(entry.get_mut()).is_some() {
+                entry.remove();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn sanity() {
+        let limiter = HostLimiter::new(2);
+
+        let permit_1 = limiter.acquire("example.com").await;
+        let permit_2 = limiter.acquire("example.com").await;
+
+        // The host is at its limit, this should time out.
+        tokio::time::timeout(Duration::from_millis(50), limiter.acquire("example.com"))
+            .await
+            .unwrap_err();
+
+        // Other hosts are not limited.
+        let permit_3 = limiter.acquire("example.org").await;
+
+        // Releasing a permit allows another request.
+        drop(permit_1);
+        let permit_4 = limiter.acquire("example.com").await;
+
+        drop(permit_2);
+        drop(permit_3);
+        drop(permit_4);
+
+        // The limiter should have released all resources.
+        assert!(limiter.map.lock().unwrap().is_empty());
+    }
+}
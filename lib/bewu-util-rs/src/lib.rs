@@ -37,3 +37,18 @@ pub use self::download_hls::*;
 mod async_mutex_map;
 #[cfg(feature = "async-mutex-map")]
 pub use self::async_mutex_map::*;
+
+#[cfg(feature = "host-limiter")]
+mod host_limiter;
+#[cfg(feature = "host-limiter")]
+pub use self::host_limiter::*;
+
+#[cfg(feature = "ffprobe")]
+mod ffprobe;
+#[cfg(feature = "ffprobe")]
+pub use self::ffprobe::*;
+
+#[cfg(feature = "quality-preference")]
+mod quality_preference;
+#[cfg(feature = "quality-preference")]
+pub use self::quality_preference::*;
@@ -0,0 +1,124 @@
+use hls_parser::VariantStream;
+use std::cmp::Reverse;
+
+/// A preference for the quality of a downloaded stream.
+///
+/// Limits are soft.
+/// If no stream satisfies them, the smallest stream is chosen instead of failing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QualityPreference {
+    /// The maximum vertical resolution, like `720`.
+    pub max_resolution: Option<u32>,
+
+    /// The maximum bandwidth, in bits per second.
+    pub max_bandwidth: Option<u64>,
+
+    /// Codecs to prefer, from most to least preferred.
+    ///
+    /// These are matched against the start of a stream's codecs,
+    /// so `avc1` matches `avc1.64001f`.
+    pub preferred_codecs: Vec<String>,
+}
+
+impl QualityPreference {
+    /// Select the best variant stream for this preference.
+    ///
+    /// Of the streams within the limits, the stream with the most preferred codec is chosen.
+    /// Ties are broken by the highest bandwidth.
+    /// Returns `None` if there are no variant streams.
+    pub fn select_variant_stream<'a>(
+        &self,
+        variant_streams: &'a [VariantStream],
+    ) -> Option<&'a VariantStream> {
+        variant_streams
+            .iter()
+            .filter(|stream| self.is_within_limits(stream))
+            .max_by_key(|stream| (Reverse(self.get_codec_rank(stream)), stream.bandwidth))
+            .or_else(|| variant_streams.iter().min_by_key(|stream| stream.bandwidth))
+    }
+
+    /// Check if the given variant stream is within the resolution and bandwidth limits.
+    fn is_within_limits(&self, stream: &VariantStream) -> bool {
+        let resolution_ok = match (self.max_resolution, stream.resolution) {
+            (Some(max_resolution), Some((_width, height))) => height <= u64::from(max_resolution),
+            _ => true,
+        };
+        let bandwidth_ok = self
+            .max_bandwidth
+            .is_none_or(|max_bandwidth| stream.bandwidth <= max_bandwidth);
+
+        resolution_ok && bandwidth_ok
+    }
+
+    /// Get the rank of the most preferred codec of the given stream.
+    ///
+    /// Lower is better.
+    /// Streams with no preferred codecs rank after all others.
+    fn get_codec_rank(&self, stream: &VariantStream) -> usize {
+        let codecs = stream.codecs.as_deref().unwrap_or(&[]);
+
+        self.preferred_codecs
+            .iter()
+            .position(|preferred_codec| {
+                codecs
+                    .iter()
+                    .any(|codec| codec.starts_with(preferred_codec.as_str()))
+            })
+            .unwrap_or(self.preferred_codecs.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hls_parser::MasterPlaylist;
+
+    const MASTER_PLAYLIST: &str = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS=\"avc1.4d401e,mp4a.40.2\"
+360.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=1400000,RESOLUTION=1280x720,CODECS=\"hvc1.1.6.L93.B0,mp4a.40.2\"
+720-hevc.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2800000,RESOLUTION=1280x720,CODECS=\"avc1.4d401f,mp4a.40.2\"
+720.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080,CODECS=\"avc1.640028,mp4a.40.2\"
+1080.m3u8
+";
+
+    fn select(preference: &QualityPreference) -> String {
+        let master_playlist: MasterPlaylist = MASTER_PLAYLIST.parse().expect("invalid playlist");
+        preference
+            .select_variant_stream(&master_playlist.variant_streams)
+            .expect("missing stream")
+            .uri
+            .to_string()
+    }
+
+    #[test]
+    fn select_variant_stream() {
+        assert_eq!(select(&QualityPreference::default()), "1080.m3u8");
+
+        let preference = QualityPreference {
+            max_resolution: Some(720),
+            ..QualityPreference::default()
+        };
+        assert_eq!(select(&preference), "720.m3u8");
+
+        let preference = QualityPreference {
+            max_bandwidth: Some(2_000_000),
+            ..QualityPreference::default()
+        };
+        assert_eq!(select(&preference), "720-hevc.m3u8");
+
+        let preference = QualityPreference {
+            preferred_codecs: vec!["hvc1".into(), "avc1".into()],
+            ..QualityPreference::default()
+        };
+        assert_eq!(select(&preference), "720-hevc.m3u8");
+
+        let preference = QualityPreference {
+            max_resolution: Some(240),
+            ..QualityPreference::default()
+        };
+        assert_eq!(select(&preference), "360.m3u8");
+    }
+}
@@ -0,0 +1,18 @@
+/// A sub-range of a resource
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ByteRange {
+    /// The length of the sub-range, in bytes
+    pub length: u64,
+
+    /// The offset of the start of the sub-range from the start of the resource, in bytes
+    pub offset: u64,
+}
+
+impl ByteRange {
+    /// Get the offset of the end of the sub-range, in bytes.
+    ///
+    /// This is exclusive.
+    pub fn end(&self) -> u64 {
+        self.offset.saturating_add(self.length)
+    }
+}
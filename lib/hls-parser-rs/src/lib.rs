@@ -1,14 +1,23 @@
 //! https://datatracker.ietf.org/doc/html/rfc8216
 //! https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis
 
+mod byte_range;
 mod master_playlist;
 mod media_playlist;
+mod media_type;
 mod playlist_type;
 mod tag;
 
+pub use self::byte_range::ByteRange;
+pub use self::master_playlist::ClosedCaptions;
 pub use self::master_playlist::MasterPlaylist;
+pub use self::master_playlist::Rendition;
 pub use self::master_playlist::VariantStream;
+pub use self::media_playlist::InitializationSection;
 pub use self::media_playlist::MediaPlaylist;
+pub use self::media_playlist::MediaSegment;
+pub use self::media_type::MediaType;
+pub use self::media_type::ParseMediaTypeError;
 pub use self::playlist_type::ParsePlaylistTypeError;
 pub use self::playlist_type::PlaylistType;
 pub(crate) use self::tag::ParseTagError;
@@ -24,11 +33,14 @@ const EXT_INF_TAG: &str = "EXTINF";
 const EXT_X_VERSION_TAG: &str = "EXT-X-VERSION";
 const EXT_X_MEDIA_SEQUENCE_TAG: &str = "EXT-X-MEDIA-SEQUENCE";
 const EXT_X_KEY_TAG: &str = "EXT-X-KEY";
+const EXT_X_MEDIA_TAG: &str = "EXT-X-MEDIA";
 const EXT_X_STREAM_INF_TAG: &str = "EXT-X-STREAM-INF";
 const EXT_X_ALLOW_CACHE_TAG: &str = "EXT-X-ALLOW-CACHE";
 const EXT_X_PLAYLIST_TYPE_TAG: &str = "EXT-X-PLAYLIST-TYPE";
 const EXT_X_ENDLIST_TAG: &str = "EXT-X-ENDLIST";
 const EXT_X_INDEPENDENT_SEGMENTS_TAG: &str = "EXT-X-INDEPENDENT-SEGMENTS";
+const EXT_X_BYTERANGE_TAG: &str = "EXT-X-BYTERANGE";
+const EXT_X_MAP_TAG: &str = "EXT-X-MAP";
 
 /// An error that may occur while parsing a video range
 #[derive(Debug)]
@@ -56,13 +68,30 @@ pub enum VideoRange {
     Pq,
 }
 
+impl VideoRange {
+    /// Get the video range as a str.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sdr => "SDR",
+            Self::Hlg => "HLG",
+            Self::Pq => "PQ",
+        }
+    }
+}
+
+impl std::fmt::Display for VideoRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 impl std::str::FromStr for VideoRange {
     type Err = ParseVideoRangeError;
 
     fn from_str(input: &str) -> Result<Self, Self::Err> {
         match input {
             "SDR" => Ok(Self::Sdr),
-            "HLQ" => Ok(Self::Hlg),
+            "HLG" => Ok(Self::Hlg),
             "PQ" => Ok(Self::Pq),
             _ => Err(ParseVideoRangeError(input.into())),
         }
@@ -107,6 +136,16 @@ pub enum Error {
         tag: &'static str,
     },
 
+    /// A variant stream referenced a rendition group that does not exist
+    #[error("missing {media_type} rendition group \"{group_id}\"")]
+    MissingRenditionGroup {
+        /// The media type of the group
+        media_type: MediaType,
+
+        /// The id of the group
+        group_id: Box<str>,
+    },
+
     /// An error occured while parsing a tag
     #[error("tag parse error")]
     Tag {
@@ -117,4 +156,8 @@ pub enum Error {
 
     #[error("a tag was invalid in the given context")]
     InvalidTag,
+
+    /// A byte range without an offset did not follow a byte range of the same resource
+    #[error("a byte range without an offset must follow a byte range of the same resource")]
+    MissingByteRangeOffset,
 }
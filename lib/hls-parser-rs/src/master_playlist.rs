@@ -1,4 +1,5 @@
 use crate::Error;
+use crate::MediaType;
 use crate::Tag;
 use crate::UriReferenceStr;
 use crate::UriReferenceString;
@@ -7,10 +8,40 @@ use crate::EXT_M3U_TAG;
 use crate::EXT_X_STREAM_INF_TAG;
 
 /// A master playlist
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct MasterPlaylist {
     /// A list of all variant streams
     pub variant_streams: Vec<VariantStream>,
+
+    /// A list of all alternate renditions
+    pub renditions: Vec<Rendition>,
+}
+
+impl MasterPlaylist {
+    /// Get the renditions in the given group.
+    pub fn get_rendition_group<'a>(
+        &'a self,
+        media_type: MediaType,
+        group_id: &'a str,
+    ) -> impl Iterator<Item = &'a Rendition> + 'a {
+        self.renditions.iter().filter(move |rendition| {
+            rendition.media_type == media_type && &*rendition.group_id == group_id
+        })
+    }
+
+    /// Get the renditions of the given type for a variant stream.
+    ///
+    /// This is empty if the variant stream has no rendition group of the given type.
+    pub fn get_variant_stream_renditions<'a>(
+        &'a self,
+        variant_stream: &'a VariantStream,
+        media_type: MediaType,
+    ) -> impl Iterator<Item = &'a Rendition> + 'a {
+        let group_id = variant_stream.get_rendition_group_id(media_type);
+        self.renditions.iter().filter(move |rendition| {
+            rendition.media_type == media_type && Some(&*rendition.group_id) == group_id
+        })
+    }
 }
 
 impl std::str::FromStr for MasterPlaylist {
@@ -28,6 +59,7 @@ impl std::str::FromStr for MasterPlaylist {
 
         let mut stream_info = None;
         let mut variant_streams = Vec::with_capacity(4);
+        let mut renditions = Vec::new();
         for line in lines {
             if line.is_empty() {
                 continue;
@@ -46,6 +78,10 @@ impl std::str::FromStr for MasterPlaylist {
                             frame_rate,
                             video_range,
                             name,
+                            audio,
+                            video,
+                            subtitles,
+                            closed_captions,
                         } => {
                             if stream_info.is_some() {
                                 return Err(Error::DuplicateTag {
@@ -62,8 +98,52 @@ impl std::str::FromStr for MasterPlaylist {
                                 frame_rate,
                                 video_range,
                                 name,
+                                audio,
+                                video,
+                                subtitles,
+                                closed_captions,
                             ));
                         }
+                        Tag::ExtXMedia {
+                            media_type,
+                            uri,
+                            group_id,
+                            language,
+                            assoc_language,
+                            name,
+                            default,
+                            autoselect,
+                            forced,
+                            instream_id,
+                            characteristics,
+                            channels,
+                        } => {
+                            let uri = uri
+                                .map(|uri| {
+                                    UriReferenceStr::new(&uri)
+                                        .map(UriReferenceString::from)
+                                        .map_err(|error| Error::InvalidUri {
+                                            line: line.into(),
+                                            error,
+                                        })
+                                })
+                                .transpose()?;
+
+                            renditions.push(Rendition {
+                                media_type,
+                                uri,
+                                group_id,
+                                language,
+                                assoc_language,
+                                name,
+                                default,
+                                autoselect,
+                                forced,
+                                instream_id,
+                                characteristics,
+                                channels,
+                            });
+                        }
                         _ => {
                             return Err(Error::InvalidTag);
                         }
@@ -82,6 +162,10 @@ impl std::str::FromStr for MasterPlaylist {
                     frame_rate,
                     video_range,
                     name,
+                    audio,
+                    video,
+                    subtitles,
+                    closed_captions,
                 ) = stream_info.take().ok_or(Error::MissingTag {
                     tag: EXT_X_STREAM_INF_TAG,
                 })?;
@@ -95,16 +179,89 @@ impl std::str::FromStr for MasterPlaylist {
                     frame_rate,
                     video_range,
                     name,
+                    audio,
+                    video,
+                    subtitles,
+                    closed_captions,
+                });
+            }
+        }
+
+        // Every rendition group referenced by a variant stream must exist.
+        for variant_stream in variant_streams.iter() {
+            for media_type in [
+                MediaType::Audio,
+                MediaType::Video,
+                MediaType::Subtitles,
+                MediaType::ClosedCaptions,
+            ] {
+                let group_id = match variant_stream.get_rendition_group_id(media_type) {
+                    Some(group_id) => group_id,
+                    None => continue,
+                };
+                let exists = renditions.iter().any(|rendition| {
+                    rendition.media_type == media_type && &*rendition.group_id == group_id
                 });
+                if !exists {
+                    return Err(Error::MissingRenditionGroup {
+                        media_type,
+                        group_id: group_id.into(),
+                    });
+                }
             }
         }
 
-        Ok(Self { variant_streams })
+        Ok(Self {
+            variant_streams,
+            renditions,
+        })
+    }
+}
+
+impl std::fmt::Display for MasterPlaylist {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{EXT_M3U_TAG}")?;
+        for rendition in self.renditions.iter() {
+            let tag = Tag::ExtXMedia {
+                media_type: rendition.media_type,
+                uri: rendition.uri.as_ref().map(|uri| uri.as_str().into()),
+                group_id: rendition.group_id.clone(),
+                language: rendition.language.clone(),
+                assoc_language: rendition.assoc_language.clone(),
+                name: rendition.name.clone(),
+                default: rendition.default,
+                autoselect: rendition.autoselect,
+                forced: rendition.forced,
+                instream_id: rendition.instream_id.clone(),
+                characteristics: rendition.characteristics.clone(),
+                channels: rendition.channels.clone(),
+            };
+            writeln!(f, "{tag}")?;
+        }
+        for variant_stream in self.variant_streams.iter() {
+            let tag = Tag::ExtXStreamInf {
+                bandwidth: variant_stream.bandwidth,
+                average_bandwidth: variant_stream.average_bandwidth,
+                codecs: variant_stream.codecs.clone(),
+                resolution: variant_stream.resolution,
+                frame_rate: variant_stream.frame_rate,
+                video_range: variant_stream.video_range,
+                name: variant_stream.name.clone(),
+                audio: variant_stream.audio.clone(),
+                video: variant_stream.video.clone(),
+                subtitles: variant_stream.subtitles.clone(),
+                closed_captions: variant_stream.closed_captions.clone(),
+            };
+            writeln!(f, "{tag}")?;
+            writeln!(f, "{}", variant_stream.uri)?;
+        }
+
+        Ok(())
     }
 }
 
 /// A variant stream
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct VariantStream {
     /// The uri of the stream
     pub uri: UriReferenceString,
@@ -129,6 +286,86 @@ pub struct VariantStream {
 
     /// The name
     pub name: Option<Box<str>>,
+
+    /// The id of the audio rendition group
+    pub audio: Option<Box<str>>,
+
+    /// The id of the video rendition group
+    pub video: Option<Box<str>>,
+
+    /// The id of the subtitles rendition group
+    pub subtitles: Option<Box<str>>,
+
+    /// The closed captions rendition group
+    pub closed_captions: Option<ClosedCaptions>,
+}
+
+impl VariantStream {
+    /// Get the id of the rendition group of the given type.
+    pub fn get_rendition_group_id(&self, media_type: MediaType) -> Option<&str> {
+        match media_type {
+            MediaType::Audio => self.audio.as_deref(),
+            MediaType::Video => self.video.as_deref(),
+            MediaType::Subtitles => self.subtitles.as_deref(),
+            MediaType::ClosedCaptions => match self.closed_captions.as_ref()? {
+                ClosedCaptions::GroupId(group_id) => Some(group_id),
+                ClosedCaptions::None => None,
+            },
+        }
+    }
+}
+
+/// The closed captions of a variant stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClosedCaptions {
+    /// The id of the closed captions rendition group
+    GroupId(Box<str>),
+
+    /// The variant stream has no closed captions
+    None,
+}
+
+/// An alternate rendition, from an EXT-X-MEDIA tag
+#[derive(Debug, PartialEq)]
+pub struct Rendition {
+    /// The media type
+    pub media_type: MediaType,
+
+    /// The uri of the media playlist.
+    ///
+    /// If this is `None`, the rendition is included in the media playlist of the variant stream.
+    /// This is always `None` for closed captions.
+    pub uri: Option<UriReferenceString>,
+
+    /// The id of the group this rendition belongs to
+    pub group_id: Box<str>,
+
+    /// The language, as a BCP 47 language tag
+    pub language: Option<Box<str>>,
+
+    /// The associated language, as a BCP 47 language tag
+    pub assoc_language: Option<Box<str>>,
+
+    /// The human-readable name
+    pub name: Box<str>,
+
+    /// Whether this rendition should be played when the user has no preference
+    pub default: bool,
+
+    /// Whether this rendition may be chosen automatically
+    pub autoselect: bool,
+
+    /// Whether this subtitles rendition has content that is essential to play
+    pub forced: bool,
+
+    /// The closed captions channel, like `CC1` or `SERVICE1`
+    pub instream_id: Option<Box<str>>,
+
+    /// The uniform type identifiers of the characteristics of this rendition
+    pub characteristics: Option<Vec<Box<str>>>,
+
+    /// The audio channels, like `2` or `6`
+    pub channels: Option<Box<str>>,
 }
 
 #[cfg(test)]
@@ -145,6 +382,11 @@ mod test {
         "/test_data/real-master-playlist-1.m3u8"
     ));
 
+    const MASTER_PLAYLIST_WITH_ALTERNATE_RENDITIONS: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test_data/master-playlist-with-alternate-renditions.m3u8"
+    ));
+
     #[test]
     fn parse_master_playlist() {
         let playlist: MasterPlaylist = MASTER_PLAYLIST.parse().expect("failed to parse");
@@ -156,4 +398,102 @@ mod test {
         let playlist: MasterPlaylist = REAL_MASTER_PLAYLIST_1.parse().expect("failed to parse");
         dbg!(&playlist);
     }
+
+    #[test]
+    fn parse_master_playlist_with_alternate_renditions() {
+        let playlist: MasterPlaylist = MASTER_PLAYLIST_WITH_ALTERNATE_RENDITIONS
+            .parse()
+            .expect("failed to parse");
+        assert!(playlist.renditions.len() == 6);
+        assert!(playlist.variant_streams.len() == 2);
+
+        let variant_stream = &playlist.variant_streams[0];
+        assert!(variant_stream.audio.as_deref() == Some("aac"));
+        assert!(variant_stream.subtitles.as_deref() == Some("subs"));
+        assert!(variant_stream.closed_captions == Some(ClosedCaptions::GroupId("cc".into())));
+
+        let audio: Vec<_> = playlist
+            .get_variant_stream_renditions(variant_stream, MediaType::Audio)
+            .collect();
+        assert!(audio.len() == 2);
+        assert!(audio[0].language.as_deref() == Some("en"));
+        assert!(audio[0].default);
+        assert!(audio[0].uri.as_ref().map(|uri| uri.as_str()) == Some("audio/en/prog_index.m3u8"));
+        assert!(audio[1].language.as_deref() == Some("ja"));
+        assert!(&*audio[1].name == "日本語");
+        assert!(!audio[1].default);
+
+        let subtitles: Vec<_> = playlist
+            .get_variant_stream_renditions(variant_stream, MediaType::Subtitles)
+            .collect();
+        assert!(subtitles.len() == 3);
+        assert!(subtitles[1].forced);
+        assert!(subtitles[2].assoc_language.as_deref() == Some("es"));
+        assert!(subtitles[2].characteristics.as_ref().map(|c| c.len()) == Some(2));
+
+        let closed_captions: Vec<_> = playlist
+            .get_variant_stream_renditions(variant_stream, MediaType::ClosedCaptions)
+            .collect();
+        assert!(closed_captions.len() == 1);
+        assert!(closed_captions[0].uri.is_none());
+        assert!(closed_captions[0].instream_id.as_deref() == Some("CC1"));
+
+        let variant_stream = &playlist.variant_streams[1];
+        assert!(variant_stream.closed_captions == Some(ClosedCaptions::None));
+        assert!(playlist
+            .get_variant_stream_renditions(variant_stream, MediaType::ClosedCaptions)
+            .next()
+            .is_none());
+        assert!(playlist
+            .get_variant_stream_renditions(variant_stream, MediaType::Video)
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn reject_missing_rendition_group() {
+        let input = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",URI=\"audio.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,AUDIO=\"mp3\"
+video.m3u8
+";
+        let error = input
+            .parse::<MasterPlaylist>()
+            .expect_err("parsed missing rendition group");
+        assert!(
+            matches!(
+                error,
+                Error::MissingRenditionGroup {
+                    media_type: MediaType::Audio,
+                    ref group_id,
+                } if &**group_id == "mp3"
+            ),
+            "{error:?}"
+        );
+
+        // A group of the wrong type does not count.
+        let input = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",URI=\"audio.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=1280000,SUBTITLES=\"aac\"
+video.m3u8
+";
+        assert!(input.parse::<MasterPlaylist>().is_err());
+    }
+
+    #[test]
+    fn write_round_trip() {
+        for input in [
+            MASTER_PLAYLIST,
+            REAL_MASTER_PLAYLIST_1,
+            MASTER_PLAYLIST_WITH_ALTERNATE_RENDITIONS,
+        ] {
+            let playlist: MasterPlaylist = input.parse().expect("failed to parse");
+            let output = playlist.to_string();
+            let new_playlist: MasterPlaylist = output.parse().expect("failed to parse output");
+            assert!(playlist == new_playlist, "{output}");
+
+            // Writing is deterministic.
+            assert!(new_playlist.to_string() == output);
+        }
+    }
 }
@@ -1,4 +1,6 @@
+use crate::ByteRange;
 use crate::Error;
+use crate::ParseTagError;
 use crate::PlaylistType;
 use crate::Tag;
 use crate::UriReferenceStr;
@@ -10,7 +12,7 @@ use crate::EXT_X_VERSION_TAG;
 use std::time::Duration;
 
 /// A media playlist
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct MediaPlaylist {
     /// The target duration
     pub target_duration: Duration,
@@ -28,6 +30,12 @@ pub struct MediaPlaylist {
 
     /// The playlist type
     pub playlist_type: Option<PlaylistType>,
+
+    /// Whether all media samples in a media segment can be decoded without information from other media segments
+    pub independent_segments: bool,
+
+    /// Whether no more media segments will be added to this playlist
+    pub end_list: bool,
 }
 
 impl std::str::FromStr for MediaPlaylist {
@@ -47,11 +55,17 @@ impl std::str::FromStr for MediaPlaylist {
         let mut version = None;
         let mut media_sequence_number = None;
         let mut playlist_type = None;
+        let mut independent_segments = false;
+        let mut end_list = false;
         let mut encryption_method = None;
         let mut encryption_uri = None;
+        let mut encryption_iv = None;
+        let mut encryption_key_format = None;
+        let mut initialization_section = None;
 
         let mut ext_inf_tag = None;
-        let mut media_segments = Vec::with_capacity(16);
+        let mut byte_range_tag = None;
+        let mut media_segments: Vec<MediaSegment> = Vec::with_capacity(16);
         for line in lines {
             if line.is_empty() {
                 continue;
@@ -89,10 +103,38 @@ impl std::str::FromStr for MediaPlaylist {
                             // TODO: Disallow dupes?
                             media_sequence_number = Some(number);
                         }
-                        Tag::ExtXKey { method, uri } => {
-                            // TODO: Apply encryption data to media segments individually
+                        Tag::ExtXKey {
+                            method,
+                            uri,
+                            iv,
+                            key_format,
+                        } => {
+                            // Each tag replaces the last, and applies to all following media segments.
                             encryption_method = Some(method);
                             encryption_uri = uri;
+                            encryption_iv = iv;
+                            encryption_key_format = key_format;
+                        }
+                        Tag::ExtXByteRange { length, offset } => {
+                            byte_range_tag = Some((length, offset));
+                        }
+                        Tag::ExtXMap { uri, byte_range } => {
+                            let uri =
+                                UriReferenceStr::new(&uri).map_err(|error| Error::InvalidUri {
+                                    line: line.into(),
+                                    error,
+                                })?;
+
+                            // Each tag replaces the last, and applies to all following media segments.
+                            // The media initialization section is encrypted by the EXT-X-KEY tag before it.
+                            initialization_section = Some(InitializationSection {
+                                uri: uri.into(),
+                                byte_range,
+                                encryption_method: encryption_method.clone(),
+                                encryption_uri: encryption_uri.clone(),
+                                encryption_iv,
+                                encryption_key_format: encryption_key_format.clone(),
+                            });
                         }
                         Tag::ExtXAllowCache {} => {
                             // This was removed in spec, but is still allowed/may appear
@@ -107,10 +149,12 @@ impl std::str::FromStr for MediaPlaylist {
                             // TODO: Investigate more
                             // I interpret this as meaning that the server will stop updating the playlist,
                             // not that all future entries are invalid.
+                            end_list = true;
                         }
                         Tag::ExtXIndependentSegments => {
                             // TODO: It means "all media samples in a Media Segment can be decoded without information from other segments.  It applies to every Media Segment in the Playlist."
                             // How to handle?
+                            independent_segments = true;
                         }
                         _ => {
                             return Err(Error::InvalidTag);
@@ -126,12 +170,43 @@ impl std::str::FromStr for MediaPlaylist {
                     .take()
                     .ok_or(Error::MissingTag { tag: EXT_INF_TAG })?;
 
+                // There will never be more than u64::MAX media segments.
+                let media_sequence_number =
+                    media_sequence_number.unwrap_or(0) + media_segments.len() as u64;
+
+                let byte_range = match byte_range_tag.take() {
+                    Some((length, Some(offset))) => Some(ByteRange { length, offset }),
+                    Some((length, None)) => {
+                        // The sub-range starts after the sub-range of the last media segment,
+                        // which must be of the same resource.
+                        let last_byte_range = media_segments
+                            .last()
+                            .filter(|segment| segment.uri.as_str() == uri.as_str())
+                            .and_then(|segment| segment.byte_range)
+                            .ok_or(Error::MissingByteRangeOffset)?;
+                        let offset = last_byte_range.end();
+                        if offset.checked_add(length).is_none() {
+                            return Err(Error::Tag {
+                                error: ParseTagError::ByteRangeOverflow,
+                            });
+                        }
+
+                        Some(ByteRange { length, offset })
+                    }
+                    None => None,
+                };
+
                 media_segments.push(MediaSegment {
                     duration,
                     title,
                     uri: uri.into(),
+                    media_sequence_number,
                     encryption_method: encryption_method.clone(),
                     encryption_uri: encryption_uri.clone(),
+                    encryption_iv,
+                    encryption_key_format: encryption_key_format.clone(),
+                    byte_range,
+                    initialization_section: initialization_section.clone(),
                 })
             }
         }
@@ -148,10 +223,134 @@ impl std::str::FromStr for MediaPlaylist {
             version,
             media_sequence_number,
             playlist_type,
+            independent_segments,
+            end_list,
         })
     }
 }
 
+impl std::fmt::Display for MediaPlaylist {
+    /// Write this playlist.
+    ///
+    /// Tags are written in a canonical order,
+    /// and an EXT-X-KEY tag is only written when the encryption of a media segment changes.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{EXT_M3U_TAG}")?;
+        if let Some(version) = self.version {
+            writeln!(f, "{}", Tag::ExtXVersion { version })?;
+        }
+        writeln!(
+            f,
+            "{}",
+            Tag::ExtXTargetDuration {
+                duration: self.target_duration
+            }
+        )?;
+        if let Some(number) = self.media_sequence_number {
+            writeln!(f, "{}", Tag::ExtXMediaSequence { number })?;
+        }
+        if let Some(playlist_type) = self.playlist_type {
+            writeln!(f, "{}", Tag::ExtXPlaylistType { playlist_type })?;
+        }
+        if self.independent_segments {
+            writeln!(f, "{}", Tag::ExtXIndependentSegments)?;
+        }
+
+        let mut last_encryption = None;
+        let mut last_initialization_section = None;
+        for segment in self.media_segments.iter() {
+            // A media initialization section cannot be removed once it is set,
+            // so a media segment without one is written with the last one.
+            if let Some(initialization_section) = segment.initialization_section.as_ref() {
+                if last_initialization_section != Some(initialization_section) {
+                    write_encryption_key(
+                        f,
+                        &mut last_encryption,
+                        (
+                            &initialization_section.encryption_method,
+                            &initialization_section.encryption_uri,
+                            &initialization_section.encryption_iv,
+                            &initialization_section.encryption_key_format,
+                        ),
+                    )?;
+
+                    let tag = Tag::ExtXMap {
+                        uri: initialization_section.uri.as_str().into(),
+                        byte_range: initialization_section.byte_range,
+                    };
+                    writeln!(f, "{tag}")?;
+                }
+                last_initialization_section = Some(initialization_section);
+            }
+
+            write_encryption_key(
+                f,
+                &mut last_encryption,
+                (
+                    &segment.encryption_method,
+                    &segment.encryption_uri,
+                    &segment.encryption_iv,
+                    &segment.encryption_key_format,
+                ),
+            )?;
+
+            let tag = Tag::ExtInf {
+                duration: segment.duration,
+                title: segment.title.clone(),
+            };
+            writeln!(f, "{tag}")?;
+            if let Some(byte_range) = segment.byte_range {
+                let tag = Tag::ExtXByteRange {
+                    length: byte_range.length,
+                    offset: Some(byte_range.offset),
+                };
+                writeln!(f, "{tag}")?;
+            }
+            writeln!(f, "{}", segment.uri)?;
+        }
+
+        if self.end_list {
+            writeln!(f, "{}", Tag::ExtXEndList)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The encryption method, uri, IV, and key format of an EXT-X-KEY tag.
+type Encryption<'a> = (
+    &'a Option<Box<str>>,
+    &'a Option<Box<str>>,
+    &'a Option<[u8; 16]>,
+    &'a Option<Box<str>>,
+);
+
+/// Write an EXT-X-KEY tag, if the encryption changed from the last one.
+fn write_encryption_key<'a>(
+    f: &mut std::fmt::Formatter<'_>,
+    last_encryption: &mut Option<Encryption<'a>>,
+    encryption: Encryption<'a>,
+) -> std::fmt::Result {
+    let (method, uri, iv, key_format) = encryption;
+    let is_changed = match last_encryption {
+        Some(last_encryption) => *last_encryption != encryption,
+        None => method.is_some(),
+    };
+    if is_changed {
+        let tag = Tag::ExtXKey {
+            // Encryption can only be removed by setting the method to NONE.
+            method: method.clone().unwrap_or_else(|| "NONE".into()),
+            uri: uri.clone(),
+            iv: *iv,
+            key_format: key_format.clone(),
+        };
+        writeln!(f, "{tag}")?;
+    }
+    *last_encryption = Some(encryption);
+
+    Ok(())
+}
+
 /// A media segment
 #[derive(Debug, PartialEq, Eq)]
 pub struct MediaSegment {
@@ -164,11 +363,61 @@ pub struct MediaSegment {
     /// The uri
     pub uri: UriReferenceString,
 
+    /// The media sequence number
+    pub media_sequence_number: u64,
+
     /// The encryption method, if it is present.
     pub encryption_method: Option<Box<str>>,
 
     /// The encryption uri, if it was specified.
     pub encryption_uri: Option<Box<str>>,
+
+    /// The encryption IV, if it was specified.
+    ///
+    /// If this is `None` for the AES-128 method,
+    /// the IV is the big-endian media sequence number.
+    pub encryption_iv: Option<[u8; 16]>,
+
+    /// The encryption key format, if it was specified.
+    ///
+    /// If this is `None`, it can be assumed to be "identity".
+    pub encryption_key_format: Option<Box<str>>,
+
+    /// The byte range of the media segment in the resource at the uri, if it is a sub-range.
+    pub byte_range: Option<ByteRange>,
+
+    /// The media initialization section, if it was specified.
+    ///
+    /// This is needed to parse the media segment, like the fMP4 header of CMAF media segments.
+    pub initialization_section: Option<InitializationSection>,
+}
+
+/// A media initialization section, from an EXT-X-MAP tag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitializationSection {
+    /// The uri
+    pub uri: UriReferenceString,
+
+    /// The byte range of the media initialization section in the resource at the uri, if it is a sub-range.
+    pub byte_range: Option<ByteRange>,
+
+    /// The encryption method, if it is present.
+    ///
+    /// This is from the EXT-X-KEY tag before the EXT-X-MAP tag.
+    pub encryption_method: Option<Box<str>>,
+
+    /// The encryption uri, if it was specified.
+    pub encryption_uri: Option<Box<str>>,
+
+    /// The encryption IV, if it was specified.
+    ///
+    /// This is required for the AES-128 method.
+    pub encryption_iv: Option<[u8; 16]>,
+
+    /// The encryption key format, if it was specified.
+    ///
+    /// If this is `None`, it can be assumed to be "identity".
+    pub encryption_key_format: Option<Box<str>>,
 }
 
 #[cfg(test)]
@@ -195,6 +444,11 @@ mod test {
         "/test_data/playlist-with-encrypted-media-segments.m3u8"
     ));
 
+    const PLAYLIST_WITH_ENCRYPTION_IV: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test_data/playlist-with-encryption-iv.m3u8"
+    ));
+
     const REAL_MEDIA_PLAYLIST_1: &str = include_str!(concat!(
         env!("CARGO_MANIFEST_DIR"),
         "/test_data/real-media-playlist-1.m3u8"
@@ -205,6 +459,21 @@ mod test {
         "/test_data/real-media-playlist-2.m3u8"
     ));
 
+    const FMP4_MEDIA_PLAYLIST: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test_data/fmp4-media-playlist.m3u8"
+    ));
+
+    const FMP4_MEDIA_PLAYLIST_WITH_BYTE_RANGES: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test_data/fmp4-media-playlist-with-byte-ranges.m3u8"
+    ));
+
+    const FMP4_MEDIA_PLAYLIST_WITH_ENCRYPTED_INITIALIZATION_SECTION: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test_data/fmp4-media-playlist-with-encrypted-initialization-section.m3u8"
+    ));
+
     #[test]
     fn parse_simple_media_playlist() {
         let playlist: MediaPlaylist = SIMPLE_MEDIA_PLAYLIST.parse().expect("failed to parse");
@@ -218,8 +487,13 @@ mod test {
                         uri: UriReferenceStr::new("http://media.example.com/first.ts")
                             .unwrap()
                             .into(),
+                        media_sequence_number: 0,
                         encryption_method: None,
                         encryption_uri: None,
+                        encryption_iv: None,
+                        encryption_key_format: None,
+                        byte_range: None,
+                        initialization_section: None,
                     },
                     MediaSegment {
                         duration: Duration::from_secs_f64(9.009),
@@ -227,8 +501,13 @@ mod test {
                         uri: UriReferenceStr::new("http://media.example.com/second.ts")
                             .unwrap()
                             .into(),
+                        media_sequence_number: 1,
                         encryption_method: None,
                         encryption_uri: None,
+                        encryption_iv: None,
+                        encryption_key_format: None,
+                        byte_range: None,
+                        initialization_section: None,
                     },
                     MediaSegment {
                         duration: Duration::from_secs_f64(3.003),
@@ -236,8 +515,13 @@ mod test {
                         uri: UriReferenceStr::new("http://media.example.com/third.ts")
                             .unwrap()
                             .into(),
+                        media_sequence_number: 2,
                         encryption_method: None,
                         encryption_uri: None,
+                        encryption_iv: None,
+                        encryption_key_format: None,
+                        byte_range: None,
+                        initialization_section: None,
                     }
                 ]
         );
@@ -263,10 +547,63 @@ mod test {
             .parse()
             .expect("failed to parse");
         assert!(playlist.version == Some(3));
+        assert!(playlist
+            .media_segments
+            .iter()
+            .map(|segment| segment.media_sequence_number)
+            .eq(7794..7798));
 
         dbg!(&playlist);
     }
 
+    #[test]
+    fn parse_playlist_with_encryption_iv() {
+        let playlist: MediaPlaylist = PLAYLIST_WITH_ENCRYPTION_IV
+            .parse()
+            .expect("failed to parse");
+
+        let segments = &playlist.media_segments;
+        assert!(segments.len() == 3);
+
+        assert!(segments[0].media_sequence_number == 10);
+        assert!(segments[0].encryption_method.as_deref() == Some("AES-128"));
+        assert!(segments[0].encryption_uri.as_deref() == Some("key.bin"));
+        assert!(
+            segments[0].encryption_iv
+                == Some([
+                    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C,
+                    0x0D, 0x0E, 0x0F
+                ])
+        );
+        assert!(segments[0].encryption_key_format.as_deref() == Some("identity"));
+
+        // A new key tag resets the IV.
+        assert!(segments[1].media_sequence_number == 11);
+        assert!(segments[1].encryption_method.as_deref() == Some("AES-128"));
+        assert!(segments[1].encryption_iv.is_none());
+
+        assert!(segments[2].encryption_method.as_deref() == Some("NONE"));
+        assert!(segments[2].encryption_uri.is_none());
+    }
+
+    #[test]
+    fn reject_invalid_encryption_iv() {
+        for iv in [
+            "0x0001",
+            "0x000102030405060708090A0B0C0D0E0",
+            "00010203",
+            "0x",
+        ] {
+            let playlist = format!(
+                "#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\",IV={iv}\n"
+            );
+            assert!(
+                playlist.parse::<MediaPlaylist>().is_err(),
+                "parsed invalid IV \"{iv}\""
+            );
+        }
+    }
+
     #[test]
     fn parse_real_media_playlist_1() {
         let playlist: MediaPlaylist = REAL_MEDIA_PLAYLIST_1.parse().expect("failed to parse");
@@ -289,4 +626,154 @@ mod test {
 
         dbg!(&playlist);
     }
+
+    #[test]
+    fn parse_fmp4_media_playlist() {
+        let playlist: MediaPlaylist = FMP4_MEDIA_PLAYLIST.parse().expect("failed to parse");
+        assert!(playlist.version == Some(7));
+        assert!(playlist.media_segments.len() == 3);
+        assert!(playlist.media_segments.iter().all(|segment| {
+            segment.byte_range.is_none()
+                && segment.initialization_section
+                    == Some(InitializationSection {
+                        uri: UriReferenceStr::new("init.mp4").unwrap().into(),
+                        byte_range: None,
+                        encryption_method: None,
+                        encryption_uri: None,
+                        encryption_iv: None,
+                        encryption_key_format: None,
+                    })
+        }));
+    }
+
+    #[test]
+    fn parse_fmp4_media_playlist_with_byte_ranges() {
+        let playlist: MediaPlaylist = FMP4_MEDIA_PLAYLIST_WITH_BYTE_RANGES
+            .parse()
+            .expect("failed to parse");
+
+        let initialization_section = InitializationSection {
+            uri: UriReferenceStr::new("main.mp4").unwrap().into(),
+            byte_range: Some(ByteRange {
+                length: 720,
+                offset: 0,
+            }),
+            encryption_method: None,
+            encryption_uri: None,
+            encryption_iv: None,
+            encryption_key_format: None,
+        };
+        assert!(playlist
+            .media_segments
+            .iter()
+            .all(
+                |segment| segment.initialization_section.as_ref() == Some(&initialization_section)
+            ));
+
+        // Byte ranges without an offset follow the last byte range.
+        let byte_ranges: Vec<_> = playlist
+            .media_segments
+            .iter()
+            .map(|segment| segment.byte_range)
+            .collect();
+        assert!(
+            byte_ranges
+                == [
+                    Some(ByteRange {
+                        length: 1160328,
+                        offset: 720
+                    }),
+                    Some(ByteRange {
+                        length: 1170840,
+                        offset: 1161048
+                    }),
+                    Some(ByteRange {
+                        length: 786344,
+                        offset: 2331888
+                    }),
+                ]
+        );
+    }
+
+    #[test]
+    fn parse_fmp4_media_playlist_with_encrypted_initialization_section() {
+        let playlist: MediaPlaylist = FMP4_MEDIA_PLAYLIST_WITH_ENCRYPTED_INITIALIZATION_SECTION
+            .parse()
+            .expect("failed to parse");
+
+        // The media initialization section keeps the key before its EXT-X-MAP tag,
+        // even after the media segments change keys.
+        let initialization_section = InitializationSection {
+            uri: UriReferenceStr::new("init.mp4").unwrap().into(),
+            byte_range: None,
+            encryption_method: Some("AES-128".into()),
+            encryption_uri: Some("key.bin".into()),
+            encryption_iv: Some([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]),
+            encryption_key_format: None,
+        };
+        assert!(playlist
+            .media_segments
+            .iter()
+            .all(
+                |segment| segment.initialization_section.as_ref() == Some(&initialization_section)
+            ));
+        assert!(playlist.media_segments[0].encryption_uri.as_deref() == Some("key.bin"));
+        assert!(playlist.media_segments[1].encryption_uri.as_deref() == Some("key-2.bin"));
+    }
+
+    #[test]
+    fn reject_byte_range_without_offset() {
+        for input in [
+            // There is no previous media segment.
+            "#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXTINF:10.0,\n#EXT-X-BYTERANGE:100\nmain.ts\n",
+            // The previous media segment is not a sub-range.
+            "#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXTINF:10.0,\nmain.ts\n#EXTINF:10.0,\n#EXT-X-BYTERANGE:100\nmain.ts\n",
+            // The previous media segment is of a different resource.
+            "#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXTINF:10.0,\n#EXT-X-BYTERANGE:100@0\nfirst.ts\n#EXTINF:10.0,\n#EXT-X-BYTERANGE:100\nsecond.ts\n",
+        ] {
+            let error = input
+                .parse::<MediaPlaylist>()
+                .expect_err("parsed byte range without offset");
+            assert!(
+                matches!(error, Error::MissingByteRangeOffset),
+                "{error:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn write_round_trip() {
+        for input in [
+            SIMPLE_MEDIA_PLAYLIST,
+            LIVE_MEDIA_PLAYLIST_USING_HTTPS,
+            PLAYLIST_WITH_ENCRYPTED_MEDIA_SEGMENTS,
+            PLAYLIST_WITH_ENCRYPTION_IV,
+            REAL_MEDIA_PLAYLIST_1,
+            REAL_MEDIA_PLAYLIST_2,
+            FMP4_MEDIA_PLAYLIST,
+            FMP4_MEDIA_PLAYLIST_WITH_BYTE_RANGES,
+            FMP4_MEDIA_PLAYLIST_WITH_ENCRYPTED_INITIALIZATION_SECTION,
+        ] {
+            let playlist: MediaPlaylist = input.parse().expect("failed to parse");
+            let output = playlist.to_string();
+            let new_playlist: MediaPlaylist = output.parse().expect("failed to parse output");
+            assert!(playlist == new_playlist, "{output}");
+
+            // Writing is deterministic.
+            assert!(new_playlist.to_string() == output);
+        }
+    }
+
+    #[test]
+    fn write_media_playlist() {
+        let playlist: MediaPlaylist = PLAYLIST_WITH_ENCRYPTION_IV
+            .parse()
+            .expect("failed to parse");
+        let output = playlist.to_string();
+        assert!(output.starts_with("#EXTM3U\n"));
+        assert!(output.contains("#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\",IV=0x000102030405060708090A0B0C0D0E0F,KEYFORMAT=\"identity\"\n"));
+        assert!(output.contains("#EXT-X-KEY:METHOD=NONE\n"));
+        // Each key tag is only written once.
+        assert!(output.matches("#EXT-X-KEY:").count() == 3);
+    }
 }
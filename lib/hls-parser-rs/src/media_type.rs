@@ -0,0 +1,59 @@
+/// Failed to parse a media type
+#[derive(Debug)]
+pub struct ParseMediaTypeError(pub Box<str>);
+
+impl std::fmt::Display for ParseMediaTypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{}\" is an invalid media type", self.0)
+    }
+}
+
+impl std::error::Error for ParseMediaTypeError {}
+
+/// The media type of a rendition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MediaType {
+    /// Audio
+    Audio,
+
+    /// Video
+    Video,
+
+    /// WebVTT subtitles
+    Subtitles,
+
+    /// Closed captions, carried in the video of a variant stream
+    ClosedCaptions,
+}
+
+impl MediaType {
+    /// Get the media type as a str.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Audio => "AUDIO",
+            Self::Video => "VIDEO",
+            Self::Subtitles => "SUBTITLES",
+            Self::ClosedCaptions => "CLOSED-CAPTIONS",
+        }
+    }
+}
+
+impl std::fmt::Display for MediaType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for MediaType {
+    type Err = ParseMediaTypeError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "AUDIO" => Ok(MediaType::Audio),
+            "VIDEO" => Ok(MediaType::Video),
+            "SUBTITLES" => Ok(MediaType::Subtitles),
+            "CLOSED-CAPTIONS" => Ok(MediaType::ClosedCaptions),
+            _ => Err(ParseMediaTypeError(input.into())),
+        }
+    }
+}
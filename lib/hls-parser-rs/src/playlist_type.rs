@@ -11,7 +11,7 @@ impl std::fmt::Display for ParsePlaylistTypeError {
 impl std::error::Error for ParsePlaylistTypeError {}
 
 /// The playlist type
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PlaylistType {
     /// The playlist cannot change
     Vod,
@@ -20,6 +20,22 @@ pub enum PlaylistType {
     Event,
 }
 
+impl PlaylistType {
+    /// Get the playlist type as a str.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Vod => "VOD",
+            Self::Event => "EVENT",
+        }
+    }
+}
+
+impl std::fmt::Display for PlaylistType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 impl std::str::FromStr for PlaylistType {
     type Err = ParsePlaylistTypeError;
 
@@ -1,13 +1,20 @@
+use crate::ByteRange;
+use crate::ClosedCaptions;
+use crate::MediaType;
+use crate::ParseMediaTypeError;
 use crate::ParsePlaylistTypeError;
 use crate::ParseVideoRangeError;
 use crate::PlaylistType;
 use crate::VideoRange;
 use crate::EXT_INF_TAG;
 use crate::EXT_X_ALLOW_CACHE_TAG;
+use crate::EXT_X_BYTERANGE_TAG;
 use crate::EXT_X_ENDLIST_TAG;
 use crate::EXT_X_INDEPENDENT_SEGMENTS_TAG;
 use crate::EXT_X_KEY_TAG;
+use crate::EXT_X_MAP_TAG;
 use crate::EXT_X_MEDIA_SEQUENCE_TAG;
+use crate::EXT_X_MEDIA_TAG;
 use crate::EXT_X_PLAYLIST_TYPE_TAG;
 use crate::EXT_X_STREAM_INF_TAG;
 use crate::EXT_X_TARGET_DURATION_TAG;
@@ -24,6 +31,28 @@ const VIDEO_RANGE_ATTR: &str = "VIDEO-RANGE";
 const NAME_ATTR: &str = "NAME";
 const METHOD_ATTR: &str = "METHOD";
 const URI_ATTR: &str = "URI";
+const IV_ATTR: &str = "IV";
+const KEYFORMAT_ATTR: &str = "KEYFORMAT";
+const KEYFORMATVERSIONS_ATTR: &str = "KEYFORMATVERSIONS";
+const TYPE_ATTR: &str = "TYPE";
+const GROUP_ID_ATTR: &str = "GROUP-ID";
+const LANGUAGE_ATTR: &str = "LANGUAGE";
+const ASSOC_LANGUAGE_ATTR: &str = "ASSOC-LANGUAGE";
+const DEFAULT_ATTR: &str = "DEFAULT";
+const AUTOSELECT_ATTR: &str = "AUTOSELECT";
+const FORCED_ATTR: &str = "FORCED";
+const INSTREAM_ID_ATTR: &str = "INSTREAM-ID";
+const CHARACTERISTICS_ATTR: &str = "CHARACTERISTICS";
+const CHANNELS_ATTR: &str = "CHANNELS";
+const AUDIO_ATTR: &str = "AUDIO";
+const VIDEO_ATTR: &str = "VIDEO";
+const SUBTITLES_ATTR: &str = "SUBTITLES";
+const CLOSED_CAPTIONS_ATTR: &str = "CLOSED-CAPTIONS";
+const BYTERANGE_ATTR: &str = "BYTERANGE";
+
+const YES: &str = "YES";
+const NO: &str = "NO";
+const NONE: &str = "NONE";
 
 /// An error that may occur while parsing a tag
 #[derive(Debug, thiserror::Error)]
@@ -111,11 +140,39 @@ pub enum ParseTagError {
         #[from]
         error: ParseVideoRangeError,
     },
+
+    /// Invalid media type
+    #[error("invalid media type")]
+    InvalidMediaType {
+        #[from]
+        error: ParseMediaTypeError,
+    },
+
+    /// An attribute that must be YES or NO was something else
+    #[error("attribute \"{name}\" must be YES or NO, but got \"{value}\"")]
+    InvalidYesNo {
+        /// The name of the attribute
+        name: Box<str>,
+
+        /// The value of the attribute
+        value: Box<str>,
+    },
+
+    /// A byte range ends after the largest possible offset
+    #[error("the byte range ends after the largest possible offset")]
+    ByteRangeOverflow,
+
+    /// An IV was not 16 bytes
+    #[error("an IV must be 16 bytes, but got {length} bytes")]
+    InvalidIvLength {
+        /// The length of the IV, in bytes
+        length: usize,
+    },
 }
 
 /// A tag
 #[allow(clippy::enum_variant_names)]
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub(crate) enum Tag {
     /// The EXT-X-TARGETDURATION tag
     ExtXTargetDuration {
@@ -148,6 +205,51 @@ pub(crate) enum Tag {
     ExtXKey {
         method: Box<str>,
         uri: Option<Box<str>>,
+
+        /// The IV, if it was specified.
+        iv: Option<[u8; 16]>,
+
+        /// The key format, if it was specified.
+        key_format: Option<Box<str>>,
+    },
+
+    /// The EXT-X-MEDIA tag
+    ExtXMedia {
+        /// The media type
+        media_type: MediaType,
+
+        /// The uri of the media playlist
+        uri: Option<Box<str>>,
+
+        /// The group this rendition belongs to
+        group_id: Box<str>,
+
+        /// The language
+        language: Option<Box<str>>,
+
+        /// The associated language
+        assoc_language: Option<Box<str>>,
+
+        /// The human-readable name
+        name: Box<str>,
+
+        /// Whether to play this rendition when the user has no preference
+        default: bool,
+
+        /// Whether this rendition may be chosen automatically
+        autoselect: bool,
+
+        /// Whether this rendition has content that is essential to play
+        forced: bool,
+
+        /// The closed captions channel
+        instream_id: Option<Box<str>>,
+
+        /// The uniform type identifiers of characteristics
+        characteristics: Option<Vec<Box<str>>>,
+
+        /// The audio channels
+        channels: Option<Box<str>>,
     },
 
     /// The EXT-X-STREAM-INF tag
@@ -172,6 +274,18 @@ pub(crate) enum Tag {
 
         /// The name
         name: Option<Box<str>>,
+
+        /// The audio rendition group id
+        audio: Option<Box<str>>,
+
+        /// The video rendition group id
+        video: Option<Box<str>>,
+
+        /// The subtitles rendition group id
+        subtitles: Option<Box<str>>,
+
+        /// The closed captions rendition group
+        closed_captions: Option<ClosedCaptions>,
     },
 
     /// The EXT-X-ALLOW_CACHE tag
@@ -185,6 +299,26 @@ pub(crate) enum Tag {
 
     /// The EXT-X-INDEPENDENT-SEGMENTS tag
     ExtXIndependentSegments,
+
+    /// The EXT-X-BYTERANGE tag
+    ExtXByteRange {
+        /// The length of the sub-range, in bytes
+        length: u64,
+
+        /// The offset of the sub-range.
+        ///
+        /// If this is `None`, the sub-range starts after the sub-range of the last media segment.
+        offset: Option<u64>,
+    },
+
+    /// The EXT-X-MAP tag
+    ExtXMap {
+        /// The uri of the media initialization section
+        uri: Box<str>,
+
+        /// The byte range of the media initialization section, if it is a sub-range.
+        byte_range: Option<ByteRange>,
+    },
 }
 
 impl std::str::FromStr for Tag {
@@ -225,11 +359,166 @@ impl std::str::FromStr for Tag {
                 .map_err(|error| ParseTagError::ParseInt { error })?;
 
             Ok(Self::ExtXMediaSequence { number })
+        } else if let Some(line) = line.strip_prefix(EXT_X_MEDIA_TAG) {
+            // This must be checked after EXT-X-MEDIA-SEQUENCE, as this tag name is a prefix of it.
+            let line = line.strip_prefix(':').ok_or(ParseTagError::MissingColon)?;
+
+            let mut media_type = None;
+            let mut uri = None;
+            let mut group_id = None;
+            let mut language = None;
+            let mut assoc_language = None;
+            let mut name_attr = None;
+            let mut default = None;
+            let mut autoselect = None;
+            let mut forced = None;
+            let mut instream_id = None;
+            let mut characteristics = None;
+            let mut channels = None;
+
+            let mut parser = AttributeListParser::new(line);
+            loop {
+                let name = parser.parse_name()?;
+                parser.parse_equals()?;
+
+                match name {
+                    TYPE_ATTR => {
+                        let value: MediaType = parser.parse_enumerated_string()?.parse()?;
+                        if media_type.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        media_type = Some(value);
+                    }
+                    URI_ATTR => {
+                        let value = parser.parse_quoted_string()?;
+                        if uri.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        uri = Some(value);
+                    }
+                    GROUP_ID_ATTR => {
+                        let value = parser.parse_quoted_string()?;
+                        if group_id.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        group_id = Some(value);
+                    }
+                    LANGUAGE_ATTR => {
+                        let value = parser.parse_quoted_string()?;
+                        if language.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        language = Some(value);
+                    }
+                    ASSOC_LANGUAGE_ATTR => {
+                        let value = parser.parse_quoted_string()?;
+                        if assoc_language.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        assoc_language = Some(value);
+                    }
+                    NAME_ATTR => {
+                        let value = parser.parse_quoted_string()?;
+                        if name_attr.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        name_attr = Some(value);
+                    }
+                    DEFAULT_ATTR => {
+                        let value = parse_yes_no(name, parser.parse_enumerated_string()?)?;
+                        if default.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        default = Some(value);
+                    }
+                    AUTOSELECT_ATTR => {
+                        let value = parse_yes_no(name, parser.parse_enumerated_string()?)?;
+                        if autoselect.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        autoselect = Some(value);
+                    }
+                    FORCED_ATTR => {
+                        let value = parse_yes_no(name, parser.parse_enumerated_string()?)?;
+                        if forced.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        forced = Some(value);
+                    }
+                    INSTREAM_ID_ATTR => {
+                        let value = parser.parse_quoted_string()?;
+                        if instream_id.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        instream_id = Some(value);
+                    }
+                    CHARACTERISTICS_ATTR => {
+                        let value = parser.parse_quoted_string()?;
+                        let value = value
+                            .split(',')
+                            .map(|s| s.into())
+                            .collect::<Vec<Box<str>>>();
+                        if characteristics.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        characteristics = Some(value);
+                    }
+                    CHANNELS_ATTR => {
+                        let value = parser.parse_quoted_string()?;
+                        if channels.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        channels = Some(value);
+                    }
+                    _ => {
+                        return Err(ParseTagError::UnknownAttribute { name: name.into() });
+                    }
+                }
+
+                match parser.parse_comma() {
+                    Ok(()) => {}
+                    Err(AttributeListParseError::UnexpectedEnd) => {
+                        break;
+                    }
+                    Err(e) => {
+                        return Err(ParseTagError::from(e));
+                    }
+                }
+            }
+
+            let media_type =
+                media_type.ok_or(ParseTagError::MissingAttribute { name: TYPE_ATTR })?;
+            let group_id = group_id.ok_or(ParseTagError::MissingAttribute {
+                name: GROUP_ID_ATTR,
+            })?;
+            let name_attr = name_attr.ok_or(ParseTagError::MissingAttribute { name: NAME_ATTR })?;
+            if media_type == MediaType::ClosedCaptions && instream_id.is_none() {
+                return Err(ParseTagError::MissingAttribute {
+                    name: INSTREAM_ID_ATTR,
+                });
+            }
+
+            Ok(Self::ExtXMedia {
+                media_type,
+                uri: uri.map(|uri| uri.into()),
+                group_id: group_id.into(),
+                language: language.map(|language| language.into()),
+                assoc_language: assoc_language.map(|assoc_language| assoc_language.into()),
+                name: name_attr.into(),
+                default: default.unwrap_or(false),
+                autoselect: autoselect.unwrap_or(false),
+                forced: forced.unwrap_or(false),
+                instream_id: instream_id.map(|instream_id| instream_id.into()),
+                characteristics,
+                channels: channels.map(|channels| channels.into()),
+            })
         } else if let Some(line) = line.strip_prefix(EXT_X_KEY_TAG) {
             let line = line.strip_prefix(':').ok_or(ParseTagError::MissingColon)?;
 
             let mut method = None;
             let mut uri = None;
+            let mut iv = None;
+            let mut key_format = None;
 
             let mut parser = AttributeListParser::new(line);
 
@@ -257,6 +546,32 @@ impl std::str::FromStr for Tag {
                         }
                         uri = Some(value);
                     }
+                    IV_ATTR => {
+                        let value = parser.parse_hexadecimal_sequence()?;
+                        let value: [u8; 16] = value.try_into().map_err(|value: Vec<u8>| {
+                            ParseTagError::InvalidIvLength {
+                                length: value.len(),
+                            }
+                        })?;
+
+                        if iv.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        iv = Some(value);
+                    }
+                    KEYFORMAT_ATTR => {
+                        let value = parser.parse_quoted_string()?;
+
+                        if key_format.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        key_format = Some(value);
+                    }
+                    KEYFORMATVERSIONS_ATTR => {
+                        let _value = parser.parse_quoted_string()?;
+                        // TODO: Only version 1 of the identity key format exists.
+                        // Consider adding if important
+                    }
                     _ => {
                         return Err(ParseTagError::UnknownAttribute { name: name.into() });
                     }
@@ -278,6 +593,8 @@ impl std::str::FromStr for Tag {
             Ok(Self::ExtXKey {
                 method: method.into(),
                 uri: uri.map(|uri| uri.into()),
+                iv,
+                key_format: key_format.map(|key_format| key_format.into()),
             })
         } else if let Some(line) = line.strip_prefix(EXT_X_STREAM_INF_TAG) {
             let line = line.strip_prefix(':').ok_or(ParseTagError::MissingColon)?;
@@ -289,6 +606,10 @@ impl std::str::FromStr for Tag {
             let mut frame_rate = None;
             let mut video_range = None;
             let mut name_attr = None;
+            let mut audio = None;
+            let mut video = None;
+            let mut subtitles = None;
+            let mut closed_captions = None;
 
             let mut parser = AttributeListParser::new(line);
             loop {
@@ -366,6 +687,47 @@ impl std::str::FromStr for Tag {
 
                         name_attr = Some(value);
                     }
+                    AUDIO_ATTR => {
+                        let value = parser.parse_quoted_string()?;
+                        if audio.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        audio = Some(value);
+                    }
+                    VIDEO_ATTR => {
+                        let value = parser.parse_quoted_string()?;
+                        if video.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        video = Some(value);
+                    }
+                    SUBTITLES_ATTR => {
+                        let value = parser.parse_quoted_string()?;
+                        if subtitles.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        subtitles = Some(value);
+                    }
+                    CLOSED_CAPTIONS_ATTR => {
+                        // This is either a quoted string group id, or the enumerated string NONE.
+                        let value = if parser.peek_char() == Some('"') {
+                            ClosedCaptions::GroupId(parser.parse_quoted_string()?.into())
+                        } else {
+                            let value = parser.parse_enumerated_string()?;
+                            if value != NONE {
+                                return Err(ParseTagError::UnknownAttributeValuePair {
+                                    name: name.into(),
+                                    value: value.into(),
+                                });
+                            }
+                            ClosedCaptions::None
+                        };
+
+                        if closed_captions.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        closed_captions = Some(value);
+                    }
                     _ => {
                         return Err(ParseTagError::UnknownAttribute { name: name.into() });
                     }
@@ -393,6 +755,10 @@ impl std::str::FromStr for Tag {
                 frame_rate,
                 video_range,
                 name: name_attr.map(|name| name.into()),
+                audio: audio.map(|audio| audio.into()),
+                video: video.map(|video| video.into()),
+                subtitles: subtitles.map(|subtitles| subtitles.into()),
+                closed_captions,
             })
         } else if let Some(_line) = line.strip_prefix(EXT_X_ALLOW_CACHE_TAG) {
             // TODO: This was removed in the spec
@@ -407,12 +773,293 @@ impl std::str::FromStr for Tag {
             Ok(Self::ExtXEndList)
         } else if let Some(_line) = line.strip_prefix(EXT_X_INDEPENDENT_SEGMENTS_TAG) {
             Ok(Self::ExtXIndependentSegments)
+        } else if let Some(line) = line.strip_prefix(EXT_X_BYTERANGE_TAG) {
+            let line = line.strip_prefix(':').ok_or(ParseTagError::MissingColon)?;
+            let (length, offset) = parse_byte_range(line)?;
+
+            Ok(Self::ExtXByteRange { length, offset })
+        } else if let Some(line) = line.strip_prefix(EXT_X_MAP_TAG) {
+            let line = line.strip_prefix(':').ok_or(ParseTagError::MissingColon)?;
+
+            let mut uri = None;
+            let mut byte_range = None;
+
+            let mut parser = AttributeListParser::new(line);
+            loop {
+                let name = parser.parse_name()?;
+                parser.parse_equals()?;
+
+                match name {
+                    URI_ATTR => {
+                        let value = parser.parse_quoted_string()?;
+                        if uri.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        uri = Some(value);
+                    }
+                    BYTERANGE_ATTR => {
+                        let (length, offset) = parse_byte_range(parser.parse_quoted_string()?)?;
+
+                        // Unlike EXT-X-BYTERANGE, a missing offset means the start of the resource.
+                        let value = ByteRange {
+                            length,
+                            offset: offset.unwrap_or(0),
+                        };
+
+                        if byte_range.is_some() {
+                            return Err(ParseTagError::DuplicateAttribute { name: name.into() });
+                        }
+                        byte_range = Some(value);
+                    }
+                    _ => {
+                        return Err(ParseTagError::UnknownAttribute { name: name.into() });
+                    }
+                }
+
+                match parser.parse_comma() {
+                    Ok(()) => {}
+                    Err(AttributeListParseError::UnexpectedEnd) => {
+                        break;
+                    }
+                    Err(e) => {
+                        return Err(ParseTagError::from(e));
+                    }
+                }
+            }
+
+            let uri = uri.ok_or(ParseTagError::MissingAttribute { name: URI_ATTR })?;
+
+            Ok(Self::ExtXMap {
+                uri: uri.into(),
+                byte_range,
+            })
         } else {
             Err(ParseTagError::Unknown { line: line.into() })
         }
     }
 }
 
+impl std::fmt::Display for Tag {
+    /// Write this tag as a line, without the trailing newline.
+    ///
+    /// Quoted string values are written as-is,
+    /// so they must not contain double quotes or line breaks.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ExtXTargetDuration { duration } => {
+                write!(f, "#{EXT_X_TARGET_DURATION_TAG}:{}", duration.as_secs())
+            }
+            Self::ExtInf { duration, title } => {
+                write!(
+                    f,
+                    "#{EXT_INF_TAG}:{},{}",
+                    duration.as_secs_f64(),
+                    title.as_deref().unwrap_or("")
+                )
+            }
+            Self::ExtXVersion { version } => write!(f, "#{EXT_X_VERSION_TAG}:{version}"),
+            Self::ExtXMediaSequence { number } => {
+                write!(f, "#{EXT_X_MEDIA_SEQUENCE_TAG}:{number}")
+            }
+            Self::ExtXKey {
+                method,
+                uri,
+                iv,
+                key_format,
+            } => {
+                let mut writer = AttributeListWriter::new(f, EXT_X_KEY_TAG)?;
+                writer.write_enumerated_string(METHOD_ATTR, method)?;
+                if let Some(uri) = uri {
+                    writer.write_quoted_string(URI_ATTR, uri)?;
+                }
+                if let Some(iv) = iv {
+                    writer.write_hexadecimal_sequence(IV_ATTR, iv)?;
+                }
+                if let Some(key_format) = key_format {
+                    writer.write_quoted_string(KEYFORMAT_ATTR, key_format)?;
+                }
+                Ok(())
+            }
+            Self::ExtXMedia {
+                media_type,
+                uri,
+                group_id,
+                language,
+                assoc_language,
+                name,
+                default,
+                autoselect,
+                forced,
+                instream_id,
+                characteristics,
+                channels,
+            } => {
+                let mut writer = AttributeListWriter::new(f, EXT_X_MEDIA_TAG)?;
+                writer.write_enumerated_string(TYPE_ATTR, media_type.as_str())?;
+                if let Some(uri) = uri {
+                    writer.write_quoted_string(URI_ATTR, uri)?;
+                }
+                writer.write_quoted_string(GROUP_ID_ATTR, group_id)?;
+                if let Some(language) = language {
+                    writer.write_quoted_string(LANGUAGE_ATTR, language)?;
+                }
+                if let Some(assoc_language) = assoc_language {
+                    writer.write_quoted_string(ASSOC_LANGUAGE_ATTR, assoc_language)?;
+                }
+                writer.write_quoted_string(NAME_ATTR, name)?;
+                // These default to NO, so they are only written if they are YES.
+                if *default {
+                    writer.write_enumerated_string(DEFAULT_ATTR, YES)?;
+                }
+                if *autoselect {
+                    writer.write_enumerated_string(AUTOSELECT_ATTR, YES)?;
+                }
+                if *forced {
+                    writer.write_enumerated_string(FORCED_ATTR, YES)?;
+                }
+                if let Some(instream_id) = instream_id {
+                    writer.write_quoted_string(INSTREAM_ID_ATTR, instream_id)?;
+                }
+                if let Some(characteristics) = characteristics {
+                    writer.write_quoted_string(CHARACTERISTICS_ATTR, &characteristics.join(","))?;
+                }
+                if let Some(channels) = channels {
+                    writer.write_quoted_string(CHANNELS_ATTR, channels)?;
+                }
+                Ok(())
+            }
+            Self::ExtXStreamInf {
+                bandwidth,
+                average_bandwidth,
+                codecs,
+                resolution,
+                frame_rate,
+                video_range,
+                name,
+                audio,
+                video,
+                subtitles,
+                closed_captions,
+            } => {
+                let mut writer = AttributeListWriter::new(f, EXT_X_STREAM_INF_TAG)?;
+                writer.write_decimal_integer(BANDWIDTH_ATTR, *bandwidth)?;
+                if let Some(average_bandwidth) = average_bandwidth {
+                    writer.write_decimal_integer(AVERAGE_BANDWIDTH_ATTR, *average_bandwidth)?;
+                }
+                if let Some(codecs) = codecs {
+                    writer.write_quoted_string(CODECS_ATTR, &codecs.join(","))?;
+                }
+                if let Some((width, height)) = resolution {
+                    writer.write_decimal_resolution(RESOLUTION_ATTR, *width, *height)?;
+                }
+                if let Some(frame_rate) = frame_rate {
+                    // The spec says this should be rounded to 3 decimal places.
+                    writer.write_attribute(FRAME_RATE_ATTR, format_args!("{frame_rate:.3}"))?;
+                }
+                if let Some(video_range) = video_range {
+                    writer.write_enumerated_string(VIDEO_RANGE_ATTR, video_range.as_str())?;
+                }
+                if let Some(name) = name {
+                    writer.write_quoted_string(NAME_ATTR, name)?;
+                }
+                if let Some(audio) = audio {
+                    writer.write_quoted_string(AUDIO_ATTR, audio)?;
+                }
+                if let Some(video) = video {
+                    writer.write_quoted_string(VIDEO_ATTR, video)?;
+                }
+                if let Some(subtitles) = subtitles {
+                    writer.write_quoted_string(SUBTITLES_ATTR, subtitles)?;
+                }
+                match closed_captions {
+                    Some(ClosedCaptions::GroupId(group_id)) => {
+                        writer.write_quoted_string(CLOSED_CAPTIONS_ATTR, group_id)?;
+                    }
+                    Some(ClosedCaptions::None) => {
+                        writer.write_enumerated_string(CLOSED_CAPTIONS_ATTR, NONE)?;
+                    }
+                    None => {}
+                }
+                Ok(())
+            }
+            Self::ExtXAllowCache {} => write!(f, "#{EXT_X_ALLOW_CACHE_TAG}:YES"),
+            Self::ExtXPlaylistType { playlist_type } => {
+                write!(f, "#{EXT_X_PLAYLIST_TYPE_TAG}:{}", playlist_type.as_str())
+            }
+            Self::ExtXEndList => write!(f, "#{EXT_X_ENDLIST_TAG}"),
+            Self::ExtXIndependentSegments => write!(f, "#{EXT_X_INDEPENDENT_SEGMENTS_TAG}"),
+            Self::ExtXByteRange { length, offset } => {
+                write!(f, "#{EXT_X_BYTERANGE_TAG}:{length}")?;
+                if let Some(offset) = offset {
+                    write!(f, "@{offset}")?;
+                }
+                Ok(())
+            }
+            Self::ExtXMap { uri, byte_range } => {
+                let mut writer = AttributeListWriter::new(f, EXT_X_MAP_TAG)?;
+                writer.write_quoted_string(URI_ATTR, uri)?;
+                if let Some(byte_range) = byte_range {
+                    let value = format!("{}@{}", byte_range.length, byte_range.offset);
+                    writer.write_quoted_string(BYTERANGE_ATTR, &value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A writer for the attribute list of a tag
+struct AttributeListWriter<'a, 'b> {
+    f: &'a mut std::fmt::Formatter<'b>,
+    is_first: bool,
+}
+
+impl<'a, 'b> AttributeListWriter<'a, 'b> {
+    /// Write the tag name, and make a writer for its attributes.
+    fn new(f: &'a mut std::fmt::Formatter<'b>, tag: &str) -> Result<Self, std::fmt::Error> {
+        write!(f, "#{tag}:")?;
+        Ok(Self { f, is_first: true })
+    }
+
+    fn write_attribute(&mut self, name: &str, value: std::fmt::Arguments<'_>) -> std::fmt::Result {
+        if !self.is_first {
+            self.f.write_str(",")?;
+        }
+        self.is_first = false;
+
+        write!(self.f, "{name}={value}")
+    }
+
+    fn write_decimal_integer(&mut self, name: &str, value: u64) -> std::fmt::Result {
+        self.write_attribute(name, format_args!("{value}"))
+    }
+
+    fn write_decimal_resolution(
+        &mut self,
+        name: &str,
+        width: u64,
+        height: u64,
+    ) -> std::fmt::Result {
+        self.write_attribute(name, format_args!("{width}x{height}"))
+    }
+
+    fn write_enumerated_string(&mut self, name: &str, value: &str) -> std::fmt::Result {
+        self.write_attribute(name, format_args!("{value}"))
+    }
+
+    fn write_quoted_string(&mut self, name: &str, value: &str) -> std::fmt::Result {
+        self.write_attribute(name, format_args!("\"{value}\""))
+    }
+
+    fn write_hexadecimal_sequence(&mut self, name: &str, value: &[u8]) -> std::fmt::Result {
+        self.write_attribute(name, format_args!("0x"))?;
+        for byte in value {
+            write!(self.f, "{byte:02X}")?;
+        }
+        Ok(())
+    }
+}
+
 /// An error that may occur while parsing an attribute list
 #[derive(Debug, thiserror::Error)]
 pub enum AttributeListParseError {
@@ -440,6 +1087,10 @@ pub enum AttributeListParseError {
         #[source]
         error: std::num::ParseFloatError,
     },
+
+    /// A hexadecimal sequence had an odd number of digits
+    #[error("hexadecimal sequence has an odd number of digits")]
+    OddHexadecimalSequence,
 }
 
 #[derive(Debug)]
@@ -456,6 +1107,11 @@ impl<'a> AttributeListParser<'a> {
         }
     }
 
+    /// Peek the next char, without consuming it.
+    fn peek_char(&mut self) -> Option<char> {
+        self.iter.peek().map(|(_i, c)| *c)
+    }
+
     /// Parse the name in a name=value pair
     fn parse_name(&mut self) -> Result<&'a str, AttributeListParseError> {
         let (start_i, start_c) = self
@@ -471,12 +1127,12 @@ impl<'a> AttributeListParser<'a> {
         }
         self.iter.next();
 
-        let mut end_i = start_i + 1;
+        let mut end_i = start_i + start_c.len_utf8();
         while let Some((i, c)) = self.iter.peek() {
             if !is_valid_attribute_key_char(*c) {
                 break;
             }
-            end_i = *i + 1;
+            end_i = *i + c.len_utf8();
             self.iter.next();
         }
 
@@ -565,12 +1221,12 @@ impl<'a> AttributeListParser<'a> {
         }
         self.iter.next();
 
-        let mut end_i = start_i + 1;
+        let mut end_i = start_i + start_c.len_utf8();
         while let Some((i, c)) = self.iter.peek() {
             if !c.is_ascii_digit() {
                 break;
             }
-            end_i = *i + 1;
+            end_i = *i + c.len_utf8();
             self.iter.next();
         }
 
@@ -593,12 +1249,12 @@ impl<'a> AttributeListParser<'a> {
         }
         self.iter.next();
 
-        let mut end_i = start_i + 1;
+        let mut end_i = start_i + start_c.len_utf8();
         while let Some((i, c)) = self.iter.peek() {
             if matches!(c, '\r' | '\n' | '"') {
                 break;
             }
-            end_i = *i + 1;
+            end_i = *i + c.len_utf8();
             self.iter.next();
         }
         self.parse_double_quote()?;
@@ -606,6 +1262,62 @@ impl<'a> AttributeListParser<'a> {
         Ok(&self.input[start_i + 1..end_i])
     }
 
+    fn parse_hexadecimal_sequence(&mut self) -> Result<Vec<u8>, AttributeListParseError> {
+        let (_start_i, start_c) = self
+            .iter
+            .peek()
+            .copied()
+            .ok_or(AttributeListParseError::UnexpectedEnd)?;
+        if start_c != '0' {
+            return Err(AttributeListParseError::UnexpectedChar {
+                expected: "'0'",
+                actual: start_c,
+            });
+        }
+        self.iter.next();
+
+        let (_start_i, start_c) = self
+            .iter
+            .peek()
+            .copied()
+            .ok_or(AttributeListParseError::UnexpectedEnd)?;
+        if !matches!(start_c, 'x' | 'X') {
+            return Err(AttributeListParseError::UnexpectedChar {
+                expected: "'x' or 'X'",
+                actual: start_c,
+            });
+        }
+        self.iter.next();
+
+        let mut digits = Vec::with_capacity(32);
+        while let Some((_i, c)) = self.iter.peek() {
+            let digit = match c.to_digit(16) {
+                Some(digit) => digit as u8,
+                None => break,
+            };
+            digits.push(digit);
+            self.iter.next();
+        }
+
+        if digits.is_empty() {
+            return match self.iter.peek() {
+                Some((_i, c)) => Err(AttributeListParseError::UnexpectedChar {
+                    expected: "a hexadecimal digit",
+                    actual: *c,
+                }),
+                None => Err(AttributeListParseError::UnexpectedEnd),
+            };
+        }
+        if digits.len() % 2 != 0 {
+            return Err(AttributeListParseError::OddHexadecimalSequence);
+        }
+
+        Ok(digits
+            .chunks_exact(2)
+            .map(|pair| (pair[0] << 4) | pair[1])
+            .collect())
+    }
+
     fn parse_decimal_resolution(&mut self) -> Result<(u64, u64), AttributeListParseError> {
         let w = self.parse_decimal_integer()?;
         self.parse_x()?;
@@ -627,12 +1339,12 @@ impl<'a> AttributeListParser<'a> {
         }
         self.iter.next();
 
-        let mut end_i = start_i + 1;
+        let mut end_i = start_i + start_c.len_utf8();
         while let Some((i, c)) = self.iter.peek() {
             if !c.is_ascii_digit() && *c != '.' {
                 break;
             }
-            end_i = *i + 1;
+            end_i = *i + c.len_utf8();
             self.iter.next();
         }
 
@@ -655,12 +1367,12 @@ impl<'a> AttributeListParser<'a> {
         }
         self.iter.next();
 
-        let mut end_i = start_i + 1;
+        let mut end_i = start_i + start_c.len_utf8();
         while let Some((i, c)) = self.iter.peek() {
             if !is_valid_enumerated_string_char(*c) {
                 break;
             }
-            end_i = *i + 1;
+            end_i = *i + c.len_utf8();
             self.iter.next();
         }
 
@@ -672,6 +1384,286 @@ fn is_valid_attribute_key_char(c: char) -> bool {
     matches!(c,  'A'..='Z' | '0'..='9' | '-')
 }
 
+/// Parse a byte range, in the format `<n>[@<o>]`.
+fn parse_byte_range(input: &str) -> Result<(u64, Option<u64>), ParseTagError> {
+    let (length, offset) = match input.split_once('@') {
+        Some((length, offset)) => (length, Some(offset)),
+        None => (input, None),
+    };
+
+    let length: u64 = length
+        .parse()
+        .map_err(|error| ParseTagError::ParseInt { error })?;
+    let offset: Option<u64> = offset
+        .map(|offset| offset.parse())
+        .transpose()
+        .map_err(|error| ParseTagError::ParseInt { error })?;
+
+    if offset.unwrap_or(0).checked_add(length).is_none() {
+        return Err(ParseTagError::ByteRangeOverflow);
+    }
+
+    Ok((length, offset))
+}
+
+/// Parse an enumerated string that must be YES or NO.
+fn parse_yes_no(name: &str, value: &str) -> Result<bool, ParseTagError> {
+    match value {
+        YES => Ok(true),
+        NO => Ok(false),
+        _ => Err(ParseTagError::InvalidYesNo {
+            name: name.into(),
+            value: value.into(),
+        }),
+    }
+}
+
 fn is_valid_enumerated_string_char(c: char) -> bool {
     !matches!(c, ',' | '"') && !c.is_whitespace()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn write_round_trip() {
+        let tags = [
+            Tag::ExtXTargetDuration {
+                duration: Duration::from_secs(10),
+            },
+            Tag::ExtInf {
+                duration: Duration::from_secs_f64(9.009),
+                title: None,
+            },
+            Tag::ExtInf {
+                duration: Duration::from_secs(10),
+                title: Some("Episode, Part 1".into()),
+            },
+            Tag::ExtXVersion { version: 3 },
+            Tag::ExtXMediaSequence { number: 2680 },
+            Tag::ExtXKey {
+                method: "NONE".into(),
+                uri: None,
+                iv: None,
+                key_format: None,
+            },
+            Tag::ExtXKey {
+                method: "AES-128".into(),
+                uri: Some("https://example.com/key.bin".into()),
+                iv: Some([
+                    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0xA0, 0xB1, 0xC2,
+                    0xD3, 0xE4, 0xFF,
+                ]),
+                key_format: Some("identity".into()),
+            },
+            Tag::ExtXStreamInf {
+                bandwidth: 1280000,
+                average_bandwidth: None,
+                codecs: None,
+                resolution: None,
+                frame_rate: None,
+                video_range: None,
+                name: None,
+                audio: None,
+                video: None,
+                subtitles: None,
+                closed_captions: None,
+            },
+            Tag::ExtXStreamInf {
+                bandwidth: 1280000,
+                average_bandwidth: Some(1000000),
+                codecs: Some(vec!["avc1.4d401f".into(), "mp4a.40.2".into()]),
+                resolution: Some((1280, 720)),
+                frame_rate: Some(23.976),
+                video_range: Some(VideoRange::Hlg),
+                name: Some("720p".into()),
+                audio: Some("aac".into()),
+                video: Some("video".into()),
+                subtitles: Some("subs".into()),
+                closed_captions: Some(ClosedCaptions::GroupId("cc".into())),
+            },
+            Tag::ExtXStreamInf {
+                bandwidth: 65000,
+                average_bandwidth: None,
+                codecs: None,
+                resolution: None,
+                frame_rate: None,
+                video_range: None,
+                name: None,
+                audio: None,
+                video: None,
+                subtitles: None,
+                closed_captions: Some(ClosedCaptions::None),
+            },
+            Tag::ExtXMedia {
+                media_type: MediaType::Audio,
+                uri: Some("audio/en.m3u8".into()),
+                group_id: "aac".into(),
+                language: Some("en".into()),
+                assoc_language: None,
+                name: "English".into(),
+                default: true,
+                autoselect: true,
+                forced: false,
+                instream_id: None,
+                characteristics: None,
+                channels: Some("2".into()),
+            },
+            Tag::ExtXMedia {
+                media_type: MediaType::Subtitles,
+                uri: Some("subtitles/es.m3u8".into()),
+                group_id: "subs".into(),
+                language: Some("es-419".into()),
+                assoc_language: Some("es".into()),
+                name: "Español".into(),
+                default: false,
+                autoselect: false,
+                forced: true,
+                instream_id: None,
+                characteristics: Some(vec![
+                    "public.accessibility.transcribes-spoken-dialog".into(),
+                    "public.accessibility.describes-music-and-sound".into(),
+                ]),
+                channels: None,
+            },
+            Tag::ExtXMedia {
+                media_type: MediaType::ClosedCaptions,
+                uri: None,
+                group_id: "cc".into(),
+                language: None,
+                assoc_language: None,
+                name: "English".into(),
+                default: false,
+                autoselect: false,
+                forced: false,
+                instream_id: Some("CC1".into()),
+                characteristics: None,
+                channels: None,
+            },
+            Tag::ExtXAllowCache {},
+            Tag::ExtXPlaylistType {
+                playlist_type: PlaylistType::Vod,
+            },
+            Tag::ExtXPlaylistType {
+                playlist_type: PlaylistType::Event,
+            },
+            Tag::ExtXEndList,
+            Tag::ExtXIndependentSegments,
+            Tag::ExtXByteRange {
+                length: 75232,
+                offset: None,
+            },
+            Tag::ExtXByteRange {
+                length: 82112,
+                offset: Some(752321),
+            },
+            Tag::ExtXMap {
+                uri: "init.mp4".into(),
+                byte_range: None,
+            },
+            Tag::ExtXMap {
+                uri: "main.mp4".into(),
+                byte_range: Some(ByteRange {
+                    length: 720,
+                    offset: 0,
+                }),
+            },
+        ];
+
+        for tag in tags {
+            let line = tag.to_string();
+            let new_tag: Tag = line
+                .strip_prefix('#')
+                .expect("missing tag prefix")
+                .parse()
+                .unwrap_or_else(|error| panic!("failed to parse \"{line}\": {error}"));
+            assert!(tag == new_tag, "{line}");
+        }
+    }
+
+    #[test]
+    fn write_tags() {
+        let tag = Tag::ExtXKey {
+            method: "AES-128".into(),
+            uri: Some("key.bin".into()),
+            iv: Some([0xAB; 16]),
+            key_format: None,
+        };
+        assert!(
+            tag.to_string()
+                == "#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\",IV=0xABABABABABABABABABABABABABABABAB"
+        );
+
+        let tag = Tag::ExtInf {
+            duration: Duration::from_millis(3003),
+            title: None,
+        };
+        assert!(tag.to_string() == "#EXTINF:3.003,");
+    }
+
+    #[test]
+    fn parse_byte_range_tags() {
+        let tag: Tag = "EXT-X-MAP:URI=\"main.mp4\",BYTERANGE=\"720\""
+            .parse()
+            .expect("failed to parse");
+        assert!(
+            tag == Tag::ExtXMap {
+                uri: "main.mp4".into(),
+                byte_range: Some(ByteRange {
+                    length: 720,
+                    offset: 0
+                }),
+            }
+        );
+
+        for line in [
+            "EXT-X-BYTERANGE:",
+            "EXT-X-BYTERANGE:100@",
+            "EXT-X-BYTERANGE:@100",
+            "EXT-X-BYTERANGE:-100",
+            "EXT-X-BYTERANGE:18446744073709551615@1",
+            "EXT-X-MAP:BYTERANGE=\"720@0\"",
+            "EXT-X-MAP:URI=\"main.mp4\",BYTERANGE=720",
+        ] {
+            assert!(
+                line.parse::<Tag>().is_err(),
+                "parsed invalid tag \"{line}\""
+            );
+        }
+    }
+
+    #[test]
+    fn parse_media_tags() {
+        // EXT-X-MEDIA is a prefix of EXT-X-MEDIA-SEQUENCE.
+        let tag: Tag = "EXT-X-MEDIA-SEQUENCE:10".parse().expect("failed to parse");
+        assert!(tag == Tag::ExtXMediaSequence { number: 10 });
+
+        for (line, expected) in [
+            ("EXT-X-MEDIA:TYPE=AUDIO,NAME=\"English\"", "GROUP-ID"),
+            ("EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\"", "NAME"),
+            (
+                "EXT-X-MEDIA:TYPE=CLOSED-CAPTIONS,GROUP-ID=\"cc\",NAME=\"English\"",
+                "INSTREAM-ID",
+            ),
+        ] {
+            let error = line.parse::<Tag>().expect_err("parsed invalid tag");
+            assert!(
+                matches!(error, ParseTagError::MissingAttribute { name } if name == expected),
+                "{error:?}"
+            );
+        }
+
+        for line in [
+            "EXT-X-MEDIA:TYPE=TEXT,GROUP-ID=\"subs\",NAME=\"English\"",
+            "EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",DEFAULT=TRUE",
+            "EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",GROUP-ID=\"aac\",NAME=\"English\"",
+            "EXT-X-STREAM-INF:BANDWIDTH=1280000,CLOSED-CAPTIONS=CC1",
+        ] {
+            assert!(
+                line.parse::<Tag>().is_err(),
+                "parsed invalid tag \"{line}\""
+            );
+        }
+    }
+}
@@ -86,7 +86,7 @@ pub struct Anime {
     pub nsfw: bool,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
 #[serde(from = "&str")]
 pub enum AgeRating {
     General,
@@ -109,7 +109,20 @@ impl From<&'_ str> for AgeRating {
     }
 }
 
-#[derive(Debug, serde::Deserialize)]
+impl AgeRating {
+    /// Get the string used by the api for this age rating.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::General => "G",
+            Self::ParentalGuidance => "PG",
+            Self::Restricted => "R",
+            Self::Explicit => "R18",
+            Self::Other(s) => s,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
 #[serde(from = "&str")]
 pub enum Subtype {
     Ona,
@@ -137,7 +150,23 @@ impl<'a> From<&'a str> for Subtype {
     }
 }
 
-#[derive(Debug, serde::Deserialize)]
+impl Subtype {
+    /// Get the string used by the api for this subtype.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Ona => "ONA",
+            Self::Ova => "OVA",
+            Self::Tv => "TV",
+            Self::Movie => "movie",
+            Self::Music => "music",
+            Self::Special => "special",
+
+            Self::Other(s) => s,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
 #[serde(from = "&str")]
 pub enum Status {
     Current,
@@ -162,6 +191,20 @@ impl<'a> From<&'a str> for Status {
     }
 }
 
+impl Status {
+    /// Get the string used by the api for this status.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Current => "current",
+            Self::Finished => "finished",
+            Self::Tba => "tba",
+            Self::Unreleased => "unreleased",
+            Self::Upcoming => "upcoming",
+            Self::Other(s) => s,
+        }
+    }
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct Images {
     /// The tiny image url
@@ -0,0 +1,23 @@
+/// A category, like a genre or theme.
+#[derive(Debug, serde::Deserialize)]
+pub struct Category {
+    /// The title
+    pub title: String,
+
+    /// The description
+    pub description: Option<String>,
+
+    /// The URL slug
+    pub slug: String,
+
+    /// Whether this is nsfw
+    pub nsfw: bool,
+
+    /// The number of child categories
+    #[serde(rename = "childCount")]
+    pub child_count: Option<u64>,
+
+    /// The number of media in this category
+    #[serde(rename = "totalMediaCount")]
+    pub total_media_count: Option<u64>,
+}
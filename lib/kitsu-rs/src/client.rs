@@ -0,0 +1,489 @@
+use crate::Anime;
+use crate::AnimeQuery;
+use crate::Category;
+use crate::Episode;
+use crate::Error;
+use crate::ExternalSite;
+use crate::JsonDocument;
+use crate::Mapping;
+use crate::Page;
+use crate::RateLimiter;
+use crate::ResourceIdentifier;
+use crate::ResourceObject;
+use crate::Season;
+use crate::StreamingLink;
+use reqwest::StatusCode;
+use std::num::NonZeroU64;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_stream::Stream;
+use url::Url;
+
+/// The default base url
+const DEFAULT_BASE_URL: &str = "https://kitsu.io/api/edge/";
+
+/// The maximum page size for episodes
+const EPISODES_PAGE_LIMIT: u32 = 20;
+
+/// A builder for a [`Client`].
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    /// The base url of the api
+    pub base_url: Url,
+
+    /// The http client
+    pub http_client: reqwest::Client,
+
+    /// The maximum number of requests in a burst
+    pub rate_limit_burst: u32,
+
+    /// The number of requests allowed per second, after a burst.
+    pub rate_limit_per_second: f64,
+
+    /// The maximum number of times to retry a failed request
+    pub max_retries: u32,
+
+    /// The delay before the first retry.
+    ///
+    /// This doubles after each retry, and is randomized.
+    pub retry_base_delay: Duration,
+
+    /// The maximum delay before a retry
+    pub retry_max_delay: Duration,
+}
+
+impl ClientBuilder {
+    /// Make a new builder with the default settings.
+    pub fn new() -> Self {
+        Self {
+            base_url: Url::parse(DEFAULT_BASE_URL).unwrap(),
+            http_client: reqwest::Client::new(),
+            rate_limit_burst: 10,
+            rate_limit_per_second: 5.0,
+            max_retries: 3,
+            retry_base_delay: Duration::from_millis(500),
+            retry_max_delay: Duration::from_secs(30),
+        }
+    }
+
+    /// Set the base url of the api.
+    ///
+    /// This defaults to https://kitsu.io/api/edge/.
+    pub fn base_url(&mut self, base_url: Url) -> &mut Self {
+        self.base_url = base_url;
+        self
+    }
+
+    /// Set the http client.
+    pub fn http_client(&mut self, http_client: reqwest::Client) -> &mut Self {
+        self.http_client = http_client;
+        self
+    }
+
+    /// Set the rate limit.
+    ///
+    /// This defaults to bursts of 10 requests, then 5 requests per second.
+    ///
+    /// # Panics
+    /// The client will panic on build if `per_second` is not positive.
+    pub fn rate_limit(&mut self, burst: u32, per_second: f64) -> &mut Self {
+        self.rate_limit_burst = burst;
+        self.rate_limit_per_second = per_second;
+        self
+    }
+
+    /// Set the maximum number of times to retry a failed request.
+    ///
+    /// This defaults to 3.
+    pub fn max_retries(&mut self, max_retries: u32) -> &mut Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the base and maximum delay between retries.
+    ///
+    /// This defaults to 500 milliseconds and 30 seconds.
+    pub fn retry_delay(&mut self, base_delay: Duration, max_delay: Duration) -> &mut Self {
+        self.retry_base_delay = base_delay;
+        self.retry_max_delay = max_delay;
+        self
+    }
+
+    /// Build the client.
+    pub fn build(&self) -> Client {
+        // Urls are joined to the base url, so it must end in a slash to be treated as a directory.
+        let mut base_url = self.base_url.clone();
+        if !base_url.path().ends_with('/') {
+            let path = format!("{}/", base_url.path());
+            base_url.set_path(&path);
+        }
+
+        Client {
+            http_client: self.http_client.clone(),
+            base_url: Arc::new(base_url),
+            rate_limiter: Arc::new(RateLimiter::new(
+                self.rate_limit_burst,
+                self.rate_limit_per_second,
+            )),
+            max_retries: self.max_retries,
+            retry_base_delay: self.retry_base_delay,
+            retry_max_delay: self.retry_max_delay,
+        }
+    }
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The client
+#[derive(Debug, Clone)]
+pub struct Client {
+    http_client: reqwest::Client,
+    base_url: Arc<Url>,
+    rate_limiter: Arc<RateLimiter>,
+    max_retries: u32,
+    retry_base_delay: Duration,
+    retry_max_delay: Duration,
+}
+
+impl Client {
+    /// Make a new client with the default settings
+    pub fn new() -> Self {
+        ClientBuilder::new().build()
+    }
+
+    /// Make a builder for a client
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+
+    /// Get the url of an api path, like `anime/1`.
+    fn get_url(&self, path: &str) -> Result<Url, Error> {
+        Ok(self.base_url.join(path)?)
+    }
+
+    /// Get the url of an api path with query params.
+    fn get_url_with_params<I, K, V>(&self, path: &str, params: I) -> Result<Url, Error>
+    where
+        I: IntoIterator,
+        I::Item: std::borrow::Borrow<(K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut url = self.get_url(path)?;
+        url.query_pairs_mut().extend_pairs(params);
+        Ok(url)
+    }
+
+    /// Get a json document, with rate limiting and retries.
+    ///
+    /// Rate limited requests, server errors and connection errors are retried.
+    async fn get<T>(&self, url: Url) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let mut retries = 0;
+        loop {
+            self.rate_limiter.acquire().await;
+
+            let error = match self.try_get(url.clone()).await {
+                Ok(value) => return Ok(value),
+                Err(error) if error.is_retryable() => error,
+                Err(error) => return Err(error),
+            };
+
+            if retries >= self.max_retries {
+                return Err(error);
+            }
+
+            let delay = match error {
+                Error::RateLimited {
+                    retry_after: Some(retry_after),
+                } => retry_after.min(self.retry_max_delay),
+                _ => get_retry_delay(self.retry_base_delay, self.retry_max_delay, retries),
+            };
+            tokio::time::sleep(delay).await;
+
+            retries += 1;
+        }
+    }
+
+    /// Get a json document, without retrying.
+    async fn try_get<T>(&self, url: Url) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let response = self
+            .http_client
+            .get(url)
+            .header(reqwest::header::ACCEPT, "application/vnd.api+json")
+            .send()
+            .await?;
+
+        let status = response.status();
+        match status {
+            status if status.is_success() => {}
+            StatusCode::NOT_FOUND => return Err(Error::NotFound),
+            StatusCode::TOO_MANY_REQUESTS => {
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.trim().parse().ok())
+                    .map(Duration::from_secs);
+                return Err(Error::RateLimited { retry_after });
+            }
+            status if status.is_server_error() => return Err(Error::Server { status }),
+            status => return Err(Error::InvalidStatus { status }),
+        }
+
+        let body = response.bytes().await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Perform a search for anime
+    pub async fn search(
+        &self,
+        query: &str,
+    ) -> Result<JsonDocument<Vec<ResourceObject<Anime>>>, Error> {
+        let url = self.get_url_with_params("anime", &[("filter[text]", query)])?;
+        self.get(url).await
+    }
+
+    /// Query anime with filters, sorting, sparse fieldsets and includes.
+    pub async fn query_anime(
+        &self,
+        query: &AnimeQuery,
+    ) -> Result<Page<ResourceObject<Anime>>, Error> {
+        let url = self.get_url_with_params("anime", query.to_params())?;
+        self.get(url).await
+    }
+
+    /// Get trending anime
+    pub async fn get_trending_anime(
+        &self,
+    ) -> Result<JsonDocument<Vec<ResourceObject<Anime>>>, Error> {
+        let url = self.get_url("trending/anime")?;
+        self.get(url).await
+    }
+
+    /// Get a page of the anime of a season, most popular first.
+    pub async fn get_season_anime(
+        &self,
+        season_year: u32,
+        season: Season,
+        limit: u32,
+        offset: u64,
+    ) -> Result<Page<ResourceObject<Anime>>, Error> {
+        let mut query = AnimeQuery::new();
+        query
+            .season(season)
+            .season_year(season_year)
+            .sort("userCount", true)
+            .limit(limit)
+            .offset(offset);
+        self.query_anime(&query).await
+    }
+
+    /// Get an anime
+    pub async fn get_anime(
+        &self,
+        id: NonZeroU64,
+    ) -> Result<JsonDocument<ResourceObject<Anime>>, Error> {
+        let url = self.get_url(&format!("anime/{id}"))?;
+        self.get(url).await
+    }
+
+    /// Get a page of a paginated collection, by url.
+    pub async fn get_page<T>(&self, url: &str) -> Result<Page<T>, Error>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let url = Url::parse(url)?;
+        self.get(url).await
+    }
+
+    /// Get the first page of anime epsiodes
+    pub async fn get_anime_episodes(
+        &self,
+        anime_id: NonZeroU64,
+    ) -> Result<JsonDocument<Vec<ResourceObject<Episode>>>, Error> {
+        let url = self.get_url(&format!("anime/{anime_id}/episodes"))?;
+        self.get(url).await
+    }
+
+    /// Get a page of anime episodes
+    pub async fn get_anime_episodes_page(
+        &self,
+        anime_id: NonZeroU64,
+        limit: u32,
+        offset: u64,
+    ) -> Result<Page<ResourceObject<Episode>>, Error> {
+        let url = self.get_url_with_params(
+            &format!("anime/{anime_id}/episodes"),
+            &[
+                ("page[limit]", limit.to_string()),
+                ("page[offset]", offset.to_string()),
+            ],
+        )?;
+        self.get(url).await
+    }
+
+    /// Get all anime episodes, as a stream.
+    ///
+    /// Pages are fetched as needed, following the `next` link of each page.
+    /// An error will be the last item from this stream if it occurs.
+    pub fn get_anime_episodes_stream(
+        &self,
+        anime_id: NonZeroU64,
+    ) -> impl Stream<Item = Result<ResourceObject<Episode>, Error>> {
+        let client = self.clone();
+
+        async_stream::try_stream! {
+            let mut page = client
+                .get_anime_episodes_page(anime_id, EPISODES_PAGE_LIMIT, 0)
+                .await?;
+
+            loop {
+                let is_empty = page.data.is_empty();
+                for episode in page.data {
+                    yield episode;
+                }
+
+                // An empty page with a next link would loop forever.
+                let next = match page.links.next {
+                    Some(next) if !is_empty => next,
+                    _ => break,
+                };
+                page = client.get_page(next.as_str()).await?;
+            }
+        }
+    }
+
+    /// Get the categories of an anime
+    pub async fn get_anime_categories(
+        &self,
+        anime_id: NonZeroU64,
+    ) -> Result<JsonDocument<Vec<ResourceObject<Category>>>, Error> {
+        let url = self.get_url(&format!("anime/{anime_id}/categories"))?;
+        self.get(url).await
+    }
+
+    /// Get the streaming links of an anime
+    pub async fn get_anime_streaming_links(
+        &self,
+        anime_id: NonZeroU64,
+    ) -> Result<JsonDocument<Vec<ResourceObject<StreamingLink>>>, Error> {
+        let url = self.get_url(&format!("anime/{anime_id}/streaming-links"))?;
+        self.get(url).await
+    }
+
+    /// Get the mappings of an anime to external sites
+    pub async fn get_anime_mappings(
+        &self,
+        anime_id: NonZeroU64,
+    ) -> Result<JsonDocument<Vec<ResourceObject<Mapping>>>, Error> {
+        let url = self.get_url(&format!("anime/{anime_id}/mappings"))?;
+        self.get(url).await
+    }
+
+    /// Find the mapping of an id on an external site to a kitsu resource.
+    ///
+    /// The mapped resource is included, with the type `anime` for anime.
+    pub async fn find_mapping(
+        &self,
+        external_site: &ExternalSite,
+        external_id: &str,
+    ) -> Result<Page<ResourceObject<Mapping>>, Error> {
+        let url = self.get_url_with_params(
+            "mappings",
+            &[
+                ("filter[externalSite]", external_site.as_str()),
+                ("filter[externalId]", external_id),
+                ("include", "item"),
+            ],
+        )?;
+        self.get(url).await
+    }
+
+    /// Get the media an episode belongs to.
+    ///
+    /// This is usually an anime.
+    pub async fn get_episode_media(
+        &self,
+        episode_id: NonZeroU64,
+    ) -> Result<JsonDocument<ResourceIdentifier>, Error> {
+        let url = self.get_url(&format!("episodes/{episode_id}/relationships/media"))?;
+        self.get(url).await
+    }
+
+    /// Get an episode
+    pub async fn get_episode(
+        &self,
+        episode_id: NonZeroU64,
+    ) -> Result<JsonDocument<ResourceObject<Episode>>, Error> {
+        let url = self.get_url(&format!("episodes/{episode_id}"))?;
+        self.get(url).await
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Get the delay before a retry.
+///
+/// This is exponential backoff with full jitter,
+/// so retries from many clients are spread out.
+fn get_retry_delay(base_delay: Duration, max_delay: Duration, retries: u32) -> Duration {
+    let max_delay = base_delay
+        .saturating_mul(2_u32.saturating_pow(retries))
+        .min(max_delay);
+    let max_delay_millis = u64::try_from(max_delay.as_millis()).unwrap_or(u64::MAX);
+
+    Duration::from_millis(fastrand::u64(0..=max_delay_millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_is_bounded() {
+        let base_delay = Duration::from_millis(100);
+        let max_delay = Duration::from_secs(1);
+
+        for retries in 0..8 {
+            let expected_max = (base_delay * 2_u32.pow(retries)).min(max_delay);
+            for _ in 0..32 {
+                let delay = get_retry_delay(base_delay, max_delay, retries);
+                assert!(delay <= expected_max, "{delay:?} > {expected_max:?}");
+            }
+        }
+
+        // Overflow saturates at the max delay
+        assert!(get_retry_delay(base_delay, max_delay, u32::MAX) <= max_delay);
+    }
+
+    #[test]
+    fn base_url_is_a_directory() {
+        let client = Client::builder()
+            .base_url(Url::parse("http://127.0.0.1:8080/api/edge").unwrap())
+            .build();
+        let url = client.get_url("anime/1").unwrap();
+        assert_eq!(url.as_str(), "http://127.0.0.1:8080/api/edge/anime/1");
+
+        let url = client
+            .get_url_with_params("anime", &[("filter[text]", "cowboy bebop & co")])
+            .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "http://127.0.0.1:8080/api/edge/anime?filter%5Btext%5D=cowboy+bebop+%26+co"
+        );
+    }
+}
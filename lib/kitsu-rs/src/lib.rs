@@ -1,78 +1,98 @@
 mod anime;
+mod category;
+mod client;
 mod episode;
+mod mapping;
+#[cfg(any(test, feature = "mock-server"))]
+mod mock_server;
+mod page;
+mod query;
+mod rate_limiter;
+mod relationship;
+mod streaming_link;
 
 pub use crate::anime::AgeRating;
 pub use crate::anime::Anime;
 pub use crate::anime::Status;
 pub use crate::anime::Subtype;
+pub use crate::category::Category;
+pub use crate::client::Client;
+pub use crate::client::ClientBuilder;
 pub use crate::episode::Episode;
+pub use crate::mapping::ExternalSite;
+pub use crate::mapping::Mapping;
+#[cfg(any(test, feature = "mock-server"))]
+pub use crate::mock_server::MockServer;
+pub use crate::page::Page;
+pub use crate::page::PageLinks;
+pub use crate::page::PageMeta;
+pub use crate::query::AnimeQuery;
+pub use crate::query::Season;
+pub use crate::query::Sort;
+pub use crate::rate_limiter::RateLimiter;
+pub use crate::relationship::ResourceIdentifier;
+pub use crate::streaming_link::StreamingLink;
 pub use json_api::JsonDocument;
 pub use json_api::ResourceObject;
-use std::num::NonZeroU64;
+use std::time::Duration;
 
 /// The error type
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-    /// A JsonApi error
+    /// A reqwest error
     #[error(transparent)]
-    JsonApi(#[from] json_api::Error),
-}
+    Reqwest(#[from] reqwest::Error),
 
-/// The client
-#[derive(Default, Clone)]
-pub struct Client {
-    /// The inner json api client
-    pub client: json_api::Client,
-}
+    /// A url parse error
+    #[error(transparent)]
+    Url(#[from] url::ParseError),
 
-impl Client {
-    /// Make a new client
-    pub fn new() -> Self {
-        Client {
-            client: json_api::Client::new(),
-        }
-    }
+    /// A json parse error
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
 
-    /// Perform a search for anime
-    pub async fn search(
-        &self,
-        query: &str,
-    ) -> Result<JsonDocument<Vec<ResourceObject<Anime>>>, Error> {
-        let url = format!("https://kitsu.io/api/edge/anime?filter[text]={query}");
-        Ok(self.client.get_json_document(&url).await?)
-    }
+    /// The resource was not found
+    #[error("not found")]
+    NotFound,
 
-    /// Get an anime
-    pub async fn get_anime(
-        &self,
-        id: NonZeroU64,
-    ) -> Result<JsonDocument<ResourceObject<Anime>>, Error> {
-        let url = format!("https://kitsu.io/api/edge/anime/{id}");
-        Ok(self.client.get_json_document(&url).await?)
-    }
+    /// The client was rate limited
+    #[error("rate limited")]
+    RateLimited {
+        /// How long the server asked to wait before retrying
+        retry_after: Option<Duration>,
+    },
 
-    /// Get anime epsiodes
-    pub async fn get_anime_episodes(
-        &self,
-        anime_id: NonZeroU64,
-    ) -> Result<JsonDocument<Vec<ResourceObject<Episode>>>, Error> {
-        let url = format!("https://kitsu.io/api/edge/anime/{anime_id}/episodes");
-        Ok(self.client.get_json_document(&url).await?)
-    }
+    /// The server had an error
+    #[error("server error \"{status}\"")]
+    Server {
+        /// The http status
+        status: reqwest::StatusCode,
+    },
+
+    /// The server returned an unexpected http status
+    #[error("invalid http status \"{status}\"")]
+    InvalidStatus {
+        /// The http status
+        status: reqwest::StatusCode,
+    },
+}
 
-    /// Get an episode
-    pub async fn get_episode(
-        &self,
-        episode_id: NonZeroU64,
-    ) -> Result<JsonDocument<ResourceObject<Episode>>, Error> {
-        let url = format!("https://kitsu.io/api/edge/episodes/{episode_id}");
-        Ok(self.client.get_json_document(&url).await?)
+impl Error {
+    /// Check if a request that failed with this error may succeed if retried.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Reqwest(error) => error.is_timeout() || error.is_connect(),
+            Self::RateLimited { .. } | Self::Server { .. } => true,
+            _ => false,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::num::NonZeroU64;
+    use tokio_stream::StreamExt;
 
     const SEARCHES: &[&str] = &[
         "3-gatsu no Lion 2nd Season",
@@ -115,6 +135,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_search_page() {
+        let search_json = std::fs::read_to_string("test_data/searches/hello.json")
+            .expect("failed to read search");
+        let page = serde_json::from_str::<Page<ResourceObject<Anime>>>(&search_json)
+            .expect("failed to parse");
+
+        assert!(!page.data.is_empty());
+        assert!(page.links.next.is_some());
+        assert_eq!(page.meta.count, Some(380));
+    }
+
     #[test]
     fn parse_anime() {
         for anime in ANIME {
@@ -131,6 +163,235 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_mapping() {
+        let mapping = serde_json::from_str::<Mapping>(
+            r#"{"externalSite":"myanimelist/anime","externalId":"1"}"#,
+        )
+        .expect("failed to parse");
+        assert_eq!(mapping.external_site, ExternalSite::MyAnimeListAnime);
+        assert_eq!(mapping.external_id, "1");
+
+        let mapping = serde_json::from_str::<Mapping>(
+            r#"{"externalSite":"thetvdb/series","externalId":"76885"}"#,
+        )
+        .expect("failed to parse");
+        assert_eq!(mapping.external_site.as_str(), "thetvdb/series");
+    }
+
+    #[tokio::test]
+    async fn mock_search() {
+        let server = MockServer::start()
+            .await
+            .expect("failed to start mock server");
+        let client = server.client();
+
+        let search = client
+            .search("cowboy bebop")
+            .await
+            .expect("failed to search");
+        let anime = search.data.expect("missing data");
+        assert_eq!(anime.len(), 10);
+        assert_eq!(anime[0].id.as_deref(), Some("1"));
+
+        // The query is encoded.
+        assert_eq!(
+            server.requests(),
+            ["/api/edge/anime?filter%5Btext%5D=cowboy+bebop"]
+        );
+
+        let search = client
+            .search("not recorded & not encoded")
+            .await
+            .expect("failed to search");
+        assert!(search.data.expect("missing data").is_empty());
+    }
+
+    #[tokio::test]
+    async fn mock_get_anime() {
+        let server = MockServer::start()
+            .await
+            .expect("failed to start mock server");
+        let client = server.client();
+
+        let anime = client
+            .get_anime(NonZeroU64::new(5).unwrap())
+            .await
+            .expect("failed to get anime")
+            .data
+            .expect("missing data");
+        assert_eq!(anime.id.as_deref(), Some("5"));
+        let attributes = anime.attributes.expect("missing attributes");
+        assert_eq!(attributes.canonical_title, "Beet the Vandel Buster");
+
+        let error = client
+            .get_anime(NonZeroU64::new(1).unwrap())
+            .await
+            .expect_err("missing anime was found");
+        assert!(matches!(error, Error::NotFound), "{error:?}");
+
+        // Not found errors are not retried.
+        assert_eq!(server.request_count("/api/edge/anime/1"), 1);
+    }
+
+    #[tokio::test]
+    async fn mock_episodes() {
+        let server = MockServer::start()
+            .await
+            .expect("failed to start mock server");
+        let client = server.client();
+        let anime_id = NonZeroU64::new(5).unwrap();
+
+        let episodes = client
+            .get_anime_episodes_stream(anime_id)
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .expect("failed to get all anime episodes");
+        let numbers: Vec<u32> = episodes
+            .into_iter()
+            .map(|episode| episode.attributes.expect("missing attributes").number)
+            .collect();
+        assert_eq!(numbers, (1..=25).collect::<Vec<_>>());
+
+        // The next link is rewritten to point at the mock server.
+        assert_eq!(server.request_count("/api/edge/anime/5/episodes"), 2);
+
+        let episode_id = NonZeroU64::new(89).unwrap();
+        let episode = client
+            .get_episode(episode_id)
+            .await
+            .expect("failed to get episode")
+            .data
+            .expect("missing data");
+        assert_eq!(episode.attributes.expect("missing attributes").number, 10);
+    }
+
+    #[tokio::test]
+    async fn mock_relationships() {
+        let server = MockServer::start()
+            .await
+            .expect("failed to start mock server");
+        let client = server.client();
+        let anime_id = NonZeroU64::new(5).unwrap();
+
+        let media = client
+            .get_episode_media(NonZeroU64::new(89).unwrap())
+            .await
+            .expect("failed to get episode media")
+            .data
+            .expect("missing media");
+        assert_eq!(
+            media,
+            ResourceIdentifier {
+                id: "5".into(),
+                kind: "anime".into(),
+            }
+        );
+
+        let categories = client
+            .get_anime_categories(anime_id)
+            .await
+            .expect("failed to get anime categories")
+            .data
+            .expect("missing data");
+        let slugs: Vec<String> = categories
+            .into_iter()
+            .map(|category| category.attributes.expect("missing attributes").slug)
+            .collect();
+        assert_eq!(slugs, ["action", "adventure", "fantasy"]);
+
+        let streaming_links = client
+            .get_anime_streaming_links(anime_id)
+            .await
+            .expect("failed to get anime streaming links")
+            .data
+            .expect("missing data");
+        assert_eq!(streaming_links.len(), 1);
+
+        let mappings = client
+            .get_anime_mappings(anime_id)
+            .await
+            .expect("failed to get anime mappings")
+            .data
+            .expect("missing data");
+        let sites: Vec<ExternalSite> = mappings
+            .into_iter()
+            .map(|mapping| {
+                mapping
+                    .attributes
+                    .expect("missing attributes")
+                    .external_site
+            })
+            .collect();
+        assert_eq!(
+            sites,
+            [
+                ExternalSite::MyAnimeListAnime,
+                ExternalSite::AniDb,
+                ExternalSite::AniListAnime
+            ]
+        );
+
+        let mapping = client
+            .find_mapping(&ExternalSite::MyAnimeListAnime, "5")
+            .await
+            .expect("failed to find mapping");
+        let anime = mapping
+            .get_included::<Anime>("anime")
+            .expect("failed to parse included anime");
+        assert_eq!(anime.len(), 1);
+        assert_eq!(anime[0].id.as_deref(), Some("5"));
+
+        let mapping = client
+            .find_mapping(&ExternalSite::AniListAnime, "5")
+            .await
+            .expect("failed to find mapping");
+        assert!(mapping.data.is_empty());
+    }
+
+    #[tokio::test]
+    async fn mock_retries() {
+        let server = MockServer::start()
+            .await
+            .expect("failed to start mock server");
+        let client = server.client();
+        let anime_id = NonZeroU64::new(5).unwrap();
+
+        server.fail_next(reqwest::StatusCode::TOO_MANY_REQUESTS, 1);
+        server.fail_next(reqwest::StatusCode::BAD_GATEWAY, 1);
+        client
+            .get_anime(anime_id)
+            .await
+            .expect("failed to get anime");
+        assert_eq!(server.request_count("/api/edge/anime/5"), 3);
+
+        // The default client gives up after 3 retries.
+        server.fail_next(reqwest::StatusCode::SERVICE_UNAVAILABLE, 4);
+        let error = client
+            .get_anime(anime_id)
+            .await
+            .expect_err("request did not fail");
+        assert!(
+            matches!(
+                error,
+                Error::Server {
+                    status: reqwest::StatusCode::SERVICE_UNAVAILABLE
+                }
+            ),
+            "{error:?}"
+        );
+        assert_eq!(server.request_count("/api/edge/anime/5"), 7);
+
+        // Client errors are not retried.
+        server.fail_next(reqwest::StatusCode::BAD_REQUEST, 1);
+        let error = client
+            .get_anime(anime_id)
+            .await
+            .expect_err("request did not fail");
+        assert!(matches!(error, Error::InvalidStatus { .. }), "{error:?}");
+        assert_eq!(server.request_count("/api/edge/anime/5"), 8);
+    }
+
     #[tokio::test]
     async fn it_works() {
         let client = Client::new();
@@ -148,6 +409,13 @@ mod tests {
             .expect("failed to get anime episodes");
         dbg!(&episodes);
 
+        let episodes = client
+            .get_anime_episodes_stream(anime_id)
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .expect("failed to get all anime episodes");
+        assert!(episodes.len() > 20);
+
         // First id determined experimentally
         let episode_id = NonZeroU64::new(27).unwrap();
         let episode = client
@@ -156,5 +424,69 @@ mod tests {
             .expect("failed to get episode");
 
         dbg!(&episode);
+
+        let media = client
+            .get_episode_media(episode_id)
+            .await
+            .expect("failed to get episode media");
+        let media = media.data.expect("missing media");
+        assert_eq!(media.kind, "anime");
+
+        let categories = client
+            .get_anime_categories(anime_id)
+            .await
+            .expect("failed to get anime categories")
+            .data
+            .expect("missing data");
+        assert!(!categories.is_empty());
+        assert!(categories
+            .iter()
+            .all(|category| category.kind == "categories"));
+
+        let streaming_links = client
+            .get_anime_streaming_links(anime_id)
+            .await
+            .expect("failed to get anime streaming links")
+            .data
+            .expect("missing data");
+        assert!(!streaming_links.is_empty());
+        assert!(streaming_links
+            .iter()
+            .all(|streaming_link| streaming_link.kind == "streamingLinks"));
+
+        let mappings = client
+            .get_anime_mappings(anime_id)
+            .await
+            .expect("failed to get anime mappings")
+            .data
+            .expect("missing data");
+        assert!(!mappings.is_empty());
+        assert!(mappings.iter().all(|mapping| mapping.kind == "mappings"));
+
+        // Cowboy Bebop is 1 on both kitsu and MyAnimeList.
+        let mapping = client
+            .find_mapping(&ExternalSite::MyAnimeListAnime, "1")
+            .await
+            .expect("failed to find mapping");
+        let anime = mapping
+            .get_included::<Anime>("anime")
+            .expect("failed to parse included anime");
+        assert_eq!(anime.len(), 1);
+        assert_eq!(anime[0].id.as_deref(), Some("1"));
+
+        let trending = client
+            .get_trending_anime()
+            .await
+            .expect("failed to get trending anime")
+            .data
+            .expect("missing data");
+        assert!(!trending.is_empty());
+        assert!(trending.iter().all(|anime| anime.kind == "anime"));
+
+        let season = client
+            .get_season_anime(1998, Season::Spring, 20, 0)
+            .await
+            .expect("failed to get season anime");
+        assert!(!season.data.is_empty());
     }
 }
@@ -0,0 +1,45 @@
+/// A mapping of a kitsu resource to a resource on an external site.
+#[derive(Debug, serde::Deserialize)]
+pub struct Mapping {
+    /// The external site
+    #[serde(rename = "externalSite")]
+    pub external_site: ExternalSite,
+
+    /// The id on the external site
+    #[serde(rename = "externalId")]
+    pub external_id: String,
+}
+
+/// An external site that kitsu has mappings for
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(from = "&str")]
+pub enum ExternalSite {
+    MyAnimeListAnime,
+    AniListAnime,
+    AniDb,
+
+    Other(String),
+}
+
+impl From<&'_ str> for ExternalSite {
+    fn from(s: &str) -> Self {
+        match s {
+            "myanimelist/anime" => Self::MyAnimeListAnime,
+            "anilist/anime" => Self::AniListAnime,
+            "anidb" => Self::AniDb,
+            _ => Self::Other(s.into()),
+        }
+    }
+}
+
+impl ExternalSite {
+    /// Get the string used by the api for this site.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::MyAnimeListAnime => "myanimelist/anime",
+            Self::AniListAnime => "anilist/anime",
+            Self::AniDb => "anidb",
+            Self::Other(s) => s,
+        }
+    }
+}
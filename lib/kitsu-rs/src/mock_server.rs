@@ -0,0 +1,294 @@
+use crate::Client;
+use axum::extract::State;
+use axum::http::header;
+use axum::http::StatusCode;
+use axum::http::Uri;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use std::collections::VecDeque;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+
+/// The base url that recorded responses link to.
+///
+/// This is replaced with the base url of the mock server in responses.
+const RECORDED_BASE_URL: &str = "https://kitsu.io/api/edge/";
+
+/// The json api content type
+const CONTENT_TYPE: &str = "application/vnd.api+json";
+
+/// Recorded searches, by search text
+const SEARCHES: &[(&str, &str)] = &[
+    (
+        "3-gatsu no Lion 2nd Season",
+        include_str!("../test_data/searches/3-gatsu no Lion 2nd Season.json"),
+    ),
+    (
+        "cowboy bebop",
+        include_str!("../test_data/searches/cowboy bebop.json"),
+    ),
+    (
+        "5 Centimeter per Second",
+        include_str!("../test_data/searches/5 Centimeter per Second.json"),
+    ),
+    ("food", include_str!("../test_data/searches/food.json")),
+    ("high", include_str!("../test_data/searches/high.json")),
+    ("hello", include_str!("../test_data/searches/hello.json")),
+];
+
+/// Recorded anime, by id
+const ANIME: &[(&str, &str)] = &[
+    ("5", include_str!("../test_data/anime/5.json")),
+    ("13401", include_str!("../test_data/anime/13401.json")),
+    ("46174", include_str!("../test_data/anime/46174.json")),
+];
+
+/// Recorded episodes, by id
+const EPISODES: &[(&str, &str)] = &[
+    ("89", include_str!("../test_data/episodes/89.json")),
+    ("99605", include_str!("../test_data/episodes/99605.json")),
+];
+
+/// Responses for relationships of anime 5.
+///
+/// The episode collection is trimmed to 25 episodes, so it spans 2 pages.
+const ANIME_5_EPISODES: &[(&str, &str)] = &[
+    (
+        "0",
+        include_str!("../test_data/mock/anime-5-episodes-0.json"),
+    ),
+    (
+        "20",
+        include_str!("../test_data/mock/anime-5-episodes-20.json"),
+    ),
+];
+const ANIME_5_CATEGORIES: &str = include_str!("../test_data/mock/anime-5-categories.json");
+const ANIME_5_STREAMING_LINKS: &str =
+    include_str!("../test_data/mock/anime-5-streaming-links.json");
+const ANIME_5_MAPPINGS: &str = include_str!("../test_data/mock/anime-5-mappings.json");
+const EPISODE_89_MEDIA: &str = include_str!("../test_data/mock/episode-89-media.json");
+const MAPPINGS_MYANIMELIST_ANIME_5: &str =
+    include_str!("../test_data/mock/mappings-myanimelist-anime-5.json");
+
+/// The response for a collection with no matches
+const EMPTY_COLLECTION: &str = r#"{"data":[],"meta":{"count":0},"links":{}}"#;
+
+/// A mock kitsu api server, for testing without network access.
+///
+/// It listens on localhost and serves recorded responses for:
+/// * Searches in `test_data/searches`
+/// * Anime in `test_data/anime`
+/// * Episodes in `test_data/episodes`
+/// * The episodes, categories, streaming links and mappings of anime 5
+/// * The media of episode 89
+/// * The MyAnimeList mapping `5`, with its anime included
+///
+/// Unknown resources are a 404.
+/// Searches and mapping lookups with no recorded response are empty.
+/// The server stops when this is dropped.
+#[derive(Debug)]
+pub struct MockServer {
+    base_url: Url,
+    state: Arc<MockServerState>,
+    handle: tokio::task::JoinHandle<()>,
+}
+
+#[derive(Debug)]
+struct MockServerState {
+    base_url: String,
+
+    /// The path and query of each request, in order.
+    requests: std::sync::Mutex<Vec<String>>,
+
+    /// Statuses to respond with before serving recorded responses.
+    failures: std::sync::Mutex<VecDeque<StatusCode>>,
+}
+
+impl MockServer {
+    /// Start a mock server on a random port.
+    pub async fn start() -> std::io::Result<Self> {
+        let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let address = listener.local_addr()?;
+        let base_url = Url::parse(&format!("http://{address}/api/edge/"))
+            .expect("mock server base url should be valid");
+
+        let state = Arc::new(MockServerState {
+            base_url: base_url.as_str().into(),
+            requests: std::sync::Mutex::new(Vec::new()),
+            failures: std::sync::Mutex::new(VecDeque::new()),
+        });
+        let app = axum::Router::new()
+            .fallback(handle_request)
+            .with_state(state.clone());
+        let handle = tokio::spawn(async move {
+            // This only fails if the listener fails, which ends the server anyways.
+            let _ = axum::serve(listener, app).await.is_ok();
+        });
+
+        Ok(Self {
+            base_url,
+            state,
+            handle,
+        })
+    }
+
+    /// Get the base url of the api.
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
+    /// Make a client for this server.
+    ///
+    /// The client is not rate limited, and retries quickly.
+    pub fn client(&self) -> Client {
+        Client::builder()
+            .base_url(self.base_url.clone())
+            .rate_limit(u32::MAX, f64::MAX)
+            .retry_delay(Duration::from_millis(1), Duration::from_millis(10))
+            .build()
+    }
+
+    /// Get the path and query of each request made so far, in order.
+    ///
+    /// Query strings are as sent, so they are percent-encoded.
+    pub fn requests(&self) -> Vec<String> {
+        self.state
+            .requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Get the number of requests made so far for the given path, like `/api/edge/anime/5`.
+    pub fn request_count(&self, path: &str) -> usize {
+        self.requests()
+            .iter()
+            .filter(|request| request.split('?').next() == Some(path))
+            .count()
+    }
+
+    /// Respond to the next `count` requests with the given status.
+    ///
+    /// Rate limited responses ask the client to retry immediately.
+    pub fn fail_next(&self, status: StatusCode, count: usize) {
+        let mut failures = self
+            .state
+            .failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        failures.extend(std::iter::repeat_n(status, count));
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn handle_request(State(state): State<Arc<MockServerState>>, uri: Uri) -> Response {
+    let path_and_query = uri
+        .path_and_query()
+        .map(|path_and_query| path_and_query.as_str())
+        .unwrap_or("/");
+    state
+        .requests
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(path_and_query.into());
+
+    let failure = state
+        .failures
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .pop_front();
+    if let Some(status) = failure {
+        let mut response = error_response(status);
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, header::HeaderValue::from_static("0"));
+        }
+        return response;
+    }
+
+    let query: Vec<(String, String)> =
+        url::form_urlencoded::parse(uri.query().unwrap_or("").as_bytes())
+            .into_owned()
+            .collect();
+    let get_param = |name: &str| {
+        query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let path = match uri.path().strip_prefix("/api/edge/") {
+        Some(path) => path,
+        None => return error_response(StatusCode::NOT_FOUND),
+    };
+    let segments: Vec<&str> = path.split('/').collect();
+    let body = match segments.as_slice() {
+        ["anime"] => Some(
+            get_param("filter[text]")
+                .and_then(|text| find(SEARCHES, text))
+                .unwrap_or(EMPTY_COLLECTION),
+        ),
+        ["anime", id] => find(ANIME, id),
+        ["anime", "5", "episodes"] => Some(
+            find(ANIME_5_EPISODES, get_param("page[offset]").unwrap_or("0"))
+                .unwrap_or(EMPTY_COLLECTION),
+        ),
+        ["anime", "5", "categories"] => Some(ANIME_5_CATEGORIES),
+        ["anime", "5", "streaming-links"] => Some(ANIME_5_STREAMING_LINKS),
+        ["anime", "5", "mappings"] => Some(ANIME_5_MAPPINGS),
+        ["episodes", id] => find(EPISODES, id),
+        ["episodes", "89", "relationships", "media"] => Some(EPISODE_89_MEDIA),
+        ["mappings"] => {
+            let is_recorded = get_param("filter[externalSite]") == Some("myanimelist/anime")
+                && get_param("filter[externalId]") == Some("5");
+            Some(if is_recorded {
+                MAPPINGS_MYANIMELIST_ANIME_5
+            } else {
+                EMPTY_COLLECTION
+            })
+        }
+        _ => None,
+    };
+
+    match body {
+        Some(body) => (
+            [(header::CONTENT_TYPE, CONTENT_TYPE)],
+            body.replace(RECORDED_BASE_URL, &state.base_url),
+        )
+            .into_response(),
+        None => error_response(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Find a recorded response by key.
+fn find(responses: &[(&str, &'static str)], key: &str) -> Option<&'static str> {
+    responses
+        .iter()
+        .find(|(response_key, _)| *response_key == key)
+        .map(|(_, response)| *response)
+}
+
+/// Make a json api error response.
+fn error_response(status: StatusCode) -> Response {
+    let body = serde_json::json!({
+        "errors": [{
+            "title": status.canonical_reason().unwrap_or("Error"),
+            "status": status.as_str(),
+        }]
+    });
+
+    (
+        status,
+        [(header::CONTENT_TYPE, CONTENT_TYPE)],
+        body.to_string(),
+    )
+        .into_response()
+}
@@ -0,0 +1,61 @@
+use json_api::ResourceObject;
+use url::Url;
+
+/// A page of a paginated collection.
+///
+/// `json_api::JsonDocument` does not expose pagination links,
+/// so collections that need to be paginated are fetched as this instead.
+#[derive(Debug, serde::Deserialize)]
+pub struct Page<T> {
+    /// The items in this page
+    pub data: Vec<T>,
+
+    /// Pagination links
+    #[serde(default)]
+    pub links: PageLinks,
+
+    /// Collection metadata
+    #[serde(default)]
+    pub meta: PageMeta,
+
+    /// Included resources, from an `include` query parameter.
+    #[serde(default)]
+    pub included: Vec<serde_json::Value>,
+}
+
+impl<T> Page<T> {
+    /// Get the included resources of the given type, like `categories`.
+    pub fn get_included<A>(&self, kind: &str) -> Result<Vec<ResourceObject<A>>, serde_json::Error>
+    where
+        A: serde::de::DeserializeOwned,
+    {
+        self.included
+            .iter()
+            .filter(|resource| resource.get("type").and_then(|kind| kind.as_str()) == Some(kind))
+            // Deserializing from a borrowed value allows borrowed strings, unlike `serde_json::from_value`.
+            .map(serde::Deserialize::deserialize)
+            .collect()
+    }
+}
+
+/// Pagination links
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct PageLinks {
+    /// The url of the first page
+    pub first: Option<Url>,
+
+    /// The url of the next page.
+    ///
+    /// This is missing on the last page.
+    pub next: Option<Url>,
+
+    /// The url of the last page
+    pub last: Option<Url>,
+}
+
+/// Collection metadata
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct PageMeta {
+    /// The total number of items in the collection
+    pub count: Option<u64>,
+}
@@ -0,0 +1,318 @@
+use crate::Status;
+use crate::Subtype;
+
+/// An anime season
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Season {
+    Winter,
+    Spring,
+    Summer,
+    Fall,
+}
+
+impl Season {
+    /// Get the string used by the api for this season.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Winter => "winter",
+            Self::Spring => "spring",
+            Self::Summer => "summer",
+            Self::Fall => "fall",
+        }
+    }
+
+    /// Get the season of a month, from 1 to 12.
+    ///
+    /// Returns `None` if the month is invalid.
+    pub fn from_month(month: u32) -> Option<Self> {
+        match month {
+            12 | 1 | 2 => Some(Self::Winter),
+            3..=5 => Some(Self::Spring),
+            6..=8 => Some(Self::Summer),
+            9..=11 => Some(Self::Fall),
+            _ => None,
+        }
+    }
+}
+
+/// A sort order for a field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sort {
+    /// The field name, like `userCount`.
+    pub field: String,
+
+    /// Whether to sort in descending order
+    pub descending: bool,
+}
+
+/// A query for anime.
+///
+/// Unset fields are not sent, and use the api defaults.
+#[derive(Debug, Default, Clone)]
+pub struct AnimeQuery {
+    /// Text to search for
+    pub text: Option<String>,
+
+    /// Seasons to filter by
+    pub seasons: Vec<Season>,
+
+    /// The year of the season to filter by
+    pub season_year: Option<u32>,
+
+    /// Subtypes to filter by
+    pub subtypes: Vec<Subtype>,
+
+    /// Statuses to filter by
+    pub statuses: Vec<Status>,
+
+    /// Category slugs to filter by
+    pub categories: Vec<String>,
+
+    /// Sort orders, from highest to lowest priority.
+    pub sort: Vec<Sort>,
+
+    /// Sparse fieldsets, as a resource type and the fields to include for it.
+    pub fields: Vec<(String, Vec<String>)>,
+
+    /// Relationship paths to include, like `categories` or `mappings.item`.
+    pub include: Vec<String>,
+
+    /// The page size
+    pub limit: Option<u32>,
+
+    /// The page offset
+    pub offset: Option<u64>,
+}
+
+impl AnimeQuery {
+    /// Make a new, empty query.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the text to search for.
+    pub fn text<S>(&mut self, text: S) -> &mut Self
+    where
+        S: Into<String>,
+    {
+        self.text = Some(text.into());
+        self
+    }
+
+    /// Add a season to filter by.
+    pub fn season(&mut self, season: Season) -> &mut Self {
+        self.seasons.push(season);
+        self
+    }
+
+    /// Set the year of the season to filter by.
+    pub fn season_year(&mut self, season_year: u32) -> &mut Self {
+        self.season_year = Some(season_year);
+        self
+    }
+
+    /// Add a subtype to filter by.
+    pub fn subtype(&mut self, subtype: Subtype) -> &mut Self {
+        self.subtypes.push(subtype);
+        self
+    }
+
+    /// Add a status to filter by.
+    pub fn status(&mut self, status: Status) -> &mut Self {
+        self.statuses.push(status);
+        self
+    }
+
+    /// Add a category slug to filter by.
+    pub fn category<S>(&mut self, category: S) -> &mut Self
+    where
+        S: Into<String>,
+    {
+        self.categories.push(category.into());
+        self
+    }
+
+    /// Add a sort order.
+    pub fn sort<S>(&mut self, field: S, descending: bool) -> &mut Self
+    where
+        S: Into<String>,
+    {
+        self.sort.push(Sort {
+            field: field.into(),
+            descending,
+        });
+        self
+    }
+
+    /// Only include the given fields for a resource type.
+    pub fn fields<S, I, F>(&mut self, kind: S, fields: I) -> &mut Self
+    where
+        S: Into<String>,
+        I: IntoIterator<Item = F>,
+        F: Into<String>,
+    {
+        self.fields
+            .push((kind.into(), fields.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    /// Add a relationship path to include.
+    pub fn include<S>(&mut self, path: S) -> &mut Self
+    where
+        S: Into<String>,
+    {
+        self.include.push(path.into());
+        self
+    }
+
+    /// Set the page size.
+    pub fn limit(&mut self, limit: u32) -> &mut Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Set the page offset.
+    pub fn offset(&mut self, offset: u64) -> &mut Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Get the query string parameters of this query.
+    pub fn to_params(&self) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+
+        if let Some(text) = self.text.as_ref() {
+            params.push(("filter[text]".into(), text.clone()));
+        }
+        if !self.seasons.is_empty() {
+            params.push((
+                "filter[season]".into(),
+                join(self.seasons.iter().map(|season| season.as_str())),
+            ));
+        }
+        if let Some(season_year) = self.season_year {
+            params.push(("filter[seasonYear]".into(), season_year.to_string()));
+        }
+        if !self.subtypes.is_empty() {
+            params.push((
+                "filter[subtype]".into(),
+                join(self.subtypes.iter().map(|subtype| subtype.as_str())),
+            ));
+        }
+        if !self.statuses.is_empty() {
+            params.push((
+                "filter[status]".into(),
+                join(self.statuses.iter().map(|status| status.as_str())),
+            ));
+        }
+        if !self.categories.is_empty() {
+            params.push((
+                "filter[categories]".into(),
+                join(self.categories.iter().map(String::as_str)),
+            ));
+        }
+        if !self.sort.is_empty() {
+            let sort = self
+                .sort
+                .iter()
+                .map(|sort| {
+                    if sort.descending {
+                        format!("-{}", sort.field)
+                    } else {
+                        sort.field.clone()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            params.push(("sort".into(), sort));
+        }
+        for (kind, fields) in self.fields.iter() {
+            params.push((
+                format!("fields[{kind}]"),
+                join(fields.iter().map(String::as_str)),
+            ));
+        }
+        if !self.include.is_empty() {
+            params.push((
+                "include".into(),
+                join(self.include.iter().map(String::as_str)),
+            ));
+        }
+        if let Some(limit) = self.limit {
+            params.push(("page[limit]".into(), limit.to_string()));
+        }
+        if let Some(offset) = self.offset {
+            params.push(("page[offset]".into(), offset.to_string()));
+        }
+
+        params
+    }
+}
+
+/// Join values with commas.
+fn join<'a, I>(values: I) -> String
+where
+    I: Iterator<Item = &'a str>,
+{
+    values.collect::<Vec<_>>().join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anime_query_params() {
+        let mut query = AnimeQuery::new();
+        query
+            .text("cowboy bebop")
+            .season(Season::Spring)
+            .season_year(1998)
+            .subtype(Subtype::Tv)
+            .subtype(Subtype::Movie)
+            .status(Status::Finished)
+            .category("space")
+            .sort("userCount", true)
+            .sort("canonicalTitle", false)
+            .fields("anime", ["slug", "canonicalTitle"])
+            .include("categories")
+            .include("mappings")
+            .limit(20)
+            .offset(40);
+
+        let params = query.to_params();
+        let expected = [
+            ("filter[text]", "cowboy bebop"),
+            ("filter[season]", "spring"),
+            ("filter[seasonYear]", "1998"),
+            ("filter[subtype]", "TV,movie"),
+            ("filter[status]", "finished"),
+            ("filter[categories]", "space"),
+            ("sort", "-userCount,canonicalTitle"),
+            ("fields[anime]", "slug,canonicalTitle"),
+            ("include", "categories,mappings"),
+            ("page[limit]", "20"),
+            ("page[offset]", "40"),
+        ];
+        assert_eq!(params.len(), expected.len());
+        for ((key, value), (expected_key, expected_value)) in params.iter().zip(expected) {
+            assert_eq!(key, expected_key);
+            assert_eq!(value, expected_value);
+        }
+    }
+
+    #[test]
+    fn empty_anime_query_params() {
+        assert!(AnimeQuery::new().to_params().is_empty());
+    }
+
+    #[test]
+    fn season_from_month() {
+        assert_eq!(Season::from_month(1), Some(Season::Winter));
+        assert_eq!(Season::from_month(4), Some(Season::Spring));
+        assert_eq!(Season::from_month(8), Some(Season::Summer));
+        assert_eq!(Season::from_month(11), Some(Season::Fall));
+        assert_eq!(Season::from_month(12), Some(Season::Winter));
+        assert_eq!(Season::from_month(13), None);
+    }
+}
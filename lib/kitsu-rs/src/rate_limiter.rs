@@ -0,0 +1,93 @@
+use std::time::Duration;
+use std::time::Instant;
+
+/// A token bucket rate limiter.
+///
+/// The bucket starts full.
+/// Each request takes a token, and tokens are refilled at a constant rate.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// The maximum number of tokens
+    capacity: f64,
+
+    /// The number of tokens refilled per second
+    refill_rate: f64,
+
+    state: std::sync::Mutex<RateLimiterState>,
+}
+
+#[derive(Debug)]
+struct RateLimiterState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// Make a new rate limiter.
+    ///
+    /// `capacity` is the maximum burst size,
+    /// and `refill_rate` is the number of requests allowed per second after a burst.
+    ///
+    /// # Panics
+    /// Panics if `refill_rate` is not positive.
+    pub fn new(capacity: u32, refill_rate: f64) -> Self {
+        assert!(refill_rate > 0.0, "refill rate must be positive");
+
+        let capacity = f64::from(capacity.max(1));
+        Self {
+            capacity,
+            refill_rate,
+            state: std::sync::Mutex::new(RateLimiterState {
+                tokens: capacity,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take a token, waiting until one is available.
+    pub async fn acquire(&self) {
+        while let Err(wait) = self.try_acquire() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Try to take a token.
+    ///
+    /// On failure, returns how long to wait until a token is available.
+    fn try_acquire(&self) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+        state.tokens = (state.tokens + (elapsed * self.refill_rate)).min(self.capacity);
+        state.last_refill = now;
+
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            return Ok(());
+        }
+
+        Err(Duration::from_secs_f64(
+            (1.0 - state.tokens) / self.refill_rate,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn burst_then_wait() {
+        let rate_limiter = RateLimiter::new(2, 1.0);
+
+        assert!(rate_limiter.try_acquire().is_ok());
+        assert!(rate_limiter.try_acquire().is_ok());
+
+        let wait = rate_limiter
+            .try_acquire()
+            .expect_err("bucket should be empty");
+        assert!(wait > Duration::from_millis(900));
+        assert!(wait <= Duration::from_secs(1));
+    }
+}
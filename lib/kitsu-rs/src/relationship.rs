@@ -0,0 +1,10 @@
+/// An identifier of a resource, as returned for relationships.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct ResourceIdentifier {
+    /// The id
+    pub id: String,
+
+    /// The resource type, like `anime`.
+    #[serde(rename = "type")]
+    pub kind: String,
+}
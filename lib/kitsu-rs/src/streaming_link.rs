@@ -0,0 +1,16 @@
+/// A link to a site where an anime can be streamed.
+#[derive(Debug, serde::Deserialize)]
+pub struct StreamingLink {
+    /// The url.
+    ///
+    /// This is not always a valid url, so it is kept as a string.
+    pub url: String,
+
+    /// Subtitle languages
+    #[serde(default)]
+    pub subs: Vec<String>,
+
+    /// Dub languages
+    #[serde(default)]
+    pub dubs: Vec<String>,
+}
@@ -1,7 +1,6 @@
 use anyhow::bail;
 use anyhow::ensure;
 use anyhow::Context;
-use std::collections::HashMap;
 use std::path::Path;
 use std::path::PathBuf;
 use std::time::Duration;
@@ -20,6 +19,12 @@ pub struct Options {
         default = "Default::default()"
     )]
     pub source: Source,
+
+    #[argh(
+        option,
+        description = "the maximum vertical resolution to download, like 720"
+    )]
+    pub max_resolution: Option<u32>,
 }
 
 /// The source
@@ -71,9 +76,12 @@ pub async fn exec(client: vidstreaming::Client, options: Options) -> anyhow::Res
         .get_video_player_video_data(&video_player)
         .await
         .context("failed to get video player video data")?;
+    let preference = vidstreaming::QualityPreference {
+        max_resolution: options.max_resolution,
+    };
     let best_source = match options.source {
         Source::Main => video_player_video_data
-            .get_best_source()
+            .get_best_source(&preference)
             .context("failed to select source")?,
         Source::Backup => {
             ensure!(video_player_video_data.source_bk.len() == 1);
@@ -89,87 +97,28 @@ pub async fn exec(client: vidstreaming::Client, options: Options) -> anyhow::Res
         "the selected source is not a HLS stream"
     );
 
-    download_hls_to_mp4(&client.client, best_source.file.as_str(), &out_path).await?;
+    let quality = bewu_util::QualityPreference {
+        max_resolution: options.max_resolution,
+        ..Default::default()
+    };
+    download_hls_to_mp4(
+        &client.client,
+        best_source.file.as_str(),
+        &out_path,
+        quality,
+    )
+    .await?;
 
     Ok(())
 }
 
-// TODO: Consider making a util func
-#[allow(dead_code)]
-pub async fn probe_url(url: &str) -> anyhow::Result<ProbeResult> {
-    let output = tokio::process::Command::new("ffprobe")
-        .args(["-v", "error"])
-        .arg("-hide_banner")
-        .arg(url)
-        .args(["-of", "default=noprint_wrappers=0"])
-        .args(["-print_format", "json"])
-        .arg("-show_format")
-        // .args(["-show_entries", "stream"])
-        // .arg("-show_programs")
-        .output()
-        .await
-        .context("failed to spawn \"ffprobe\"")?;
-
-    ensure!(
-        output.status.success(),
-        "ffprobe exited with \"{}\"",
-        output.status
-    );
-
-    let stdout = std::str::from_utf8(&output.stdout)?;
-
-    Ok(serde_json::from_str(stdout)?)
-}
-
-/// Result of probing
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-pub struct ProbeResult {
-    /// Format info
-    pub format: Format,
-
-    #[serde(flatten)]
-    pub unknown: HashMap<String, serde_json::Value>,
-}
-
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-pub struct Format {
-    /// The # of programs
-    pub nb_programs: u32,
-
-    /// The start time, as a string?
-    pub start_time: String,
-
-    /// The file name
-    pub filename: String,
-
-    /// The bit rate, as a string?
-    pub bit_rate: String,
-
-    /// The format name
-    pub format_name: String,
-
-    /// The # of streams, as a string
-    pub nb_streams: u32,
-
-    /// The long name of the format
-    pub format_long_name: String,
-
-    /// The size of the data?
-    pub size: String,
-
-    /// The duration, as a string?
-    pub duration: String,
-
-    /// ?
-    pub probe_score: u32,
-
-    /// Extra k/v
-    #[serde(flatten)]
-    pub unknown: HashMap<String, serde_json::Value>,
-}
-
 /// Download a hls stream.
-async fn download_hls_to_mp4<P>(client: &reqwest::Client, url: &str, path: P) -> anyhow::Result<()>
+async fn download_hls_to_mp4<P>(
+    client: &reqwest::Client,
+    url: &str,
+    path: P,
+    quality: bewu_util::QualityPreference,
+) -> anyhow::Result<()>
 where
     P: AsRef<Path>,
 {
@@ -179,7 +128,11 @@ where
         return Ok(());
     }
 
-    let stream = bewu_util::download_hls(client.clone(), url, path)?;
+    let options = bewu_util::DownloadHlsOptions {
+        quality,
+        ..Default::default()
+    };
+    let stream = bewu_util::download_hls(client.clone(), url, path, options)?;
     tokio::pin!(stream);
 
     let mut stream_duration: Option<Duration> = None;
@@ -213,7 +166,14 @@ where
                         .sum(),
                 );
             }
-            bewu_util::DownloadHlsMessage::DownloadedMediaSegment => {
+            bewu_util::DownloadHlsMessage::DownloadedRenditionPlaylist {
+                media_playlist, ..
+            } => {
+                if let Some(progress_bar) = download_progress_bar.as_ref() {
+                    progress_bar.inc_length(u64::try_from(media_playlist.media_segments.len())?);
+                }
+            }
+            bewu_util::DownloadHlsMessage::DownloadedMediaSegment { .. } => {
                 if let Some(progress_bar) = download_progress_bar.as_ref() {
                     progress_bar.inc(1);
                 }
@@ -1,6 +1,7 @@
 mod anime_type;
 mod client;
 mod episode;
+mod quality_preference;
 mod search_results;
 mod util;
 mod video_player;
@@ -10,7 +11,9 @@ pub use self::anime_type::FromStrError as ParseAnimeTypeError;
 pub use self::client::Client;
 pub use self::episode::Episode;
 pub use self::episode::FromHtmlError as InvalidEpisodeError;
+pub use self::quality_preference::QualityPreference;
 pub use self::search_results::FromHtmlError as InvalidSearchResultsError;
+pub use self::search_results::SearchEntry;
 pub use self::search_results::SearchResults;
 pub use self::video_player::DecryptCryptoDataValueError;
 pub use self::video_player::DecryptVideoDataError;
@@ -0,0 +1,50 @@
+/// A preference for the quality of a video source.
+///
+/// Limits are soft.
+/// Sources that exceed them are ranked after all others instead of being removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QualityPreference {
+    /// The maximum vertical resolution, like `720`.
+    pub max_resolution: Option<u32>,
+}
+
+impl QualityPreference {
+    /// Get the rank of a source label.
+    ///
+    /// Lower ranks are better.
+    /// Returns `None` if the label is unknown.
+    pub(crate) fn get_label_rank(&self, label: &str) -> Option<(u8, u32)> {
+        match label {
+            "hls P" => Some((1, 0)),
+            "auto P" => Some((1, 1)),
+            label => {
+                let resolution: u32 = label.strip_suffix(" P")?.parse().ok()?;
+
+                match self.max_resolution {
+                    Some(max_resolution) if resolution > max_resolution => Some((2, resolution)),
+                    _ => Some((0, u32::MAX - resolution)),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn get_label_rank() {
+        let preference = QualityPreference::default();
+        assert!(preference.get_label_rank("1080 P") < preference.get_label_rank("720 P"));
+        assert!(preference.get_label_rank("360 P") < preference.get_label_rank("hls P"));
+        assert!(preference.get_label_rank("hls P") < preference.get_label_rank("auto P"));
+        assert_eq!(preference.get_label_rank("unknown"), None);
+
+        let preference = QualityPreference {
+            max_resolution: Some(720),
+        };
+        assert!(preference.get_label_rank("720 P") < preference.get_label_rank("480 P"));
+        assert!(preference.get_label_rank("auto P") < preference.get_label_rank("1080 P"));
+    }
+}
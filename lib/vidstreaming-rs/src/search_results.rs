@@ -1,3 +1,4 @@
+use crate::AnimeType;
 use crate::BASE_URL;
 use once_cell::sync::Lazy;
 use scraper::ElementRef;
@@ -90,6 +91,45 @@ impl SearchEntry {
 
         Ok(SearchEntry { name, url })
     }
+
+    /// Get the slug of the anime of this entry, like `bleach-dub`.
+    ///
+    /// Episode urls are formed by appending `-episode-{number}` to this.
+    pub fn anime_slug(&self) -> Option<&str> {
+        let slug = self.url.path().strip_prefix("/videos/")?;
+        let (anime_slug, _episode_number) = slug.rsplit_once("-episode-")?;
+        Some(anime_slug)
+    }
+
+    /// Get the episode number of this entry.
+    ///
+    /// Search results list the latest episode of each anime.
+    pub fn episode_number(&self) -> Option<u32> {
+        let (_anime_slug, episode_number) = self.url.path().rsplit_once("-episode-")?;
+        episode_number.parse().ok()
+    }
+
+    /// Get the anime type of this entry.
+    ///
+    /// Dubs are marked in the entry name.
+    /// Raws are not marked, so they are reported as subs.
+    pub fn anime_type(&self) -> AnimeType {
+        if self.name.contains("(Dub)") {
+            AnimeType::Dub
+        } else {
+            AnimeType::Sub
+        }
+    }
+
+    /// Get the title of the anime of this entry, without the type or episode number.
+    pub fn anime_title(&self) -> &str {
+        let title = match self.name.rsplit_once(" Episode ") {
+            Some((title, _episode_number)) => title,
+            None => self.name.as_str(),
+        };
+        let title = title.trim_end();
+        title.strip_suffix("(Dub)").unwrap_or(title).trim_end()
+    }
 }
 
 #[cfg(test)]
@@ -107,5 +147,17 @@ mod test {
 
         let res = SearchResults::from_html(&doc).expect("failed to parse search results");
         assert!(!res.entries.is_empty());
+
+        let entry = &res.entries[0];
+        assert_eq!(entry.anime_slug(), Some("bleach"));
+        assert_eq!(entry.episode_number(), Some(366));
+        assert_eq!(entry.anime_type(), AnimeType::Sub);
+        assert_eq!(entry.anime_title(), "Bleach");
+
+        let entry = &res.entries[2];
+        assert_eq!(entry.anime_slug(), Some("bleach-dub"));
+        assert_eq!(entry.episode_number(), Some(366));
+        assert_eq!(entry.anime_type(), AnimeType::Dub);
+        assert_eq!(entry.anime_title(), "Bleach");
     }
 }
@@ -1,3 +1,4 @@
+use crate::QualityPreference;
 use crate::BASE_URL;
 use cbc::cipher::KeyIvInit;
 use cipher::block_padding::Pkcs7;
@@ -322,48 +323,39 @@ pub struct VideoData {
 }
 
 impl VideoData {
-    /// Get the best source.
-    pub fn get_best_source(&self) -> Option<&Source> {
-        let mut source_1080_p = None;
-        let mut source_720_p = None;
-        let mut source_480_p = None;
-        let mut source_360_p = None;
-        let mut source_hls = None;
-        let mut source_auto_p = None;
-
-        for source in self.source.iter() {
-            match source.label.as_str() {
-                "1080 P" => {
-                    source_1080_p = Some(source);
-                }
-                "720 P" => {
-                    source_720_p = Some(source);
-                }
-                "480 P" => {
-                    source_480_p = Some(source);
-                }
-                "360 P" => {
-                    source_360_p = Some(source);
-                }
-                "hls P" => {
-                    source_hls = Some(source);
-                }
-                "auto P" => {
-                    source_auto_p = Some(source);
-                }
-                _ => {}
-            }
-        }
-
-        source_1080_p
-            .or(source_720_p)
-            .or(source_480_p)
-            .or(source_360_p)
-            .or(source_hls)
-            .or(source_auto_p)
+    /// Get the best source for the given preference.
+    pub fn get_best_source(&self, preference: &QualityPreference) -> Option<&Source> {
+        self.get_ranked_sources(preference)
+            .into_iter()
+            .find(|source| preference.get_label_rank(&source.label).is_some())
+    }
+
+    /// Get the sources, ordered from best to worst for the given preference.
+    ///
+    /// Sources with unknown labels are last.
+    pub fn get_ranked_sources(&self, preference: &QualityPreference) -> Vec<&Source> {
+        rank_sources(&self.source, preference)
+    }
+
+    /// Get the backup sources, ordered from best to worst for the given preference.
+    ///
+    /// Sources with unknown labels are last.
+    pub fn get_ranked_backup_sources(&self, preference: &QualityPreference) -> Vec<&Source> {
+        rank_sources(&self.source_bk, preference)
     }
 }
 
+/// Sort sources by the rank of their labels.
+fn rank_sources<'a>(sources: &'a [Source], preference: &QualityPreference) -> Vec<&'a Source> {
+    let mut sources: Vec<_> = sources.iter().collect();
+    sources.sort_by_key(|source| {
+        preference
+            .get_label_rank(&source.label)
+            .unwrap_or((u8::MAX, 0))
+    });
+    sources
+}
+
 /// Video source
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct Source {
@@ -413,4 +405,63 @@ mod test {
         assert!(!player.sources.is_empty());
         dbg!(&player);
     }
+
+    #[test]
+    fn ranked_sources() {
+        let video_data: VideoData = serde_json::from_str(
+            r#"{
+                "source": [
+                    { "file": "https://example.com/unknown.mp4", "label": "unknown", "type": "mp4" },
+                    { "file": "https://example.com/hls.m3u8", "label": "hls P", "type": "hls" },
+                    { "file": "https://example.com/720.mp4", "label": "720 P", "type": "mp4" }
+                ],
+                "source_bk": [
+                    { "file": "https://example.com/auto.m3u8", "label": "auto P", "type": "hls" },
+                    { "file": "https://example.com/1080.mp4", "label": "1080 P", "type": "mp4" }
+                ],
+                "advertising": [],
+                "linkiframe": "https://example.com/embed"
+            }"#,
+        )
+        .expect("failed to parse video data");
+
+        let preference = QualityPreference::default();
+
+        let labels: Vec<_> = video_data
+            .get_ranked_sources(&preference)
+            .into_iter()
+            .map(|source| source.label.as_str())
+            .collect();
+        assert_eq!(labels, ["720 P", "hls P", "unknown"]);
+
+        let labels: Vec<_> = video_data
+            .get_ranked_backup_sources(&preference)
+            .into_iter()
+            .map(|source| source.label.as_str())
+            .collect();
+        assert_eq!(labels, ["1080 P", "auto P"]);
+
+        let best_source = video_data
+            .get_best_source(&preference)
+            .expect("missing best source");
+        assert_eq!(best_source.label, "720 P");
+
+        let preference = QualityPreference {
+            max_resolution: Some(480),
+        };
+
+        let labels: Vec<_> = video_data
+            .get_ranked_sources(&preference)
+            .into_iter()
+            .map(|source| source.label.as_str())
+            .collect();
+        assert_eq!(labels, ["hls P", "720 P", "unknown"]);
+
+        let labels: Vec<_> = video_data
+            .get_ranked_backup_sources(&preference)
+            .into_iter()
+            .map(|source| source.label.as_str())
+            .collect();
+        assert_eq!(labels, ["auto P", "1080 P"]);
+    }
 }
@@ -0,0 +1,54 @@
+CREATE TABLE vidstreaming_download_queue (
+    episode_id INTEGER NOT NULL,
+    
+    -- The track of the episode.
+    -- One of "SUB", "DUB", or "RAW".
+    anime_type TEXT NOT NULL DEFAULT 'SUB',
+    
+    -- The position of this entry in the queue.
+    -- Lower positions are downloaded first.
+    position INTEGER NOT NULL,
+    
+    anime_slug TEXT NOT NULL,
+    episode_number INTEGER NOT NULL,
+    
+    -- The vidstreaming slug of the anime, from vidstreaming_anime_mappings.
+    -- If this is NULL, the slug is guessed from the kitsu slug.
+    vidstreaming_slug TEXT,
+    
+    -- The expected length of the episode, from kitsu.
+    length_minutes INTEGER,
+    
+    -- The quality preference of this download.
+    -- The preferred codecs are comma-separated, most preferred first.
+    max_resolution INTEGER,
+    max_bandwidth INTEGER,
+    preferred_codecs TEXT NOT NULL DEFAULT '',
+    
+    -- 0: Queued | The entry will be downloaded when a download slot is free.
+    -- 1: Paused | The entry will not be downloaded until it is resumed.
+    paused INTEGER NOT NULL DEFAULT 0,
+    
+    PRIMARY KEY (episode_id, anime_type),
+    FOREIGN KEY (episode_id) REFERENCES kitsu_episodes (episode_id)
+) STRICT;
+
+CREATE TABLE vidstreaming_downloads (
+    episode_id INTEGER NOT NULL,
+    
+    -- The track of the episode.
+    -- One of "SUB", "DUB", or "RAW".
+    anime_type TEXT NOT NULL DEFAULT 'SUB',
+    
+    -- The list the source came from.
+    -- One of "source", "source_bk", or "linkiframe".
+    source_origin TEXT NOT NULL,
+    source_label TEXT NOT NULL,
+    source_url TEXT NOT NULL,
+    
+    -- The time the download completed, in seconds from the unix epoch.
+    download_time INTEGER NOT NULL,
+    
+    PRIMARY KEY (episode_id, anime_type),
+    FOREIGN KEY (episode_id) REFERENCES kitsu_episodes (episode_id)
+) STRICT;
@@ -0,0 +1,22 @@
+CREATE TABLE vidstreaming_anime_mappings (
+    anime_id INTEGER NOT NULL,
+    
+    -- The track of the anime.
+    -- One of "SUB", "DUB", or "RAW".
+    anime_type TEXT NOT NULL,
+    
+    -- The vidstreaming slug of the anime, like "bleach-dub".
+    -- Episode urls are formed by appending "-episode-{number}" to this.
+    slug TEXT NOT NULL,
+    
+    -- The match score, from 0 to 1.
+    -- This is NULL for manual mappings.
+    score REAL,
+    
+    -- 0: Matched | The mapping was found by searching vidstreaming.
+    -- 1: Manual  | The mapping was set by a user, and will not be replaced by a match.
+    manual INTEGER NOT NULL DEFAULT 0,
+    
+    PRIMARY KEY (anime_id, anime_type),
+    FOREIGN KEY (anime_id) REFERENCES kitsu_anime (id)
+) STRICT;
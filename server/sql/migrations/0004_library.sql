@@ -0,0 +1,33 @@
+-- The anime table was unused before this migration.
+-- Rebuild it to add the library columns and the foreign key.
+CREATE TABLE anime_new (
+    id INTEGER NOT NULL UNIQUE PRIMARY KEY,
+    
+    -- 0: Not Installed | The anime is considered not downloaded, though metadata may still exist.
+    -- 1: Installed     | The anime is considered downloaded, and metadata will exist. Thumbnails/covers must exist.
+    status INTEGER NOT NULL,
+    
+    -- The time the anime was added to the library, in seconds from the unix epoch.
+    added_time INTEGER NOT NULL DEFAULT 0,
+    
+    -- The paths of the poster and cover images, relative to the data directory.
+    -- The poster path is set if the anime is installed.
+    -- The cover path may be NULL for installed anime, as not all anime have covers.
+    poster_path TEXT,
+    cover_path TEXT,
+    
+    FOREIGN KEY (id) REFERENCES kitsu_anime (id)
+) STRICT;
+
+INSERT INTO anime_new (
+    id,
+    status
+) SELECT
+    id,
+    status
+FROM
+    anime;
+
+DROP TABLE anime;
+
+ALTER TABLE anime_new RENAME TO anime;
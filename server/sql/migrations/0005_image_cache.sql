@@ -0,0 +1,14 @@
+CREATE TABLE image_cache (
+    -- The remote url of the image.
+    url TEXT NOT NULL UNIQUE PRIMARY KEY,
+    
+    -- The path of the image, relative to the data directory.
+    path TEXT NOT NULL,
+    
+    -- The size of the image, in bytes.
+    size INTEGER NOT NULL,
+    
+    -- The time the image was last used, in seconds from the unix epoch.
+    -- The least recently used images are evicted first.
+    last_access INTEGER NOT NULL
+) STRICT;
@@ -0,0 +1,93 @@
+-- Alternate and abbreviated titles, separated by newlines.
+ALTER TABLE kitsu_anime ADD COLUMN titles TEXT NOT NULL DEFAULT '';
+ALTER TABLE kitsu_anime ADD COLUMN abbreviated_titles TEXT NOT NULL DEFAULT '';
+
+CREATE VIRTUAL TABLE kitsu_anime_fts USING fts5 (
+    title,
+    titles,
+    abbreviated_titles,
+    synopsis,
+    
+    content = 'kitsu_anime',
+    content_rowid = 'id',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+-- Keep the index in sync with kitsu_anime.
+-- Note that REPLACE conflict resolution does not fire delete triggers,
+-- so kitsu_anime must be upserted with ON CONFLICT DO UPDATE instead.
+CREATE TRIGGER kitsu_anime_fts_insert AFTER INSERT ON kitsu_anime BEGIN
+    INSERT INTO kitsu_anime_fts (
+        rowid,
+        title,
+        titles,
+        abbreviated_titles,
+        synopsis
+    ) VALUES (
+        new.id,
+        new.title,
+        new.titles,
+        new.abbreviated_titles,
+        new.synopsis
+    );
+END;
+
+CREATE TRIGGER kitsu_anime_fts_delete AFTER DELETE ON kitsu_anime BEGIN
+    INSERT INTO kitsu_anime_fts (
+        kitsu_anime_fts,
+        rowid,
+        title,
+        titles,
+        abbreviated_titles,
+        synopsis
+    ) VALUES (
+        'delete',
+        old.id,
+        old.title,
+        old.titles,
+        old.abbreviated_titles,
+        old.synopsis
+    );
+END;
+
+CREATE TRIGGER kitsu_anime_fts_update AFTER UPDATE ON kitsu_anime BEGIN
+    INSERT INTO kitsu_anime_fts (
+        kitsu_anime_fts,
+        rowid,
+        title,
+        titles,
+        abbreviated_titles,
+        synopsis
+    ) VALUES (
+        'delete',
+        old.id,
+        old.title,
+        old.titles,
+        old.abbreviated_titles,
+        old.synopsis
+    );
+    INSERT INTO kitsu_anime_fts (
+        rowid,
+        title,
+        titles,
+        abbreviated_titles,
+        synopsis
+    ) VALUES (
+        new.id,
+        new.title,
+        new.titles,
+        new.abbreviated_titles,
+        new.synopsis
+    );
+END;
+
+-- Index anime that were cached before this migration.
+INSERT INTO kitsu_anime_fts (kitsu_anime_fts) VALUES ('rebuild');
+
+CREATE TABLE kitsu_searches (
+    -- The search query, trimmed and lowercased.
+    query TEXT NOT NULL UNIQUE PRIMARY KEY,
+    
+    -- The time the query was last searched on kitsu, in seconds from the unix epoch.
+    last_update INTEGER NOT NULL
+) STRICT;
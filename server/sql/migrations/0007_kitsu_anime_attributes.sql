@@ -0,0 +1,19 @@
+ALTER TABLE kitsu_anime ADD COLUMN subtype TEXT;
+ALTER TABLE kitsu_anime ADD COLUMN status TEXT;
+ALTER TABLE kitsu_anime ADD COLUMN episode_count INTEGER;
+ALTER TABLE kitsu_anime ADD COLUMN episode_length INTEGER;
+ALTER TABLE kitsu_anime ADD COLUMN age_rating TEXT;
+ALTER TABLE kitsu_anime ADD COLUMN age_rating_guide TEXT;
+ALTER TABLE kitsu_anime ADD COLUMN start_date TEXT;
+ALTER TABLE kitsu_anime ADD COLUMN end_date TEXT;
+ALTER TABLE kitsu_anime ADD COLUMN popularity_rank INTEGER;
+ALTER TABLE kitsu_anime ADD COLUMN rating_rank INTEGER;
+ALTER TABLE kitsu_anime ADD COLUMN user_count INTEGER;
+ALTER TABLE kitsu_anime ADD COLUMN favorites_count INTEGER;
+ALTER TABLE kitsu_anime ADD COLUMN youtube_video_id TEXT;
+ALTER TABLE kitsu_anime ADD COLUMN cover_large TEXT;
+ALTER TABLE kitsu_anime ADD COLUMN nsfw INTEGER NOT NULL DEFAULT 0;
+
+-- Existing rows are missing the new attributes,
+-- so mark them as outdated to have them fetched again.
+UPDATE kitsu_anime SET last_update = 0;
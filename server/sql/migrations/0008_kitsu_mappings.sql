@@ -0,0 +1,14 @@
+CREATE TABLE kitsu_mappings (
+    -- The external site, like "myanimelist/anime".
+    external_site TEXT NOT NULL,
+    
+    -- The id on the external site.
+    external_id TEXT NOT NULL,
+    
+    anime_id INTEGER NOT NULL,
+    
+    last_update INTEGER NOT NULL,
+    
+    PRIMARY KEY (external_site, external_id),
+    FOREIGN KEY (anime_id) REFERENCES kitsu_anime (id)
+) STRICT;
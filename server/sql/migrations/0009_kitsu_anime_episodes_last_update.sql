@@ -0,0 +1,4 @@
+-- When the full episode list of an anime was last fetched.
+-- Single episodes are cached on their own,
+-- so the presence of episodes does not mean the list is complete.
+ALTER TABLE kitsu_anime ADD COLUMN episodes_last_update INTEGER;
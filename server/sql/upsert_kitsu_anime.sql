@@ -1,17 +1,74 @@
-INSERT OR REPLACE INTO kitsu_anime (
+INSERT INTO kitsu_anime (
     id, 
-    slug, 
-    synopsis, 
-    title, 
+    slug,
+    synopsis,
+    title,
+    titles,
+    abbreviated_titles,
     rating,
     poster_large,
+    subtype,
+    status,
+    episode_count,
+    episode_length,
+    age_rating,
+    age_rating_guide,
+    start_date,
+    end_date,
+    popularity_rank,
+    rating_rank,
+    user_count,
+    favorites_count,
+    youtube_video_id,
+    cover_large,
+    nsfw,
     last_update
 ) VALUES (
     :id, 
-    :slug, 
+    :slug,
     :synopsis,
     :title,
+    :titles,
+    :abbreviated_titles,
     :rating,
     :poster_large,
+    :subtype,
+    :status,
+    :episode_count,
+    :episode_length,
+    :age_rating,
+    :age_rating_guide,
+    :start_date,
+    :end_date,
+    :popularity_rank,
+    :rating_rank,
+    :user_count,
+    :favorites_count,
+    :youtube_video_id,
+    :cover_large,
+    :nsfw,
     :last_update
-);
\ No newline at end of file
+) ON CONFLICT (id) DO UPDATE SET
+    slug = excluded.slug,
+    synopsis = excluded.synopsis,
+    title = excluded.title,
+    titles = excluded.titles,
+    abbreviated_titles = excluded.abbreviated_titles,
+    rating = excluded.rating,
+    poster_large = excluded.poster_large,
+    subtype = excluded.subtype,
+    status = excluded.status,
+    episode_count = excluded.episode_count,
+    episode_length = excluded.episode_length,
+    age_rating = excluded.age_rating,
+    age_rating_guide = excluded.age_rating_guide,
+    start_date = excluded.start_date,
+    end_date = excluded.end_date,
+    popularity_rank = excluded.popularity_rank,
+    rating_rank = excluded.rating_rank,
+    user_count = excluded.user_count,
+    favorites_count = excluded.favorites_count,
+    youtube_video_id = excluded.youtube_video_id,
+    cover_large = excluded.cover_large,
+    nsfw = excluded.nsfw,
+    last_update = excluded.last_update;
\ No newline at end of file
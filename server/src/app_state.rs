@@ -1,29 +1,61 @@
 mod database;
+mod image_cache;
 mod kitsu;
+mod library;
+mod matching;
 mod vidstreaming;
 
 // Database re-exports
 pub use self::database::KitsuAnime;
 pub use self::database::KitsuAnimeEpisode;
+pub use self::database::LibraryAnime;
+pub use self::database::LibraryEpisode;
+pub use self::database::LibraryEpisodeStatus;
+pub use self::database::LibrarySort;
+pub use self::database::VidstreamingAnimeMapping;
+
+pub use self::image_cache::get_image_size_url;
+pub use self::image_cache::ImageKind;
+pub use self::image_cache::ImageSize;
+pub use self::kitsu::KitsuSearchMode;
+pub use ::kitsu::ExternalSite as KitsuExternalSite;
 
 // Tasks
 use self::database::Database;
+use self::image_cache::ImageCacheTask;
 use self::kitsu::KitsuTask;
+use self::vidstreaming::DownloadRequest;
 use self::vidstreaming::VidstreamingTask;
+use crate::config::ConfigDownloads;
+use crate::config::ConfigImageCache;
+use crate::config::ConfigKitsu;
 use crate::util::AsyncLockFile;
 
 pub use self::vidstreaming::CloneDownloadState as VidstreamingDownloadState;
+pub use self::vidstreaming::CloneDownloadsState as VidstreamingDownloadsState;
+pub use self::vidstreaming::DownloadQueueEntry as VidstreamingDownloadQueueEntry;
+pub use self::vidstreaming::DownloadSource as VidstreamingDownloadSource;
 pub use self::vidstreaming::DownloadStateUpdate as VidstreamingDownloadStateUpdate;
+pub use self::vidstreaming::DownloadStatus as VidstreamingDownloadStatus;
+pub use self::vidstreaming::DownloadSummary as VidstreamingDownloadSummary;
+pub use self::vidstreaming::DownloadsStateUpdate as VidstreamingDownloadsStateUpdate;
 pub use self::vidstreaming::VidstreamingEpisode;
-use crate::util::AbortJoinHandle;
+use ::vidstreaming::AnimeType;
 use anyhow::ensure;
 use anyhow::Context;
 use std::num::NonZeroU64;
 use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use std::time::SystemTime;
 use tokio_stream::Stream;
 use tracing::debug;
+use tracing::error;
+use tracing::warn;
+
+/// The maximum number of results for a local kitsu search.
+const LOCAL_SEARCH_LIMIT: u64 = 20;
 
 /// The app state
 ///
@@ -31,8 +63,16 @@ use tracing::debug;
 /// # Task Structure
 /// ```
 /// +----------+      +-----------+
-/// | Database | ---> | KitsuTask |
-/// +----------+      +-----------+
+/// | Database | -+-> | KitsuTask |
+/// +----------+  |   +-----------+
+///               |
+///               |   +------------------+
+///               +-> | VidstreamingTask |
+///               |   +------------------+
+///               |
+///               |   +----------------+
+///               +-> | ImageCacheTask |
+///                   +----------------+
 ///
 /// +---------------+
 /// | AsyncLockFile |
@@ -44,14 +84,29 @@ pub struct AppState {
     database: Database,
     kitsu_task: KitsuTask,
     vidstreaming_task: VidstreamingTask,
+    image_cache_task: ImageCacheTask,
 
     kitsu_client: ::kitsu::Client,
+    vidstreaming_client: ::vidstreaming::Client,
 
-    vidstreaming_download: std::sync::Mutex<Option<AbortJoinHandle<()>>>,
+    data_directory: PathBuf,
+
+    /// How long cached kitsu metadata and searches are fresh.
+    ///
+    /// Fresh searches are answered from the database.
+    kitsu_ttl: Duration,
+
+    /// The default quality preference for downloads.
+    quality_preference: bewu_util::QualityPreference,
 }
 
 impl AppState {
-    pub async fn new<P>(data_directory: P) -> anyhow::Result<Self>
+    pub async fn new<P>(
+        data_directory: P,
+        downloads_config: &ConfigDownloads,
+        image_cache_config: &ConfigImageCache,
+        kitsu_config: &ConfigKitsu,
+    ) -> anyhow::Result<Self>
     where
         P: AsRef<Path>,
     {
@@ -111,6 +166,36 @@ impl AppState {
             }
         }
 
+        let kitsu_thumbnail_directory = kitsu_directory.join("thumbnail");
+        match tokio::fs::create_dir(&kitsu_thumbnail_directory).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!(
+                        "failed to create kitsu thumbnail directory \"{}\"",
+                        kitsu_thumbnail_directory.display()
+                    )
+                });
+            }
+        }
+
+        // Library images are copied out of the image cache,
+        // so they are never evicted.
+        let library_directory = data_directory.join("library");
+        match tokio::fs::create_dir(&library_directory).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!(
+                        "failed to create library directory \"{}\"",
+                        library_directory.display()
+                    )
+                });
+            }
+        }
+
         let vidstreaming_directory = data_directory.join("vidstreaming");
         match tokio::fs::create_dir(&vidstreaming_directory).await {
             Ok(()) => {}
@@ -147,7 +232,21 @@ impl AppState {
                 return Err(e).with_context(|| {
                     format!(
                         "failed to create vidstreaming dub directory \"{}\"",
-                        vidstreaming_sub_directory.display()
+                        vidstreaming_dub_directory.display()
+                    )
+                });
+            }
+        }
+
+        let vidstreaming_raw_directory = vidstreaming_directory.join("raw");
+        match tokio::fs::create_dir(&vidstreaming_raw_directory).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!(
+                        "failed to create vidstreaming raw directory \"{}\"",
+                        vidstreaming_raw_directory.display()
                     )
                 });
             }
@@ -166,26 +265,287 @@ impl AppState {
             .context("failed to open database")?;
 
         let kitsu_client = ::kitsu::Client::new();
-
-        let kitsu_task = KitsuTask::new(database.clone());
-        let vidstreaming_task = VidstreamingTask::new(&vidstreaming_sub_directory);
-
-        Ok(Self {
+        let vidstreaming_client = ::vidstreaming::Client::new();
+
+        // The task shares the client, so all kitsu requests share one rate limit.
+        let kitsu_ttl = Duration::from_secs(kitsu_config.ttl);
+        let kitsu_task = KitsuTask::new(
+            kitsu_client.clone(),
+            database.clone(),
+            kitsu_ttl,
+            Duration::from_secs(kitsu_config.refresh_interval.get()),
+        );
+        let host_limiter = bewu_util::HostLimiter::new(downloads_config.max_per_host.get());
+        let vidstreaming_task = VidstreamingTask::new(
+            database.clone(),
+            &vidstreaming_directory,
+            downloads_config.max_concurrent,
+            host_limiter,
+        );
+        let image_cache_task = ImageCacheTask::new(
+            database.clone(),
+            data_directory,
+            image_cache_config.max_size,
+        );
+
+        let app_state = Self {
             lock_file,
             database,
             kitsu_task,
             vidstreaming_task,
+            image_cache_task,
 
             kitsu_client,
+            vidstreaming_client,
+
+            data_directory: data_directory.into(),
 
-            vidstreaming_download: std::sync::Mutex::new(None),
-        })
+            kitsu_ttl,
+
+            quality_preference: bewu_util::QualityPreference {
+                max_resolution: downloads_config.quality.max_resolution,
+                max_bandwidth: downloads_config.quality.max_bandwidth,
+                preferred_codecs: downloads_config.quality.preferred_codecs.clone(),
+            },
+        };
+
+        if let Err(error) = app_state
+            .verify_library()
+            .await
+            .context("failed to verify library")
+        {
+            error!("{error:?}");
+        }
+
+        Ok(app_state)
     }
 
-    /// Run a search on kitsu.
-    pub async fn search_kitsu(&self, query: &str) -> anyhow::Result<Arc<[KitsuAnime]>> {
-        let anime = self.kitsu_task.search(query).await?;
-        Ok(anime)
+    /// Mark installed anime with missing images as not installed.
+    async fn verify_library(&self) -> anyhow::Result<()> {
+        for anime in self.database.get_installed_library_anime().await? {
+            let poster_exists = match anime.poster_path.as_deref() {
+                Some(poster_path) => {
+                    tokio::fs::try_exists(self.data_directory.join(poster_path)).await?
+                }
+                None => false,
+            };
+            let cover_exists = match anime.cover_path.as_deref() {
+                Some(cover_path) => {
+                    tokio::fs::try_exists(self.data_directory.join(cover_path)).await?
+                }
+                None => true,
+            };
+
+            if !poster_exists || !cover_exists {
+                warn!(
+                    "library anime {} is missing images, marking it as not installed",
+                    anime.id
+                );
+                self.database
+                    .set_library_anime_status(anime.id, false, anime.poster_path, anime.cover_path)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the local paths of kitsu images, if they are cached.
+    ///
+    /// Paths are relative to the data directory.
+    /// Results are in the same order as the given images, with the url of the requested size.
+    /// Images that are not cached are downloaded in the background.
+    pub async fn get_cached_kitsu_images(
+        &self,
+        images: Vec<(&str, ImageKind, ImageSize)>,
+    ) -> anyhow::Result<Vec<(String, Option<Arc<str>>)>> {
+        let images: Vec<(String, ImageKind)> = images
+            .into_iter()
+            .map(|(url, kind, size)| (self::image_cache::get_image_size_url(url, size), kind))
+            .collect();
+
+        let paths = self
+            .image_cache_task
+            .get_cached_images(
+                images
+                    .iter()
+                    .map(|(url, kind)| (url.as_str().into(), *kind))
+                    .collect(),
+            )
+            .await?;
+
+        Ok(images
+            .into_iter()
+            .map(|(url, _kind)| url)
+            .zip(paths)
+            .collect())
+    }
+
+    /// Get a page of the library.
+    ///
+    /// Returns the anime in the page and the total number of anime in the library.
+    pub async fn get_library_anime_page(
+        &self,
+        sort: LibrarySort,
+        descending: bool,
+        offset: u64,
+        limit: u64,
+    ) -> anyhow::Result<(Vec<LibraryAnime>, u64)> {
+        self.database
+            .get_library_anime_page(sort, descending, offset, limit)
+            .await
+    }
+
+    /// Get an anime in the library, with the download status of its episodes for the given track.
+    pub async fn get_library_anime(
+        &self,
+        id: NonZeroU64,
+        anime_type: AnimeType,
+    ) -> anyhow::Result<(LibraryAnime, Vec<LibraryEpisode>)> {
+        let anime = self
+            .database
+            .get_library_anime(id)
+            .await?
+            .with_context(|| format!("anime {id} is not in the library"))?;
+        let episodes = self.database.get_library_episodes(id, anime_type).await?;
+
+        Ok((anime, episodes))
+    }
+
+    /// Add an anime to the library and install it.
+    ///
+    /// Installing copies the poster and cover images from the image cache into the library directory.
+    /// If the anime is already in the library, it is reinstalled.
+    pub async fn add_library_anime(&self, id: NonZeroU64) -> anyhow::Result<LibraryAnime> {
+        // Save the anime and its episodes, so episode download status can be tracked.
+        self.get_kitsu_anime(id).await?;
+        self.get_kitsu_anime_episodes(id).await?;
+
+        let added_time = SystemTime::UNIX_EPOCH.elapsed()?.as_secs();
+        self.database.insert_library_anime(id, added_time).await?;
+
+        let anime = self.get_kitsu_anime(id).await?;
+
+        let poster_url = url::Url::parse(&anime.poster_large).context("invalid poster url")?;
+        let poster_path = self::library::get_library_image_path(id, "poster", &poster_url);
+        self.image_cache_task
+            .copy_image(
+                anime.poster_large.as_str().into(),
+                ImageKind::Poster,
+                self.data_directory.join(&poster_path),
+            )
+            .await
+            .context("failed to copy poster")?;
+
+        let cover_path = match anime.cover_large.as_deref() {
+            Some(cover_large) => {
+                let cover_url = url::Url::parse(cover_large).context("invalid cover url")?;
+                let cover_path = self::library::get_library_image_path(id, "cover", &cover_url);
+                self.image_cache_task
+                    .copy_image(
+                        cover_large.into(),
+                        ImageKind::Cover,
+                        self.data_directory.join(&cover_path),
+                    )
+                    .await
+                    .context("failed to copy cover")?;
+
+                Some(cover_path)
+            }
+            None => None,
+        };
+
+        self.database
+            .set_library_anime_status(id, true, Some(poster_path), cover_path)
+            .await?;
+
+        self.database
+            .get_library_anime(id)
+            .await?
+            .context("anime missing from library")
+    }
+
+    /// Remove an anime from the library.
+    ///
+    /// This removes the poster and cover images.
+    /// Downloaded episodes are kept.
+    pub async fn remove_library_anime(&self, id: NonZeroU64) -> anyhow::Result<()> {
+        let anime = match self.database.get_library_anime(id).await? {
+            Some(anime) => anime,
+            None => return Ok(()),
+        };
+
+        // Uninstall first, so an installed anime never has missing images.
+        self.database
+            .set_library_anime_status(id, false, None, None)
+            .await?;
+        for path in [anime.poster_path, anime.cover_path].into_iter().flatten() {
+            self::image_cache::remove_file_if_exists(&self.data_directory.join(path)).await?;
+        }
+        self.database.remove_library_anime(id).await?;
+
+        Ok(())
+    }
+
+    /// Search for kitsu anime.
+    pub async fn search_kitsu(
+        &self,
+        query: &str,
+        mode: KitsuSearchMode,
+    ) -> anyhow::Result<Arc<[KitsuAnime]>> {
+        match mode {
+            KitsuSearchMode::Local => {
+                let anime = self
+                    .database
+                    .search_kitsu_anime(query, LOCAL_SEARCH_LIMIT)
+                    .await?;
+                Ok(anime.into())
+            }
+            KitsuSearchMode::Remote => {
+                let anime = self.kitsu_task.search(query).await?;
+                Ok(anime)
+            }
+            KitsuSearchMode::Auto => {
+                let now = SystemTime::UNIX_EPOCH.elapsed()?.as_secs();
+                let last_update = self.database.get_kitsu_search_last_update(query).await?;
+                let is_fresh = last_update.is_some_and(|last_update| {
+                    now.saturating_sub(last_update) < self.kitsu_ttl.as_secs()
+                });
+
+                let local_anime = self
+                    .database
+                    .search_kitsu_anime(query, LOCAL_SEARCH_LIMIT)
+                    .await?;
+                if is_fresh && !local_anime.is_empty() {
+                    debug!("using local results for search \"{query}\"");
+                    return Ok(local_anime.into());
+                }
+
+                let remote_anime = match self.kitsu_task.search(query).await {
+                    Ok(anime) => anime,
+                    Err(error) if !local_anime.is_empty() => {
+                        warn!(
+                            "{:?}",
+                            error.context("failed to search kitsu, using local results")
+                        );
+                        return Ok(local_anime.into());
+                    }
+                    Err(error) => {
+                        return Err(error);
+                    }
+                };
+
+                // Keep the kitsu ordering, then add local results kitsu did not return.
+                let mut anime = remote_anime.to_vec();
+                for local_anime in local_anime {
+                    if !anime.iter().any(|anime| anime.id == local_anime.id) {
+                        anime.push(local_anime);
+                    }
+                }
+
+                Ok(anime.into())
+            }
+        }
     }
 
     /// Get the kitsu anime for the given id.
@@ -194,47 +554,52 @@ impl AppState {
         Ok(anime)
     }
 
-    /// Get kitsu episodes for the anime with the given id
-    pub async fn get_kitsu_anime_episodes(
+    /// Get the kitsu anime an id on an external site maps to.
+    ///
+    /// Returns `None` if kitsu has no mapping for the id.
+    pub async fn get_kitsu_anime_by_external_id(
         &self,
-        anime_id: NonZeroU64,
-    ) -> anyhow::Result<Arc<[KitsuAnimeEpisode]>> {
-        let document = self.kitsu_client.get_anime_episodes(anime_id).await?;
-        let document_data = document.data.context("missing document data")?;
-
-        let mut episodes = Vec::with_capacity(document_data.len());
-        let last_update = SystemTime::UNIX_EPOCH.elapsed()?.as_secs();
-        for item in document_data {
-            let attributes = item.attributes.context("missing attributes")?;
-            let episode_id: NonZeroU64 = item.id.as_deref().context("missing id")?.parse()?;
-
-            let title = attributes.canonical_title;
-            let synopsis = attributes.synopsis;
-            let length_minutes: Option<u32> = attributes.length;
-            let number = attributes.number;
-            let thumbnail_original = attributes
-                .thumbnail
-                .map(|thumbnail| thumbnail.original.into());
-
-            episodes.push(KitsuAnimeEpisode {
-                anime_id,
-                episode_id,
-
-                title,
-                synopsis,
-                length_minutes,
-                number,
-
-                thumbnail_original,
-                last_update,
-            });
+        external_site: &KitsuExternalSite,
+        external_id: &str,
+    ) -> anyhow::Result<Option<Arc<KitsuAnime>>> {
+        let anime_id = self
+            .database
+            .get_kitsu_mapping(external_site.as_str(), external_id)
+            .await?;
+        if let Some(anime_id) = anime_id {
+            let anime = self.get_kitsu_anime(anime_id).await?;
+            return Ok(Some(anime));
         }
-        let episodes: Arc<[KitsuAnimeEpisode]> = episodes.into();
 
+        let page = self
+            .kitsu_client
+            .find_mapping(external_site, external_id)
+            .await?;
+        let included_anime = page
+            .get_included::<::kitsu::Anime>("anime")
+            .context("failed to parse included anime")?;
+        let anime_id: NonZeroU64 = match included_anime.first() {
+            Some(anime) => anime.id.as_deref().context("missing id")?.parse()?,
+            None => return Ok(None),
+        };
+
+        // The mapping references the anime, so the anime must be saved first.
+        let anime = self.get_kitsu_anime(anime_id).await?;
+
+        let last_update = SystemTime::UNIX_EPOCH.elapsed()?.as_secs();
         self.database
-            .upsert_kitsu_episodes(episodes.clone())
+            .upsert_kitsu_mapping(external_site.as_str(), external_id, anime_id, last_update)
             .await?;
 
+        Ok(Some(anime))
+    }
+
+    /// Get kitsu episodes for the anime with the given id
+    pub async fn get_kitsu_anime_episodes(
+        &self,
+        anime_id: NonZeroU64,
+    ) -> anyhow::Result<Arc<[KitsuAnimeEpisode]>> {
+        let episodes = self.kitsu_task.get_anime_episodes(anime_id).await?;
         Ok(episodes)
     }
 
@@ -249,25 +614,10 @@ impl AppState {
         };
 
         // TODO: Can we avoid a look-up by using the database?
-        // Note: I hate that this is necessary.
         let anime_id_handle = {
-            // TODO: Investigate url more and add to kitsu lib
-            #[derive(Debug, serde::Deserialize)]
-            struct EpisodeMediaRelationshipMedia {
-                /// The anime id
-                pub id: String,
-
-                /// The anime type
-                #[serde(rename = "type")]
-                pub kind: String,
-            }
-
-            let client = self.kitsu_client.client.clone();
-            let url = format!("https://kitsu.io/api/edge/episodes/{id}/relationships/media");
+            let client = self.kitsu_client.clone();
             tokio::spawn(async move {
-                let document = client
-                    .get_json_document::<EpisodeMediaRelationshipMedia>(&url)
-                    .await?;
+                let document = client.get_episode_media(id).await?;
                 let document_data = document.data.context("missing document data")?;
 
                 ensure!(document_data.kind == "anime");
@@ -314,52 +664,208 @@ impl AppState {
         Ok(episode)
     }
 
+    /// Get the vidstreaming anime that a kitsu anime maps to.
+    ///
+    /// If there is no saved mapping, vidstreaming is searched for the best match, which is saved.
+    pub async fn get_vidstreaming_anime_mapping(
+        &self,
+        anime_id: NonZeroU64,
+        anime_type: AnimeType,
+    ) -> anyhow::Result<VidstreamingAnimeMapping> {
+        if let Some(mapping) = self
+            .database
+            .get_vidstreaming_anime_mapping(anime_id, anime_type)
+            .await?
+        {
+            return Ok(mapping);
+        }
+
+        // Make sure the anime is in the database before mapping it.
+        self.get_kitsu_anime(anime_id).await?;
+
+        self::matching::get_vidstreaming_anime_mapping(
+            &self.database,
+            &self.vidstreaming_client,
+            anime_id,
+            anime_type,
+        )
+        .await
+    }
+
+    /// Manually map a kitsu anime to a vidstreaming anime.
+    ///
+    /// This replaces any existing mapping.
+    pub async fn set_vidstreaming_anime_mapping(
+        &self,
+        anime_id: NonZeroU64,
+        anime_type: AnimeType,
+        slug: &str,
+    ) -> anyhow::Result<VidstreamingAnimeMapping> {
+        ensure!(!slug.is_empty(), "the vidstreaming slug is empty");
+
+        // Make sure the anime is in the database before mapping it.
+        self.get_kitsu_anime(anime_id).await?;
+
+        let mapping = VidstreamingAnimeMapping {
+            anime_id,
+            anime_type,
+            slug: slug.to_string(),
+            score: None,
+            manual: true,
+        };
+        self.database
+            .upsert_vidstreaming_anime_mapping(mapping.clone())
+            .await?;
+
+        Ok(mapping)
+    }
+
+    /// Remove the mapping of a kitsu anime to a vidstreaming anime.
+    ///
+    /// The next lookup will search vidstreaming again.
+    pub async fn remove_vidstreaming_anime_mapping(
+        &self,
+        anime_id: NonZeroU64,
+        anime_type: AnimeType,
+    ) -> anyhow::Result<()> {
+        self.database
+            .remove_vidstreaming_anime_mapping(anime_id, anime_type)
+            .await
+    }
+
     pub async fn get_vidstreaming_episode(
         &self,
         id: NonZeroU64,
+        anime_type: AnimeType,
     ) -> anyhow::Result<VidstreamingEpisode> {
         let episode = self.get_kitsu_episode(id).await?;
         let anime = self.get_kitsu_anime(episode.anime_id).await?;
         self.vidstreaming_task
-            .get_episode(anime.slug.as_str(), episode.number)
+            .get_episode(anime_type, anime.slug.as_str(), episode.number)
             .await
     }
 
     /// Start a vidstreaming episode download.
+    ///
+    /// The download is added to the end of the download queue.
+    /// Fields set in the quality override replace those of the configured quality preference.
     pub async fn start_vidstreaming_episode_download(
         &self,
         id: NonZeroU64,
+        anime_type: AnimeType,
+        quality: QualityPreferenceOverride,
     ) -> anyhow::Result<impl Stream<Item = bewu_util::StateUpdateItem<VidstreamingDownloadState>>>
     {
         let episode = self.get_kitsu_episode(id).await?;
         let anime = self.get_kitsu_anime(episode.anime_id).await?;
 
+        let mut quality_preference = self.quality_preference.clone();
+        if let Some(max_resolution) = quality.max_resolution {
+            quality_preference.max_resolution = Some(max_resolution);
+        }
+        if let Some(max_bandwidth) = quality.max_bandwidth {
+            quality_preference.max_bandwidth = Some(max_bandwidth);
+        }
+        if let Some(preferred_codecs) = quality.preferred_codecs {
+            quality_preference.preferred_codecs =
+                self::vidstreaming::split_preferred_codecs(&preferred_codecs);
+        }
+
+        // Searching vidstreaming for a match is slow,
+        // so unmapped anime are mapped by the download task.
+        let vidstreaming_slug = self
+            .database
+            .get_vidstreaming_anime_mapping(anime.id, anime_type)
+            .await?
+            .map(|mapping| mapping.slug.into());
+
         let stream = self
             .vidstreaming_task
-            .start_episode_download(anime.slug.as_str(), episode.number)
+            .start_episode_download(DownloadRequest {
+                episode_id: episode.episode_id,
+                anime_id: anime.id,
+                anime_type,
+                anime_slug: anime.slug.as_str().into(),
+                vidstreaming_slug,
+                episode_number: episode.number,
+                length_minutes: episode.length_minutes,
+                quality: quality_preference,
+            })
             .await?;
 
         Ok(stream)
     }
 
+    /// Get the vidstreaming download queue.
+    ///
+    /// Active downloads are first.
+    pub async fn get_vidstreaming_download_queue(
+        &self,
+    ) -> anyhow::Result<Vec<VidstreamingDownloadQueueEntry>> {
+        self.vidstreaming_task.get_download_queue().await
+    }
+
+    /// Move a queued vidstreaming episode download to a new position in the queue.
+    pub async fn move_vidstreaming_download_queue_entry(
+        &self,
+        id: NonZeroU64,
+        anime_type: AnimeType,
+        position: usize,
+    ) -> anyhow::Result<()> {
+        self.vidstreaming_task
+            .move_download_queue_entry(id, anime_type, position)
+            .await
+    }
+
+    /// Get a snapshot of all active, queued, and recently finished vidstreaming downloads.
+    pub fn get_vidstreaming_downloads(&self) -> Vec<VidstreamingDownloadSummary> {
+        self.vidstreaming_task.get_downloads()
+    }
+
+    /// Get a stream of updates for all vidstreaming downloads.
+    pub fn subscribe_vidstreaming_downloads(
+        &self,
+    ) -> impl Stream<Item = bewu_util::StateUpdateItem<VidstreamingDownloadsState>> {
+        self.vidstreaming_task.subscribe_downloads()
+    }
+
+    /// Cancel a queued or in-progress vidstreaming episode download.
+    pub async fn cancel_vidstreaming_episode_download(
+        &self,
+        id: NonZeroU64,
+        anime_type: AnimeType,
+    ) -> anyhow::Result<()> {
+        self.vidstreaming_task
+            .cancel_episode_download(id, anime_type)
+            .await
+    }
+
+    /// Pause a queued or in-progress vidstreaming episode download.
+    pub async fn pause_vidstreaming_episode_download(
+        &self,
+        id: NonZeroU64,
+        anime_type: AnimeType,
+    ) -> anyhow::Result<()> {
+        self.vidstreaming_task
+            .pause_episode_download(id, anime_type)
+            .await
+    }
+
+    /// Resume a paused vidstreaming episode download.
+    pub async fn resume_vidstreaming_episode_download(
+        &self,
+        id: NonZeroU64,
+        anime_type: AnimeType,
+    ) -> anyhow::Result<()> {
+        self.vidstreaming_task
+            .resume_episode_download(id, anime_type)
+            .await
+    }
+
     /// Shutdown the app state.
     ///
     /// This should only be called once.
     pub async fn shutdown(&self) -> anyhow::Result<()> {
-        let handle = self
-            .vidstreaming_download
-            .lock()
-            .unwrap_or_else(|e| e.into_inner())
-            .take();
-        if let Some(handle) = handle {
-            let handle = handle.into_inner();
-
-            handle.abort();
-
-            // TODO: Don't let an error here stop the shutdown sequence
-            handle.await?;
-        }
-
         debug!("shutting down vidstreaming task");
         let vidstreaming_shutdown_result = self
             .vidstreaming_task
@@ -367,6 +873,13 @@ impl AppState {
             .await
             .context("failed to shutdwon vidstreaming task");
 
+        debug!("shutting down image cache task");
+        let image_cache_shutdown_result = self
+            .image_cache_task
+            .shutdown()
+            .await
+            .context("failed to shutdown image cache task");
+
         debug!("shutting down kitsu task");
         let kitsu_shutdown_result = self
             .kitsu_task
@@ -394,7 +907,21 @@ impl AppState {
         database_shutdown_result
             .or(kitsu_shutdown_result)
             .or(vidstreaming_shutdown_result)
+            .or(image_cache_shutdown_result)
             .or(lock_file_unlock_result)
             .or(lock_file_shutdown_result)
     }
 }
+
+/// Per-request overrides for the configured download quality preference.
+#[derive(Debug, Default)]
+pub struct QualityPreferenceOverride {
+    /// The maximum vertical resolution.
+    pub max_resolution: Option<u32>,
+
+    /// The maximum bandwidth, in bits per second.
+    pub max_bandwidth: Option<u64>,
+
+    /// The codecs to prefer, comma-separated.
+    pub preferred_codecs: Option<String>,
+}
@@ -1,7 +1,16 @@
+mod migrations;
 mod model;
 
+pub use self::model::ImageCacheEntry;
 pub use self::model::KitsuAnime;
 pub use self::model::KitsuAnimeEpisode;
+pub use self::model::LibraryAnime;
+pub use self::model::LibraryEpisode;
+pub use self::model::LibraryEpisodeStatus;
+pub use self::model::LibrarySort;
+pub use self::model::VidstreamingAnimeMapping;
+pub use self::model::VidstreamingDownload;
+pub use self::model::VidstreamingDownloadQueueEntry;
 use anyhow::Context;
 use nd_async_rusqlite::rusqlite::named_params;
 use nd_async_rusqlite::rusqlite::OptionalExtension;
@@ -9,6 +18,7 @@ use std::num::NonZeroU64;
 use std::path::Path;
 use std::sync::Arc;
 use tracing::error;
+use vidstreaming::AnimeType;
 
 pub trait AsSlice<T> {
     fn as_slice(&self) -> &[T];
@@ -43,6 +53,21 @@ const UPSERT_KITSU_ANIME_SQL: &str = include_str!(concat!(
     env!("CARGO_MANIFEST_DIR"),
     "/sql/upsert_kitsu_anime.sql"
 ));
+
+/// Rename the slug of another anime that holds the slug of an anime about to be upserted.
+///
+/// Kitsu reassigns slugs, so the stale row gives up its slug instead of failing the upsert.
+/// The renamed row is marked stale, so it is refreshed on its next access.
+const RELEASE_KITSU_ANIME_SLUG_SQL: &str = "
+UPDATE
+    kitsu_anime
+SET
+    slug = slug || '~' || id,
+    last_update = 0
+WHERE
+    slug = :slug AND
+    id != :id;
+";
 const UPSERT_KITSU_EPISODE_SQL: &str = include_str!(concat!(
     env!("CARGO_MANIFEST_DIR"),
     "/sql/upsert_kitsu_episode.sql"
@@ -54,8 +79,25 @@ SELECT
     slug,
     synopsis,
     title,
+    titles,
+    abbreviated_titles,
     rating,
     poster_large,
+    subtype,
+    status,
+    episode_count,
+    episode_length,
+    age_rating,
+    age_rating_guide,
+    start_date,
+    end_date,
+    popularity_rank,
+    rating_rank,
+    user_count,
+    favorites_count,
+    youtube_video_id,
+    cover_large,
+    nsfw,
     last_update
 FROM
     kitsu_anime
@@ -63,6 +105,427 @@ WHERE
     id = :id;
 ";
 
+const GET_KITSU_ANIME_EPISODES_SQL: &str = "
+SELECT
+    episode_id,
+    anime_id,
+    title,
+    synopsis,
+    length_minutes,
+    number,
+    thumbnail_original,
+    last_update
+FROM
+    kitsu_episodes
+WHERE
+    anime_id = :anime_id
+ORDER BY
+    number ASC;
+";
+
+const GET_KITSU_ANIME_EPISODES_LAST_UPDATE_SQL: &str = "
+SELECT
+    episodes_last_update
+FROM
+    kitsu_anime
+WHERE
+    id = :anime_id;
+";
+
+const SET_KITSU_ANIME_EPISODES_LAST_UPDATE_SQL: &str = "
+UPDATE
+    kitsu_anime
+SET
+    episodes_last_update = :episodes_last_update
+WHERE
+    id = :anime_id;
+";
+
+const GET_AIRING_LIBRARY_ANIME_IDS_SQL: &str = "
+SELECT
+    anime.id
+FROM
+    anime
+JOIN
+    kitsu_anime
+ON
+    kitsu_anime.id = anime.id
+WHERE
+    kitsu_anime.status IS NULL OR
+    kitsu_anime.status = 'current';
+";
+
+const SEARCH_KITSU_ANIME_SQL: &str = "
+SELECT 
+    kitsu_anime.id, 
+    kitsu_anime.slug,
+    kitsu_anime.synopsis,
+    kitsu_anime.title,
+    kitsu_anime.titles,
+    kitsu_anime.abbreviated_titles,
+    kitsu_anime.rating,
+    kitsu_anime.poster_large,
+    kitsu_anime.subtype,
+    kitsu_anime.status,
+    kitsu_anime.episode_count,
+    kitsu_anime.episode_length,
+    kitsu_anime.age_rating,
+    kitsu_anime.age_rating_guide,
+    kitsu_anime.start_date,
+    kitsu_anime.end_date,
+    kitsu_anime.popularity_rank,
+    kitsu_anime.rating_rank,
+    kitsu_anime.user_count,
+    kitsu_anime.favorites_count,
+    kitsu_anime.youtube_video_id,
+    kitsu_anime.cover_large,
+    kitsu_anime.nsfw,
+    kitsu_anime.last_update
+FROM
+    kitsu_anime_fts
+JOIN
+    kitsu_anime
+ON
+    kitsu_anime.id = kitsu_anime_fts.rowid
+WHERE
+    kitsu_anime_fts MATCH :query
+ORDER BY
+    bm25(kitsu_anime_fts, 10.0, 5.0, 5.0, 1.0) ASC
+LIMIT
+    :limit;
+";
+
+const GET_KITSU_SEARCH_LAST_UPDATE_SQL: &str = "
+SELECT
+    last_update
+FROM
+    kitsu_searches
+WHERE
+    query = :query;
+";
+
+const UPSERT_KITSU_SEARCH_SQL: &str = "
+INSERT OR REPLACE INTO kitsu_searches (
+    query,
+    last_update
+) VALUES (
+    :query,
+    :last_update
+);
+";
+
+const GET_VIDSTREAMING_DOWNLOAD_QUEUE_SQL: &str = "
+SELECT
+    vidstreaming_download_queue.episode_id,
+    kitsu_episodes.anime_id,
+    vidstreaming_download_queue.anime_type,
+    vidstreaming_download_queue.anime_slug,
+    vidstreaming_download_queue.episode_number,
+    vidstreaming_download_queue.vidstreaming_slug,
+    vidstreaming_download_queue.length_minutes,
+    vidstreaming_download_queue.max_resolution,
+    vidstreaming_download_queue.max_bandwidth,
+    vidstreaming_download_queue.preferred_codecs,
+    vidstreaming_download_queue.paused
+FROM
+    vidstreaming_download_queue
+JOIN
+    kitsu_episodes
+ON
+    kitsu_episodes.episode_id = vidstreaming_download_queue.episode_id
+ORDER BY
+    vidstreaming_download_queue.position ASC;
+";
+
+const PUSH_VIDSTREAMING_DOWNLOAD_QUEUE_ENTRY_SQL: &str = "
+INSERT OR REPLACE INTO vidstreaming_download_queue (
+    episode_id,
+    anime_type,
+    position,
+    anime_slug,
+    episode_number,
+    vidstreaming_slug,
+    length_minutes,
+    max_resolution,
+    max_bandwidth,
+    preferred_codecs,
+    paused
+) VALUES (
+    :episode_id,
+    :anime_type,
+    (SELECT IFNULL(MAX(position), -1) + 1 FROM vidstreaming_download_queue),
+    :anime_slug,
+    :episode_number,
+    :vidstreaming_slug,
+    :length_minutes,
+    :max_resolution,
+    :max_bandwidth,
+    :preferred_codecs,
+    :paused
+);
+";
+
+const SET_VIDSTREAMING_DOWNLOAD_QUEUE_POSITION_SQL: &str = "
+UPDATE
+    vidstreaming_download_queue
+SET
+    position = :position
+WHERE
+    episode_id = :episode_id AND
+    anime_type = :anime_type;
+";
+
+const SET_VIDSTREAMING_DOWNLOAD_QUEUE_ENTRY_PAUSED_SQL: &str = "
+UPDATE
+    vidstreaming_download_queue
+SET
+    paused = :paused
+WHERE
+    episode_id = :episode_id AND
+    anime_type = :anime_type;
+";
+
+const REMOVE_VIDSTREAMING_DOWNLOAD_QUEUE_ENTRY_SQL: &str = "
+DELETE FROM
+    vidstreaming_download_queue
+WHERE
+    episode_id = :episode_id AND
+    anime_type = :anime_type;
+";
+
+const GET_KITSU_MAPPING_SQL: &str = "
+SELECT
+    anime_id
+FROM
+    kitsu_mappings
+WHERE
+    external_site = :external_site AND
+    external_id = :external_id;
+";
+
+const UPSERT_KITSU_MAPPING_SQL: &str = "
+INSERT OR REPLACE INTO kitsu_mappings (
+    external_site,
+    external_id,
+    anime_id,
+    last_update
+) VALUES (
+    :external_site,
+    :external_id,
+    :anime_id,
+    :last_update
+);
+";
+
+const GET_VIDSTREAMING_ANIME_MAPPING_SQL: &str = "
+SELECT
+    anime_id,
+    anime_type,
+    slug,
+    score,
+    manual
+FROM
+    vidstreaming_anime_mappings
+WHERE
+    anime_id = :anime_id AND
+    anime_type = :anime_type;
+";
+
+const UPSERT_VIDSTREAMING_ANIME_MAPPING_SQL: &str = "
+INSERT OR REPLACE INTO vidstreaming_anime_mappings (
+    anime_id,
+    anime_type,
+    slug,
+    score,
+    manual
+) VALUES (
+    :anime_id,
+    :anime_type,
+    :slug,
+    :score,
+    :manual
+);
+";
+
+const REMOVE_VIDSTREAMING_ANIME_MAPPING_SQL: &str = "
+DELETE FROM
+    vidstreaming_anime_mappings
+WHERE
+    anime_id = :anime_id AND
+    anime_type = :anime_type;
+";
+
+const UPSERT_VIDSTREAMING_DOWNLOAD_SQL: &str = "
+INSERT OR REPLACE INTO vidstreaming_downloads (
+    episode_id,
+    anime_type,
+    source_origin,
+    source_label,
+    source_url,
+    download_time
+) VALUES (
+    :episode_id,
+    :anime_type,
+    :source_origin,
+    :source_label,
+    :source_url,
+    :download_time
+);
+";
+
+const GET_LIBRARY_ANIME_SQL: &str = "
+SELECT
+    anime.id,
+    anime.status,
+    anime.added_time,
+    anime.poster_path,
+    anime.cover_path,
+    kitsu_anime.slug,
+    kitsu_anime.title,
+    kitsu_anime.rating,
+    (
+        SELECT
+            COUNT(DISTINCT vidstreaming_downloads.episode_id)
+        FROM
+            vidstreaming_downloads
+        JOIN
+            kitsu_episodes
+        ON
+            kitsu_episodes.episode_id = vidstreaming_downloads.episode_id
+        WHERE
+            kitsu_episodes.anime_id = anime.id
+    ) AS downloaded_episodes
+FROM
+    anime
+JOIN
+    kitsu_anime
+ON
+    kitsu_anime.id = anime.id
+";
+
+const GET_LIBRARY_ANIME_COUNT_SQL: &str = "
+SELECT
+    COUNT(*)
+FROM
+    anime;
+";
+
+const INSERT_LIBRARY_ANIME_SQL: &str = "
+INSERT INTO anime (
+    id,
+    status,
+    added_time
+) VALUES (
+    :id,
+    0,
+    :added_time
+) ON CONFLICT (id) DO NOTHING;
+";
+
+const SET_LIBRARY_ANIME_STATUS_SQL: &str = "
+UPDATE
+    anime
+SET
+    status = :status,
+    poster_path = :poster_path,
+    cover_path = :cover_path
+WHERE
+    id = :id;
+";
+
+const REMOVE_LIBRARY_ANIME_SQL: &str = "
+DELETE FROM
+    anime
+WHERE
+    id = :id;
+";
+
+const GET_LIBRARY_EPISODES_SQL: &str = "
+SELECT
+    kitsu_episodes.episode_id,
+    kitsu_episodes.number,
+    kitsu_episodes.title,
+    vidstreaming_downloads.download_time,
+    vidstreaming_download_queue.paused
+FROM
+    kitsu_episodes
+LEFT JOIN
+    vidstreaming_downloads
+ON
+    vidstreaming_downloads.episode_id = kitsu_episodes.episode_id AND
+    vidstreaming_downloads.anime_type = :anime_type
+LEFT JOIN
+    vidstreaming_download_queue
+ON
+    vidstreaming_download_queue.episode_id = kitsu_episodes.episode_id AND
+    vidstreaming_download_queue.anime_type = :anime_type
+WHERE
+    kitsu_episodes.anime_id = :anime_id
+ORDER BY
+    kitsu_episodes.number ASC;
+";
+
+const GET_IMAGE_CACHE_ENTRY_SQL: &str = "
+SELECT
+    url,
+    path,
+    size,
+    last_access
+FROM
+    image_cache
+WHERE
+    url = :url;
+";
+
+const GET_IMAGE_CACHE_ENTRIES_SQL: &str = "
+SELECT
+    url,
+    path,
+    size,
+    last_access
+FROM
+    image_cache
+ORDER BY
+    last_access ASC;
+";
+
+const GET_IMAGE_CACHE_SIZE_SQL: &str = "
+SELECT
+    IFNULL(SUM(size), 0)
+FROM
+    image_cache;
+";
+
+const UPSERT_IMAGE_CACHE_ENTRY_SQL: &str = "
+INSERT OR REPLACE INTO image_cache (
+    url,
+    path,
+    size,
+    last_access
+) VALUES (
+    :url,
+    :path,
+    :size,
+    :last_access
+);
+";
+
+const SET_IMAGE_CACHE_ENTRY_LAST_ACCESS_SQL: &str = "
+UPDATE
+    image_cache
+SET
+    last_access = :last_access
+WHERE
+    url = :url;
+";
+
+const REMOVE_IMAGE_CACHE_ENTRY_SQL: &str = "
+DELETE FROM
+    image_cache
+WHERE
+    url = :url;
+";
+
 #[derive(Debug, Clone)]
 pub struct Database {
     pub(crate) database: nd_async_rusqlite::AsyncConnection,
@@ -78,11 +541,17 @@ impl Database {
         let database = nd_async_rusqlite::AsyncConnection::builder()
             .open(path)
             .await?;
+        let path = path.to_path_buf();
         database
-            .access(|database| {
+            .access(move |database| {
                 database
                     .execute_batch(SETUP_SQL)
-                    .context("failed to setup database")
+                    .context("failed to setup database")?;
+
+                let version = self::migrations::get_version(database)?;
+                let backup_path = self::migrations::get_backup_path(&path, version);
+                self::migrations::migrate(database, Some(&backup_path))
+                    .context("failed to migrate database")
             })
             .await??;
 
@@ -98,16 +567,39 @@ impl Database {
             .access(move |database| {
                 let transaction = database.transaction()?;
                 {
+                    let mut release_slug_statement =
+                        transaction.prepare_cached(RELEASE_KITSU_ANIME_SLUG_SQL)?;
                     let mut statement = transaction.prepare_cached(UPSERT_KITSU_ANIME_SQL)?;
                     let anime = anime.as_slice();
                     for anime in anime.iter() {
+                        release_slug_statement.execute(named_params! {
+                            ":id": anime.id.get(),
+                            ":slug": anime.slug,
+                        })?;
                         statement.execute(named_params! {
                             ":id": anime.id.get(),
                             ":slug": anime.slug,
                             ":synopsis": anime.synopsis,
                             ":title": anime.title,
+                            ":titles": anime.titles.join("\n"),
+                            ":abbreviated_titles": anime.abbreviated_titles.join("\n"),
                             ":rating": anime.rating,
                             ":poster_large": anime.poster_large,
+                            ":cover_large": anime.cover_large,
+                            ":subtype": anime.subtype.as_ref().map(|subtype| subtype.as_str()),
+                            ":status": anime.status.as_ref().map(|status| status.as_str()),
+                            ":episode_count": anime.episode_count,
+                            ":episode_length": anime.episode_length,
+                            ":age_rating": anime.age_rating.as_ref().map(|age_rating| age_rating.as_str()),
+                            ":age_rating_guide": anime.age_rating_guide,
+                            ":start_date": anime.start_date,
+                            ":end_date": anime.end_date,
+                            ":popularity_rank": anime.popularity_rank,
+                            ":rating_rank": anime.rating_rank,
+                            ":user_count": anime.user_count,
+                            ":favorites_count": anime.favorites_count,
+                            ":youtube_video_id": anime.youtube_video_id,
+                            ":nsfw": anime.nsfw,
                             ":last_update": anime.last_update,
                         })?;
                     }
@@ -167,55 +659,150 @@ impl Database {
                         named_params! {
                             ":id": anime_id.get(),
                         },
-                        |row| {
-                            let last_update: u64 = row.get("last_update")?;
+                        |row| Ok(parse_kitsu_anime_row(row)?.map(Arc::new)),
+                    )
+                    .optional()?
+                    .transpose()?;
 
-                            /*
-                            match SystemTime::UNIX_EPOCH
-                                .elapsed()
-                                .map(|duration| duration.as_secs())
-                            {
-                                Ok(secs) => {
-                                    if secs.saturating_sub(last_update) > 10 * 60 {
+                Result::<_, anyhow::Error>::Ok(anime)
+            })
+            .await??;
 
-                                    }
+        Ok(anime)
+    }
 
-                                    duration
-                                }
-                                Err(err) => {
-                                    return Ok(anyhow::Error::from(err));
-                                }
-                            }
-                            */
+    /// Get the cached kitsu episodes of an anime, ordered by episode number.
+    pub async fn get_kitsu_anime_episodes(
+        &self,
+        anime_id: NonZeroU64,
+    ) -> anyhow::Result<Vec<KitsuAnimeEpisode>> {
+        let episodes = self
+            .database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(GET_KITSU_ANIME_EPISODES_SQL)?;
+                let rows = statement.query_map(
+                    named_params! {
+                        ":anime_id": anime_id.get(),
+                    },
+                    parse_kitsu_episode_row,
+                )?;
 
-                            let id = row.get("id")?;
-                            let id = match NonZeroU64::new(id).context("`id` is 0") {
-                                Ok(id) => id,
-                                Err(err) => {
-                                    return Ok(Err(err));
-                                }
-                            };
-                            let slug = row.get("slug")?;
-                            let synopsis = row.get("synopsis")?;
-                            let title = row.get("title")?;
-                            let rating = row.get("rating")?;
-                            let poster_large = row.get("poster_large")?;
-
-                            Ok(Result::<Arc<KitsuAnime>, anyhow::Error>::Ok(Arc::new(
-                                KitsuAnime {
-                                    id,
-                                    slug,
-                                    synopsis,
-                                    title,
-                                    rating,
-                                    poster_large,
-                                    last_update,
-                                },
-                            )))
+                let mut episodes = Vec::new();
+                for entry in rows {
+                    episodes.push(entry??);
+                }
+
+                Result::<_, anyhow::Error>::Ok(episodes)
+            })
+            .await??;
+
+        Ok(episodes)
+    }
+
+    /// Get when the full episode list of an anime was last fetched.
+    ///
+    /// Returns `None` if the anime is not cached or its episode list was never fetched.
+    pub async fn get_kitsu_anime_episodes_last_update(
+        &self,
+        anime_id: NonZeroU64,
+    ) -> anyhow::Result<Option<u64>> {
+        let last_update = self
+            .database
+            .access(move |database| {
+                let mut statement =
+                    database.prepare_cached(GET_KITSU_ANIME_EPISODES_LAST_UPDATE_SQL)?;
+                let last_update: Option<Option<u64>> = statement
+                    .query_row(
+                        named_params! {
+                            ":anime_id": anime_id.get(),
                         },
+                        |row| row.get(0),
                     )
-                    .optional()?
-                    .transpose()?;
+                    .optional()?;
+
+                Result::<_, anyhow::Error>::Ok(last_update.flatten())
+            })
+            .await??;
+
+        Ok(last_update)
+    }
+
+    /// Set when the full episode list of an anime was last fetched.
+    pub async fn set_kitsu_anime_episodes_last_update(
+        &self,
+        anime_id: NonZeroU64,
+        last_update: u64,
+    ) -> anyhow::Result<()> {
+        self.database
+            .access(move |database| {
+                let mut statement =
+                    database.prepare_cached(SET_KITSU_ANIME_EPISODES_LAST_UPDATE_SQL)?;
+                statement.execute(named_params! {
+                    ":anime_id": anime_id.get(),
+                    ":episodes_last_update": last_update,
+                })?;
+
+                Result::<_, anyhow::Error>::Ok(())
+            })
+            .await??;
+
+        Ok(())
+    }
+
+    /// Get the ids of library anime that are currently airing.
+    ///
+    /// Anime with an unknown status are included.
+    pub async fn get_airing_library_anime_ids(&self) -> anyhow::Result<Vec<NonZeroU64>> {
+        let ids = self
+            .database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(GET_AIRING_LIBRARY_ANIME_IDS_SQL)?;
+                let rows = statement.query_map([], |row| {
+                    let id = row.get(0)?;
+                    Ok(NonZeroU64::new(id).context("`id` is 0"))
+                })?;
+
+                let mut ids = Vec::new();
+                for entry in rows {
+                    ids.push(entry??);
+                }
+
+                Result::<_, anyhow::Error>::Ok(ids)
+            })
+            .await??;
+
+        Ok(ids)
+    }
+
+    /// Search cached kitsu anime by title, alternate titles, abbreviated titles and synopsis.
+    ///
+    /// Results are ordered by relevance, with title matches ranked highest.
+    pub async fn search_kitsu_anime(
+        &self,
+        query: &str,
+        limit: u64,
+    ) -> anyhow::Result<Vec<KitsuAnime>> {
+        let query = match make_fts_query(query) {
+            Some(query) => query,
+            None => return Ok(Vec::new()),
+        };
+
+        let anime = self
+            .database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(SEARCH_KITSU_ANIME_SQL)?;
+                let rows = statement.query_map(
+                    named_params! {
+                        ":query": query,
+                        ":limit": limit,
+                    },
+                    parse_kitsu_anime_row,
+                )?;
+
+                let mut anime = Vec::new();
+                for entry in rows {
+                    anime.push(entry??);
+                }
 
                 Result::<_, anyhow::Error>::Ok(anime)
             })
@@ -224,42 +811,1072 @@ impl Database {
         Ok(anime)
     }
 
-    /// Optimize the database.
-    pub async fn optimize(&self) -> anyhow::Result<()> {
+    /// Get the last time a query was searched on kitsu.
+    ///
+    /// This is the number of seconds from the unix epoch.
+    pub async fn get_kitsu_search_last_update(&self, query: &str) -> anyhow::Result<Option<u64>> {
+        let query = normalize_search_query(query);
+        let last_update = self
+            .database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(GET_KITSU_SEARCH_LAST_UPDATE_SQL)?;
+                let last_update = statement
+                    .query_row(
+                        named_params! {
+                            ":query": query,
+                        },
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+
+                Result::<_, anyhow::Error>::Ok(last_update)
+            })
+            .await??;
+
+        Ok(last_update)
+    }
+
+    /// Record that a query was searched on kitsu.
+    pub async fn upsert_kitsu_search(&self, query: &str, last_update: u64) -> anyhow::Result<()> {
+        let query = normalize_search_query(query);
         self.database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(UPSERT_KITSU_SEARCH_SQL)?;
+                statement.execute(named_params! {
+                    ":query": query,
+                    ":last_update": last_update,
+                })?;
+
+                Result::<_, anyhow::Error>::Ok(())
+            })
+            .await??;
+        Ok(())
+    }
+
+    /// Get the vidstreaming download queue, in order.
+    pub async fn get_vidstreaming_download_queue(
+        &self,
+    ) -> anyhow::Result<Vec<VidstreamingDownloadQueueEntry>> {
+        let entries = self
+            .database
             .access(|database| {
-                database.execute("PRAGMA OPTIMIZE;", [])?;
-                database.execute("VACUUM;", [])
+                let mut statement = database.prepare_cached(GET_VIDSTREAMING_DOWNLOAD_QUEUE_SQL)?;
+
+                let rows = statement.query_map([], |row| {
+                    let episode_id = row.get("episode_id")?;
+                    let episode_id = match NonZeroU64::new(episode_id).context("`episode_id` is 0")
+                    {
+                        Ok(episode_id) => episode_id,
+                        Err(err) => {
+                            return Ok(Err(err));
+                        }
+                    };
+                    let anime_id = row.get("anime_id")?;
+                    let anime_id = match NonZeroU64::new(anime_id).context("`anime_id` is 0") {
+                        Ok(anime_id) => anime_id,
+                        Err(err) => {
+                            return Ok(Err(err));
+                        }
+                    };
+                    let anime_type: String = row.get("anime_type")?;
+                    let anime_type = match anime_type
+                        .parse::<AnimeType>()
+                        .context("invalid `anime_type`")
+                    {
+                        Ok(anime_type) => anime_type,
+                        Err(err) => {
+                            return Ok(Err(err));
+                        }
+                    };
+                    let anime_slug = row.get("anime_slug")?;
+                    let episode_number = row.get("episode_number")?;
+                    let vidstreaming_slug = row.get("vidstreaming_slug")?;
+                    let length_minutes = row.get("length_minutes")?;
+                    let max_resolution = row.get("max_resolution")?;
+                    let max_bandwidth = row.get("max_bandwidth")?;
+                    let preferred_codecs = row.get("preferred_codecs")?;
+                    let paused = row.get("paused")?;
+
+                    Ok(Result::<_, anyhow::Error>::Ok(
+                        VidstreamingDownloadQueueEntry {
+                            episode_id,
+                            anime_id,
+                            anime_type,
+                            anime_slug,
+                            episode_number,
+                            vidstreaming_slug,
+                            length_minutes,
+                            max_resolution,
+                            max_bandwidth,
+                            preferred_codecs,
+                            paused,
+                        },
+                    ))
+                })?;
+
+                let mut entries = Vec::new();
+                for entry in rows {
+                    entries.push(entry??);
+                }
+
+                Result::<_, anyhow::Error>::Ok(entries)
             })
-            .await
-            .context("failed to access database")?
-            .context("failed to execute shutdown commands")?;
+            .await??;
+
+        Ok(entries)
+    }
+
+    /// Add an entry to the end of the vidstreaming download queue.
+    pub async fn push_vidstreaming_download_queue_entry(
+        &self,
+        entry: VidstreamingDownloadQueueEntry,
+    ) -> anyhow::Result<()> {
+        self.database
+            .access(move |database| {
+                let mut statement =
+                    database.prepare_cached(PUSH_VIDSTREAMING_DOWNLOAD_QUEUE_ENTRY_SQL)?;
+                statement.execute(named_params! {
+                    ":episode_id": entry.episode_id.get(),
+                    ":anime_type": entry.anime_type.as_str(),
+                    ":anime_slug": entry.anime_slug,
+                    ":episode_number": entry.episode_number,
+                    ":vidstreaming_slug": entry.vidstreaming_slug,
+                    ":length_minutes": entry.length_minutes,
+                    ":max_resolution": entry.max_resolution,
+                    ":max_bandwidth": entry.max_bandwidth,
+                    ":preferred_codecs": entry.preferred_codecs,
+                    ":paused": entry.paused,
+                })?;
 
+                Result::<_, anyhow::Error>::Ok(())
+            })
+            .await??;
         Ok(())
     }
 
-    /// Shut down the database.
+    /// Set the order of the vidstreaming download queue.
     ///
-    /// Should only be called once.
-    pub async fn shutdown(&self) -> anyhow::Result<()> {
-        let results = [
-            self.optimize().await.context("failed to optimize database"),
-            self.database
-                .close()
-                .await
-                .context("failed to send close the database"),
-        ];
+    /// Entries are given positions in the order of the provided episode ids and anime types.
+    pub async fn set_vidstreaming_download_queue_order(
+        &self,
+        entries: Vec<(NonZeroU64, AnimeType)>,
+    ) -> anyhow::Result<()> {
+        self.database
+            .access(move |database| {
+                let transaction = database.transaction()?;
+                {
+                    let mut statement =
+                        transaction.prepare_cached(SET_VIDSTREAMING_DOWNLOAD_QUEUE_POSITION_SQL)?;
+                    for (position, (episode_id, anime_type)) in entries.iter().enumerate() {
+                        statement.execute(named_params! {
+                            ":episode_id": episode_id.get(),
+                            ":anime_type": anime_type.as_str(),
+                            ":position": position,
+                        })?;
+                    }
+                }
+                transaction.commit()?;
 
-        for result in results.iter() {
-            if let Err(error) = result.as_ref() {
-                error!("{error}");
-            }
-        }
+                Result::<_, anyhow::Error>::Ok(())
+            })
+            .await??;
+        Ok(())
+    }
 
-        for result in results {
-            result?;
-        }
+    /// Set whether an entry in the vidstreaming download queue is paused.
+    pub async fn set_vidstreaming_download_queue_entry_paused(
+        &self,
+        episode_id: NonZeroU64,
+        anime_type: AnimeType,
+        paused: bool,
+    ) -> anyhow::Result<()> {
+        self.database
+            .access(move |database| {
+                let mut statement =
+                    database.prepare_cached(SET_VIDSTREAMING_DOWNLOAD_QUEUE_ENTRY_PAUSED_SQL)?;
+                statement.execute(named_params! {
+                    ":episode_id": episode_id.get(),
+                    ":anime_type": anime_type.as_str(),
+                    ":paused": paused,
+                })?;
 
+                Result::<_, anyhow::Error>::Ok(())
+            })
+            .await??;
         Ok(())
     }
+
+    /// Remove an entry from the vidstreaming download queue.
+    pub async fn remove_vidstreaming_download_queue_entry(
+        &self,
+        episode_id: NonZeroU64,
+        anime_type: AnimeType,
+    ) -> anyhow::Result<()> {
+        self.database
+            .access(move |database| {
+                let mut statement =
+                    database.prepare_cached(REMOVE_VIDSTREAMING_DOWNLOAD_QUEUE_ENTRY_SQL)?;
+                statement.execute(named_params! {
+                    ":episode_id": episode_id.get(),
+                    ":anime_type": anime_type.as_str(),
+                })?;
+
+                Result::<_, anyhow::Error>::Ok(())
+            })
+            .await??;
+        Ok(())
+    }
+
+    /// Record a completed vidstreaming download.
+    pub async fn upsert_vidstreaming_download(
+        &self,
+        download: VidstreamingDownload,
+    ) -> anyhow::Result<()> {
+        self.database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(UPSERT_VIDSTREAMING_DOWNLOAD_SQL)?;
+                statement.execute(named_params! {
+                    ":episode_id": download.episode_id.get(),
+                    ":anime_type": download.anime_type.as_str(),
+                    ":source_origin": download.source_origin,
+                    ":source_label": download.source_label,
+                    ":source_url": download.source_url,
+                    ":download_time": download.download_time,
+                })?;
+
+                Result::<_, anyhow::Error>::Ok(())
+            })
+            .await??;
+        Ok(())
+    }
+
+    /// Get the kitsu anime an id on an external site maps to.
+    pub async fn get_kitsu_mapping(
+        &self,
+        external_site: &str,
+        external_id: &str,
+    ) -> anyhow::Result<Option<NonZeroU64>> {
+        let external_site = external_site.to_string();
+        let external_id = external_id.to_string();
+        let anime_id = self
+            .database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(GET_KITSU_MAPPING_SQL)?;
+
+                let anime_id = statement
+                    .query_row(
+                        named_params! {
+                            ":external_site": external_site,
+                            ":external_id": external_id,
+                        },
+                        |row| {
+                            let anime_id = row.get("anime_id")?;
+                            Ok(NonZeroU64::new(anime_id).context("`anime_id` is 0"))
+                        },
+                    )
+                    .optional()?
+                    .transpose()?;
+
+                Result::<_, anyhow::Error>::Ok(anime_id)
+            })
+            .await??;
+
+        Ok(anime_id)
+    }
+
+    /// Insert or replace the mapping of an id on an external site to a kitsu anime.
+    pub async fn upsert_kitsu_mapping(
+        &self,
+        external_site: &str,
+        external_id: &str,
+        anime_id: NonZeroU64,
+        last_update: u64,
+    ) -> anyhow::Result<()> {
+        let external_site = external_site.to_string();
+        let external_id = external_id.to_string();
+        self.database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(UPSERT_KITSU_MAPPING_SQL)?;
+                statement.execute(named_params! {
+                    ":external_site": external_site,
+                    ":external_id": external_id,
+                    ":anime_id": anime_id.get(),
+                    ":last_update": last_update,
+                })?;
+
+                Result::<_, anyhow::Error>::Ok(())
+            })
+            .await??;
+
+        Ok(())
+    }
+
+    /// Get the vidstreaming anime mapping for a kitsu anime.
+    pub async fn get_vidstreaming_anime_mapping(
+        &self,
+        anime_id: NonZeroU64,
+        anime_type: AnimeType,
+    ) -> anyhow::Result<Option<VidstreamingAnimeMapping>> {
+        let mapping = self
+            .database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(GET_VIDSTREAMING_ANIME_MAPPING_SQL)?;
+
+                let mapping = statement
+                    .query_row(
+                        named_params! {
+                            ":anime_id": anime_id.get(),
+                            ":anime_type": anime_type.as_str(),
+                        },
+                        |row| {
+                            let slug = row.get("slug")?;
+                            let score = row.get("score")?;
+                            let manual = row.get("manual")?;
+
+                            Ok(VidstreamingAnimeMapping {
+                                anime_id,
+                                anime_type,
+                                slug,
+                                score,
+                                manual,
+                            })
+                        },
+                    )
+                    .optional()?;
+
+                Result::<_, anyhow::Error>::Ok(mapping)
+            })
+            .await??;
+
+        Ok(mapping)
+    }
+
+    /// Insert or replace a vidstreaming anime mapping.
+    pub async fn upsert_vidstreaming_anime_mapping(
+        &self,
+        mapping: VidstreamingAnimeMapping,
+    ) -> anyhow::Result<()> {
+        self.database
+            .access(move |database| {
+                let mut statement =
+                    database.prepare_cached(UPSERT_VIDSTREAMING_ANIME_MAPPING_SQL)?;
+                statement.execute(named_params! {
+                    ":anime_id": mapping.anime_id.get(),
+                    ":anime_type": mapping.anime_type.as_str(),
+                    ":slug": mapping.slug,
+                    ":score": mapping.score,
+                    ":manual": mapping.manual,
+                })?;
+
+                Result::<_, anyhow::Error>::Ok(())
+            })
+            .await??;
+        Ok(())
+    }
+
+    /// Remove a vidstreaming anime mapping.
+    pub async fn remove_vidstreaming_anime_mapping(
+        &self,
+        anime_id: NonZeroU64,
+        anime_type: AnimeType,
+    ) -> anyhow::Result<()> {
+        self.database
+            .access(move |database| {
+                let mut statement =
+                    database.prepare_cached(REMOVE_VIDSTREAMING_ANIME_MAPPING_SQL)?;
+                statement.execute(named_params! {
+                    ":anime_id": anime_id.get(),
+                    ":anime_type": anime_type.as_str(),
+                })?;
+
+                Result::<_, anyhow::Error>::Ok(())
+            })
+            .await??;
+        Ok(())
+    }
+
+    /// Get a page of the library.
+    ///
+    /// Returns the anime in the page and the total number of anime in the library.
+    pub async fn get_library_anime_page(
+        &self,
+        sort: LibrarySort,
+        descending: bool,
+        offset: u64,
+        limit: u64,
+    ) -> anyhow::Result<(Vec<LibraryAnime>, u64)> {
+        let order = if descending { "DESC" } else { "ASC" };
+        let sql = format!(
+            "{GET_LIBRARY_ANIME_SQL} ORDER BY {} {order} NULLS LAST, anime.id ASC LIMIT :limit OFFSET :offset;",
+            sort.as_sql()
+        );
+
+        let page = self
+            .database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(&sql)?;
+                let rows = statement.query_map(
+                    named_params! {
+                        ":limit": limit,
+                        ":offset": offset,
+                    },
+                    parse_library_anime_row,
+                )?;
+
+                let mut anime = Vec::new();
+                for entry in rows {
+                    anime.push(entry??);
+                }
+
+                let mut statement = database.prepare_cached(GET_LIBRARY_ANIME_COUNT_SQL)?;
+                let total = statement.query_row([], |row| row.get(0))?;
+
+                Result::<_, anyhow::Error>::Ok((anime, total))
+            })
+            .await??;
+
+        Ok(page)
+    }
+
+    /// Get an anime in the library.
+    pub async fn get_library_anime(&self, id: NonZeroU64) -> anyhow::Result<Option<LibraryAnime>> {
+        let anime = self
+            .database
+            .access(move |database| {
+                let mut statement = database
+                    .prepare_cached(&format!("{GET_LIBRARY_ANIME_SQL} WHERE anime.id = :id;"))?;
+                let anime = statement
+                    .query_row(
+                        named_params! {
+                            ":id": id.get(),
+                        },
+                        parse_library_anime_row,
+                    )
+                    .optional()?
+                    .transpose()?;
+
+                Result::<_, anyhow::Error>::Ok(anime)
+            })
+            .await??;
+
+        Ok(anime)
+    }
+
+    /// Get all installed anime in the library.
+    pub async fn get_installed_library_anime(&self) -> anyhow::Result<Vec<LibraryAnime>> {
+        let anime = self
+            .database
+            .access(move |database| {
+                let mut statement = database
+                    .prepare_cached(&format!("{GET_LIBRARY_ANIME_SQL} WHERE anime.status = 1;"))?;
+                let rows = statement.query_map([], parse_library_anime_row)?;
+
+                let mut anime = Vec::new();
+                for entry in rows {
+                    anime.push(entry??);
+                }
+
+                Result::<_, anyhow::Error>::Ok(anime)
+            })
+            .await??;
+
+        Ok(anime)
+    }
+
+    /// Add an anime to the library, as not installed.
+    ///
+    /// This does nothing if the anime is already in the library.
+    pub async fn insert_library_anime(
+        &self,
+        id: NonZeroU64,
+        added_time: u64,
+    ) -> anyhow::Result<()> {
+        self.database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(INSERT_LIBRARY_ANIME_SQL)?;
+                statement.execute(named_params! {
+                    ":id": id.get(),
+                    ":added_time": added_time,
+                })?;
+
+                Result::<_, anyhow::Error>::Ok(())
+            })
+            .await??;
+        Ok(())
+    }
+
+    /// Set the status of an anime in the library.
+    ///
+    /// Installed anime must have their images on disk before this is called.
+    pub async fn set_library_anime_status(
+        &self,
+        id: NonZeroU64,
+        installed: bool,
+        poster_path: Option<String>,
+        cover_path: Option<String>,
+    ) -> anyhow::Result<()> {
+        self.database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(SET_LIBRARY_ANIME_STATUS_SQL)?;
+                statement.execute(named_params! {
+                    ":id": id.get(),
+                    ":status": installed,
+                    ":poster_path": poster_path,
+                    ":cover_path": cover_path,
+                })?;
+
+                Result::<_, anyhow::Error>::Ok(())
+            })
+            .await??;
+        Ok(())
+    }
+
+    /// Remove an anime from the library.
+    pub async fn remove_library_anime(&self, id: NonZeroU64) -> anyhow::Result<()> {
+        self.database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(REMOVE_LIBRARY_ANIME_SQL)?;
+                statement.execute(named_params! {
+                    ":id": id.get(),
+                })?;
+
+                Result::<_, anyhow::Error>::Ok(())
+            })
+            .await??;
+        Ok(())
+    }
+
+    /// Get the episodes of an anime, with the download status of the given track.
+    pub async fn get_library_episodes(
+        &self,
+        anime_id: NonZeroU64,
+        anime_type: AnimeType,
+    ) -> anyhow::Result<Vec<LibraryEpisode>> {
+        let episodes = self
+            .database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(GET_LIBRARY_EPISODES_SQL)?;
+                let rows = statement.query_map(
+                    named_params! {
+                        ":anime_id": anime_id.get(),
+                        ":anime_type": anime_type.as_str(),
+                    },
+                    |row| {
+                        let episode_id = row.get("episode_id")?;
+                        let episode_id =
+                            match NonZeroU64::new(episode_id).context("`episode_id` is 0") {
+                                Ok(episode_id) => episode_id,
+                                Err(err) => {
+                                    return Ok(Err(err));
+                                }
+                            };
+                        let number = row.get("number")?;
+                        let title = row.get("title")?;
+                        let download_time: Option<u64> = row.get("download_time")?;
+                        let paused: Option<bool> = row.get("paused")?;
+
+                        let status = match (download_time, paused) {
+                            (Some(_), _) => LibraryEpisodeStatus::Downloaded,
+                            (None, Some(true)) => LibraryEpisodeStatus::Paused,
+                            (None, Some(false)) => LibraryEpisodeStatus::Queued,
+                            (None, None) => LibraryEpisodeStatus::Missing,
+                        };
+
+                        Ok(Result::<_, anyhow::Error>::Ok(LibraryEpisode {
+                            episode_id,
+                            number,
+                            title,
+                            status,
+                            download_time,
+                        }))
+                    },
+                )?;
+
+                let mut episodes = Vec::new();
+                for episode in rows {
+                    episodes.push(episode??);
+                }
+
+                Result::<_, anyhow::Error>::Ok(episodes)
+            })
+            .await??;
+
+        Ok(episodes)
+    }
+
+    /// Get a cached image by its remote url.
+    pub async fn get_image_cache_entry(
+        &self,
+        url: Box<str>,
+    ) -> anyhow::Result<Option<ImageCacheEntry>> {
+        let entry = self
+            .database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(GET_IMAGE_CACHE_ENTRY_SQL)?;
+                let entry = statement
+                    .query_row(
+                        named_params! {
+                            ":url": url,
+                        },
+                        parse_image_cache_entry_row,
+                    )
+                    .optional()?;
+
+                Result::<_, anyhow::Error>::Ok(entry)
+            })
+            .await??;
+
+        Ok(entry)
+    }
+
+    /// Get all cached images, least recently used first.
+    pub async fn get_image_cache_entries(&self) -> anyhow::Result<Vec<ImageCacheEntry>> {
+        let entries = self
+            .database
+            .access(|database| {
+                let mut statement = database.prepare_cached(GET_IMAGE_CACHE_ENTRIES_SQL)?;
+                let entries = statement
+                    .query_map([], parse_image_cache_entry_row)?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Result::<_, anyhow::Error>::Ok(entries)
+            })
+            .await??;
+
+        Ok(entries)
+    }
+
+    /// Get the total size of all cached images, in bytes.
+    pub async fn get_image_cache_size(&self) -> anyhow::Result<u64> {
+        let size = self
+            .database
+            .access(|database| {
+                let mut statement = database.prepare_cached(GET_IMAGE_CACHE_SIZE_SQL)?;
+                let size = statement.query_row([], |row| row.get(0))?;
+
+                Result::<_, anyhow::Error>::Ok(size)
+            })
+            .await??;
+
+        Ok(size)
+    }
+
+    /// Insert or replace a cached image.
+    pub async fn upsert_image_cache_entry(&self, entry: ImageCacheEntry) -> anyhow::Result<()> {
+        self.database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(UPSERT_IMAGE_CACHE_ENTRY_SQL)?;
+                statement.execute(named_params! {
+                    ":url": entry.url,
+                    ":path": entry.path,
+                    ":size": entry.size,
+                    ":last_access": entry.last_access,
+                })?;
+
+                Result::<_, anyhow::Error>::Ok(())
+            })
+            .await??;
+        Ok(())
+    }
+
+    /// Get many image cache entries, setting the last access time of those that exist.
+    ///
+    /// Results are in the same order as the given urls.
+    pub async fn touch_image_cache_entries(
+        &self,
+        urls: Vec<Box<str>>,
+        last_access: u64,
+    ) -> anyhow::Result<Vec<Option<ImageCacheEntry>>> {
+        let entries = self
+            .database
+            .access(move |database| {
+                let transaction = database.transaction()?;
+                let mut entries = Vec::with_capacity(urls.len());
+                {
+                    let mut get_statement =
+                        transaction.prepare_cached(GET_IMAGE_CACHE_ENTRY_SQL)?;
+                    let mut set_statement =
+                        transaction.prepare_cached(SET_IMAGE_CACHE_ENTRY_LAST_ACCESS_SQL)?;
+                    for url in urls {
+                        let entry = get_statement
+                            .query_row(
+                                named_params! {
+                                    ":url": url,
+                                },
+                                parse_image_cache_entry_row,
+                            )
+                            .optional()?;
+                        if entry.is_some() {
+                            set_statement.execute(named_params! {
+                                ":url": url,
+                                ":last_access": last_access,
+                            })?;
+                        }
+                        entries.push(entry);
+                    }
+                }
+                transaction.commit()?;
+
+                Result::<_, anyhow::Error>::Ok(entries)
+            })
+            .await??;
+
+        Ok(entries)
+    }
+
+    /// Set the time a cached image was last used.
+    pub async fn set_image_cache_entry_last_access(
+        &self,
+        url: Box<str>,
+        last_access: u64,
+    ) -> anyhow::Result<()> {
+        self.database
+            .access(move |database| {
+                let mut statement =
+                    database.prepare_cached(SET_IMAGE_CACHE_ENTRY_LAST_ACCESS_SQL)?;
+                statement.execute(named_params! {
+                    ":url": url,
+                    ":last_access": last_access,
+                })?;
+
+                Result::<_, anyhow::Error>::Ok(())
+            })
+            .await??;
+        Ok(())
+    }
+
+    /// Remove a cached image.
+    pub async fn remove_image_cache_entry(&self, url: Box<str>) -> anyhow::Result<()> {
+        self.database
+            .access(move |database| {
+                let mut statement = database.prepare_cached(REMOVE_IMAGE_CACHE_ENTRY_SQL)?;
+                statement.execute(named_params! {
+                    ":url": url,
+                })?;
+
+                Result::<_, anyhow::Error>::Ok(())
+            })
+            .await??;
+        Ok(())
+    }
+
+    /// Optimize the database.
+    pub async fn optimize(&self) -> anyhow::Result<()> {
+        self.database
+            .access(|database| {
+                database.execute("PRAGMA OPTIMIZE;", [])?;
+                database.execute("VACUUM;", [])
+            })
+            .await
+            .context("failed to access database")?
+            .context("failed to execute shutdown commands")?;
+
+        Ok(())
+    }
+
+    /// Shut down the database.
+    ///
+    /// Should only be called once.
+    pub async fn shutdown(&self) -> anyhow::Result<()> {
+        let results = [
+            self.optimize().await.context("failed to optimize database"),
+            self.database
+                .close()
+                .await
+                .context("failed to send close the database"),
+        ];
+
+        for result in results.iter() {
+            if let Err(error) = result.as_ref() {
+                error!("{error}");
+            }
+        }
+
+        for result in results {
+            result?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a row of [`GET_LIBRARY_ANIME_SQL`].
+fn parse_library_anime_row(
+    row: &nd_async_rusqlite::rusqlite::Row<'_>,
+) -> nd_async_rusqlite::rusqlite::Result<anyhow::Result<LibraryAnime>> {
+    let id = row.get("id")?;
+    let id = match NonZeroU64::new(id).context("`id` is 0") {
+        Ok(id) => id,
+        Err(err) => {
+            return Ok(Err(err));
+        }
+    };
+    let installed = row.get("status")?;
+    let added_time = row.get("added_time")?;
+    let poster_path = row.get("poster_path")?;
+    let cover_path = row.get("cover_path")?;
+    let slug = row.get("slug")?;
+    let title = row.get("title")?;
+    let rating = row.get("rating")?;
+    let downloaded_episodes = row.get("downloaded_episodes")?;
+
+    Ok(Ok(LibraryAnime {
+        id,
+        installed,
+        added_time,
+        poster_path,
+        cover_path,
+        slug,
+        title,
+        rating,
+        downloaded_episodes,
+    }))
+}
+
+/// Parse a row of the `image_cache` table.
+fn parse_image_cache_entry_row(
+    row: &nd_async_rusqlite::rusqlite::Row<'_>,
+) -> nd_async_rusqlite::rusqlite::Result<ImageCacheEntry> {
+    let url = row.get("url")?;
+    let path = row.get("path")?;
+    let size = row.get("size")?;
+    let last_access = row.get("last_access")?;
+
+    Ok(ImageCacheEntry {
+        url,
+        path,
+        size,
+        last_access,
+    })
+}
+
+/// Parse a row of the `kitsu_anime` table.
+fn parse_kitsu_anime_row(
+    row: &nd_async_rusqlite::rusqlite::Row<'_>,
+) -> nd_async_rusqlite::rusqlite::Result<anyhow::Result<KitsuAnime>> {
+    let id = row.get("id")?;
+    let id = match NonZeroU64::new(id).context("`id` is 0") {
+        Ok(id) => id,
+        Err(err) => {
+            return Ok(Err(err));
+        }
+    };
+    let slug = row.get("slug")?;
+    let synopsis = row.get("synopsis")?;
+    let title = row.get("title")?;
+    let titles: String = row.get("titles")?;
+    let abbreviated_titles: String = row.get("abbreviated_titles")?;
+    let rating = row.get("rating")?;
+    let poster_large = row.get("poster_large")?;
+    let cover_large = row.get("cover_large")?;
+    let subtype: Option<String> = row.get("subtype")?;
+    let status: Option<String> = row.get("status")?;
+    let episode_count = row.get("episode_count")?;
+    let episode_length = row.get("episode_length")?;
+    let age_rating: Option<String> = row.get("age_rating")?;
+    let age_rating_guide = row.get("age_rating_guide")?;
+    let start_date = row.get("start_date")?;
+    let end_date = row.get("end_date")?;
+    let popularity_rank = row.get("popularity_rank")?;
+    let rating_rank = row.get("rating_rank")?;
+    let user_count = row.get("user_count")?;
+    let favorites_count = row.get("favorites_count")?;
+    let youtube_video_id = row.get("youtube_video_id")?;
+    let nsfw = row.get("nsfw")?;
+    let last_update = row.get("last_update")?;
+
+    Ok(Ok(KitsuAnime {
+        id,
+        slug,
+        synopsis,
+        title,
+        titles: split_titles(&titles),
+        abbreviated_titles: split_titles(&abbreviated_titles),
+        rating,
+        poster_large,
+        cover_large,
+        subtype: subtype.as_deref().map(kitsu::Subtype::from),
+        status: status.as_deref().map(kitsu::Status::from),
+        episode_count,
+        episode_length,
+        age_rating: age_rating.as_deref().map(kitsu::AgeRating::from),
+        age_rating_guide,
+        start_date,
+        end_date,
+        popularity_rank,
+        rating_rank,
+        user_count,
+        favorites_count,
+        youtube_video_id,
+        nsfw,
+        last_update,
+    }))
+}
+
+/// Parse a row of the `kitsu_episodes` table.
+fn parse_kitsu_episode_row(
+    row: &nd_async_rusqlite::rusqlite::Row<'_>,
+) -> nd_async_rusqlite::rusqlite::Result<anyhow::Result<KitsuAnimeEpisode>> {
+    let episode_id = row.get("episode_id")?;
+    let episode_id = match NonZeroU64::new(episode_id).context("`episode_id` is 0") {
+        Ok(episode_id) => episode_id,
+        Err(err) => {
+            return Ok(Err(err));
+        }
+    };
+    let anime_id = row.get("anime_id")?;
+    let anime_id = match NonZeroU64::new(anime_id).context("`anime_id` is 0") {
+        Ok(anime_id) => anime_id,
+        Err(err) => {
+            return Ok(Err(err));
+        }
+    };
+    let title = row.get("title")?;
+    let synopsis = row.get("synopsis")?;
+    let length_minutes = row.get("length_minutes")?;
+    let number = row.get("number")?;
+    let thumbnail_original = row.get("thumbnail_original")?;
+    let last_update = row.get("last_update")?;
+
+    Ok(Ok(KitsuAnimeEpisode {
+        episode_id,
+        anime_id,
+        title,
+        synopsis,
+        length_minutes,
+        number,
+        thumbnail_original,
+        last_update,
+    }))
+}
+
+/// Split titles stored as newline-separated text.
+fn split_titles(titles: &str) -> Vec<String> {
+    titles
+        .lines()
+        .filter(|title| !title.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Normalize a search query, so equivalent queries share a key.
+fn normalize_search_query(query: &str) -> String {
+    query.trim().to_lowercase()
+}
+
+/// Make an FTS5 query from user input.
+///
+/// Each word is matched as a prefix, and all words must match.
+/// Returns `None` if the input has no words.
+fn make_fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{word}\"*"))
+        .collect();
+
+    if terms.is_empty() {
+        return None;
+    }
+
+    Some(terms.join(" "))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn make_kitsu_anime(id: u64, slug: &str, title: &str) -> KitsuAnime {
+        KitsuAnime {
+            id: NonZeroU64::new(id).unwrap(),
+            slug: slug.into(),
+            synopsis: None,
+            title: title.into(),
+            titles: Vec::new(),
+            abbreviated_titles: Vec::new(),
+            rating: None,
+            poster_large: format!("https://media.kitsu.app/anime/poster_images/{id}/large.jpg"),
+            cover_large: None,
+            subtype: None,
+            status: None,
+            episode_count: None,
+            episode_length: None,
+            age_rating: None,
+            age_rating_guide: None,
+            start_date: None,
+            end_date: None,
+            popularity_rank: None,
+            rating_rank: None,
+            user_count: None,
+            favorites_count: None,
+            youtube_video_id: None,
+            nsfw: false,
+            last_update: 100,
+        }
+    }
+
+    #[tokio::test]
+    async fn upsert_kitsu_anime_reassigned_slug() {
+        let directory = std::env::temp_dir().join(format!(
+            "bewu-database-test-reassigned-slug-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).expect("failed to create directory");
+        let database = Database::new(directory.join("database.db"))
+            .await
+            .expect("failed to open database");
+
+        database
+            .upsert_kitsu_anime(make_kitsu_anime(1, "shared-slug", "Old Anime"))
+            .await
+            .expect("failed to upsert old anime");
+
+        // Kitsu gave the slug to another anime, in the same batch as an unrelated anime.
+        let anime: Arc<[KitsuAnime]> = Arc::from(vec![
+            make_kitsu_anime(2, "shared-slug", "New Anime"),
+            make_kitsu_anime(3, "other-slug", "Other Anime"),
+        ]);
+        database
+            .upsert_kitsu_anime(anime)
+            .await
+            .expect("failed to upsert anime with a reassigned slug");
+
+        let new_anime = database
+            .get_kitsu_anime(NonZeroU64::new(2).unwrap())
+            .await
+            .expect("failed to get new anime")
+            .expect("missing new anime");
+        assert_eq!(new_anime.slug, "shared-slug");
+
+        let other_anime = database
+            .get_kitsu_anime(NonZeroU64::new(3).unwrap())
+            .await
+            .expect("failed to get other anime")
+            .expect("missing other anime");
+        assert_eq!(other_anime.title, "Other Anime");
+
+        // The old anime is kept, but gives up its slug and is marked stale.
+        let old_anime = database
+            .get_kitsu_anime(NonZeroU64::new(1).unwrap())
+            .await
+            .expect("failed to get old anime")
+            .expect("missing old anime");
+        assert_eq!(old_anime.title, "Old Anime");
+        assert_ne!(old_anime.slug, "shared-slug");
+        assert_eq!(old_anime.last_update, 0);
+
+        // The renamed anime is still searchable.
+        let results = database
+            .search_kitsu_anime("old anime", 20)
+            .await
+            .expect("failed to search");
+        assert!(results.iter().any(|anime| anime.id.get() == 1));
+
+        database
+            .shutdown()
+            .await
+            .expect("failed to shutdown database");
+        std::fs::remove_dir_all(&directory).expect("failed to remove directory");
+    }
 }
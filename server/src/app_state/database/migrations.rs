@@ -0,0 +1,282 @@
+use anyhow::ensure;
+use anyhow::Context;
+use nd_async_rusqlite::rusqlite::Connection;
+use std::path::Path;
+use std::path::PathBuf;
+use tracing::info;
+
+/// The migrations, in order.
+///
+/// Applying the migration at index `n` moves the database from version `n` to version `n + 1`.
+const MIGRATIONS: &[&str] = &[
+    include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/sql/migrations/0001_initial.sql"
+    )),
+    include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/sql/migrations/0002_vidstreaming_downloads.sql"
+    )),
+    include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/sql/migrations/0003_vidstreaming_anime_mappings.sql"
+    )),
+    include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/sql/migrations/0004_library.sql"
+    )),
+    include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/sql/migrations/0005_image_cache.sql"
+    )),
+    include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/sql/migrations/0006_kitsu_anime_search.sql"
+    )),
+    include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/sql/migrations/0007_kitsu_anime_attributes.sql"
+    )),
+    include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/sql/migrations/0008_kitsu_mappings.sql"
+    )),
+    include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/sql/migrations/0009_kitsu_anime_episodes_last_update.sql"
+    )),
+];
+
+/// Get the schema version that all migrations lead to.
+pub fn latest_version() -> u32 {
+    // There will never be more than u32::MAX migrations.
+    MIGRATIONS.len() as u32
+}
+
+/// Get the schema version of a database.
+pub fn get_version(connection: &Connection) -> anyhow::Result<u32> {
+    let version = connection
+        .query_row("PRAGMA user_version;", [], |row| row.get(0))
+        .context("failed to get schema version")?;
+    Ok(version)
+}
+
+/// Migrate a database to the latest schema version.
+///
+/// Each migration is applied in its own transaction,
+/// so a failed migration leaves the database at the last successful version.
+/// If a backup path is given and the database is not empty,
+/// a backup is made there before migrating.
+///
+/// # Errors
+///
+/// Returns an error if the database is newer than the latest known version.
+pub fn migrate(connection: &mut Connection, backup_path: Option<&Path>) -> anyhow::Result<()> {
+    let version = get_version(connection)?;
+    let latest_version = latest_version();
+    ensure!(
+        version <= latest_version,
+        "the database schema version {version} is newer than the latest known version {latest_version}"
+    );
+    if version == latest_version {
+        return Ok(());
+    }
+
+    if let Some(backup_path) = backup_path {
+        let is_empty =
+            connection.query_row("SELECT COUNT(*) = 0 FROM sqlite_schema;", [], |row| {
+                row.get::<_, bool>(0)
+            })?;
+        if !is_empty {
+            backup(connection, backup_path)?;
+        }
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let next_version = index as u32 + 1;
+        info!("migrating database to schema version {next_version}");
+
+        let transaction = connection.transaction()?;
+        transaction
+            .execute_batch(migration)
+            .with_context(|| format!("failed to apply migration {next_version}"))?;
+        transaction.pragma_update(None, "user_version", next_version)?;
+        transaction.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Back up a database to the given path.
+///
+/// Any existing file at the path is replaced.
+fn backup(connection: &Connection, backup_path: &Path) -> anyhow::Result<()> {
+    info!("backing up database to \"{}\"", backup_path.display());
+
+    match std::fs::remove_file(backup_path) {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => {
+            return Err(error).with_context(|| {
+                format!("failed to remove old backup \"{}\"", backup_path.display())
+            });
+        }
+    }
+
+    let backup_path_str = backup_path
+        .to_str()
+        .context("backup path is not valid unicode")?;
+    connection
+        .execute("VACUUM INTO ?1;", [backup_path_str])
+        .with_context(|| {
+            format!(
+                "failed to back up database to \"{}\"",
+                backup_path.display()
+            )
+        })?;
+
+    Ok(())
+}
+
+/// Get the path to back up a database to before migrating from the given version.
+pub fn get_backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut backup_path = path.as_os_str().to_owned();
+    backup_path.push(format!(".v{version}.bak"));
+    backup_path.into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FIXTURE_V0: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/test_data/database-v0.sql"
+    ));
+
+    fn get_table_columns(connection: &Connection, table: &str) -> Vec<String> {
+        let mut statement = connection
+            .prepare(&format!("PRAGMA table_info({table});"))
+            .expect("failed to prepare");
+        statement
+            .query_map([], |row| row.get("name"))
+            .expect("failed to query")
+            .collect::<Result<_, _>>()
+            .expect("failed to get column")
+    }
+
+    #[test]
+    fn migrate_fixture_from_v0() {
+        let mut connection = Connection::open_in_memory().expect("failed to open database");
+        connection
+            .execute_batch(FIXTURE_V0)
+            .expect("failed to load fixture");
+        assert_eq!(get_version(&connection).unwrap(), 0);
+
+        migrate(&mut connection, None).expect("failed to migrate");
+        assert_eq!(get_version(&connection).unwrap(), latest_version());
+
+        // Existing data is kept.
+        let title: String = connection
+            .query_row("SELECT title FROM kitsu_anime WHERE id = 1;", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(title, "Cowboy Bebop");
+        let episodes: u64 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM kitsu_episodes WHERE anime_id = 1;",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(episodes, 2);
+        let status: u64 = connection
+            .query_row("SELECT status FROM anime WHERE id = 1;", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(status, 1);
+
+        // New columns and tables exist.
+        let anime_columns = get_table_columns(&connection, "anime");
+        assert!(anime_columns.iter().any(|column| column == "poster_path"));
+        let kitsu_anime_columns = get_table_columns(&connection, "kitsu_anime");
+        assert!(kitsu_anime_columns.iter().any(|column| column == "subtype"));
+        for table in [
+            "vidstreaming_download_queue",
+            "vidstreaming_downloads",
+            "vidstreaming_anime_mappings",
+            "image_cache",
+            "kitsu_searches",
+            "kitsu_mappings",
+        ] {
+            assert!(
+                !get_table_columns(&connection, table).is_empty(),
+                "missing table \"{table}\""
+            );
+        }
+
+        // Existing anime are indexed for search.
+        let id: u64 = connection
+            .query_row(
+                "SELECT rowid FROM kitsu_anime_fts WHERE kitsu_anime_fts MATCH '\"bebop\"*';",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(id, 1);
+
+        // Migrating again does nothing.
+        migrate(&mut connection, None).expect("failed to migrate");
+        assert_eq!(get_version(&connection).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn migrate_empty() {
+        let mut connection = Connection::open_in_memory().expect("failed to open database");
+        migrate(&mut connection, None).expect("failed to migrate");
+        assert_eq!(get_version(&connection).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn refuse_newer_version() {
+        let mut connection = Connection::open_in_memory().expect("failed to open database");
+        connection
+            .pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+
+        migrate(&mut connection, None).expect_err("newer schema was migrated");
+        assert_eq!(get_version(&connection).unwrap(), latest_version() + 1);
+    }
+
+    #[test]
+    fn backup_before_migrating() {
+        let directory =
+            std::env::temp_dir().join(format!("bewu-migrations-test-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).expect("failed to create directory");
+        let path = directory.join("database.db");
+        let _ = std::fs::remove_file(&path);
+
+        let mut connection = Connection::open(&path).expect("failed to open database");
+        connection
+            .execute_batch(FIXTURE_V0)
+            .expect("failed to load fixture");
+
+        let backup_path = get_backup_path(&path, 0);
+        migrate(&mut connection, Some(&backup_path)).expect("failed to migrate");
+
+        let backup = Connection::open(&backup_path).expect("failed to open backup");
+        assert_eq!(get_version(&backup).unwrap(), 0);
+        let title: String = backup
+            .query_row("SELECT title FROM kitsu_anime WHERE id = 1;", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(title, "Cowboy Bebop");
+
+        drop(backup);
+        drop(connection);
+        std::fs::remove_dir_all(&directory).expect("failed to remove directory");
+    }
+}
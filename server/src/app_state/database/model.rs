@@ -1,4 +1,5 @@
 use std::num::NonZeroU64;
+use vidstreaming::AnimeType;
 
 /// Anime data fetched from kitsu
 #[derive(Debug, Clone)]
@@ -15,6 +16,12 @@ pub struct KitsuAnime {
     /// The title
     pub title: String,
 
+    /// Alternate titles, like english and romanized titles.
+    pub titles: Vec<String>,
+
+    /// Abbreviated titles
+    pub abbreviated_titles: Vec<String>,
+
     /// The rating
     ///
     /// This is a stringified float from 0.00-100.00.
@@ -25,6 +32,51 @@ pub struct KitsuAnime {
 
     pub poster_large: String,
 
+    /// The large cover image url
+    pub cover_large: Option<String>,
+
+    /// The subtype, like tv or movie.
+    pub subtype: Option<kitsu::Subtype>,
+
+    /// The airing status
+    pub status: Option<kitsu::Status>,
+
+    /// The number of episodes
+    pub episode_count: Option<u64>,
+
+    /// The length of an episode, in minutes.
+    pub episode_length: Option<u64>,
+
+    /// The age rating
+    pub age_rating: Option<kitsu::AgeRating>,
+
+    /// A description of the age rating
+    pub age_rating_guide: Option<String>,
+
+    /// The date airing started, as `YYYY-MM-DD`.
+    pub start_date: Option<String>,
+
+    /// The date airing ended, as `YYYY-MM-DD`.
+    pub end_date: Option<String>,
+
+    /// The popularity rank
+    pub popularity_rank: Option<u64>,
+
+    /// The rating rank
+    pub rating_rank: Option<u64>,
+
+    /// The number of users that added this anime
+    pub user_count: Option<u64>,
+
+    /// The number of users that favorited this anime
+    pub favorites_count: Option<u64>,
+
+    /// The youtube id of the trailer
+    pub youtube_video_id: Option<String>,
+
+    /// Whether this is nsfw
+    pub nsfw: bool,
+
     /// The timestamp of the last update.
     ///
     /// This is the number of seconds from the unix epoch.
@@ -60,3 +112,203 @@ pub struct KitsuAnimeEpisode {
     /// This is the number of seconds from the unix epoch.
     pub last_update: u64,
 }
+
+/// An entry in the vidstreaming download queue
+#[derive(Debug, Clone)]
+pub struct VidstreamingDownloadQueueEntry {
+    /// The kitsu episode id
+    pub episode_id: NonZeroU64,
+
+    /// The kitsu anime id.
+    ///
+    /// This is not saved, as it is looked up from the kitsu episode.
+    pub anime_id: NonZeroU64,
+
+    /// The track of the episode
+    pub anime_type: AnimeType,
+
+    /// The kitsu anime slug
+    pub anime_slug: String,
+
+    /// The episode number, in the current season.
+    pub episode_number: u32,
+
+    /// The vidstreaming anime slug.
+    ///
+    /// If this is `None`, the slug is guessed from the kitsu anime slug.
+    pub vidstreaming_slug: Option<String>,
+
+    /// The expected length of the episode, in minutes.
+    pub length_minutes: Option<u32>,
+
+    /// The maximum vertical resolution to download.
+    pub max_resolution: Option<u32>,
+
+    /// The maximum bandwidth to download, in bits per second.
+    pub max_bandwidth: Option<u64>,
+
+    /// The codecs to prefer, comma-separated.
+    pub preferred_codecs: String,
+
+    /// Whether this entry is paused.
+    pub paused: bool,
+}
+
+/// A completed vidstreaming download
+#[derive(Debug, Clone)]
+pub struct VidstreamingDownload {
+    /// The kitsu episode id
+    pub episode_id: NonZeroU64,
+
+    /// The track of the episode
+    pub anime_type: AnimeType,
+
+    /// The list the source came from
+    pub source_origin: String,
+
+    /// The source label
+    pub source_label: String,
+
+    /// The source url
+    pub source_url: String,
+
+    /// The time the download completed.
+    ///
+    /// This is the number of seconds from the unix epoch.
+    pub download_time: u64,
+}
+
+/// A mapping from a kitsu anime to a vidstreaming anime
+#[derive(Debug, Clone)]
+pub struct VidstreamingAnimeMapping {
+    /// The kitsu anime id
+    pub anime_id: NonZeroU64,
+
+    /// The track of the anime
+    pub anime_type: AnimeType,
+
+    /// The vidstreaming slug
+    pub slug: String,
+
+    /// The match score, from 0 to 1.
+    ///
+    /// This is `None` for manual mappings.
+    pub score: Option<f64>,
+
+    /// Whether this mapping was set by a user.
+    pub manual: bool,
+}
+
+/// An anime in the library
+#[derive(Debug, Clone)]
+pub struct LibraryAnime {
+    /// The kitsu anime id
+    pub id: NonZeroU64,
+
+    /// Whether the anime is installed.
+    ///
+    /// Installed anime have their images on disk.
+    pub installed: bool,
+
+    /// The time the anime was added to the library.
+    ///
+    /// This is the number of seconds from the unix epoch.
+    pub added_time: u64,
+
+    /// The poster path, relative to the data directory.
+    pub poster_path: Option<String>,
+
+    /// The cover path, relative to the data directory.
+    pub cover_path: Option<String>,
+
+    /// The kitsu url slug
+    pub slug: String,
+
+    /// The title
+    pub title: String,
+
+    /// The kitsu rating
+    pub rating: Option<String>,
+
+    /// The number of episodes with at least one downloaded track.
+    pub downloaded_episodes: u64,
+}
+
+/// The field to sort the library by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LibrarySort {
+    /// Sort by title
+    Title,
+
+    /// Sort by the time the anime was added
+    AddedTime,
+
+    /// Sort by kitsu rating
+    Rating,
+}
+
+impl LibrarySort {
+    /// Get the SQL expression to sort by.
+    pub(super) fn as_sql(self) -> &'static str {
+        match self {
+            Self::Title => "kitsu_anime.title",
+            Self::AddedTime => "anime.added_time",
+            Self::Rating => "CAST(kitsu_anime.rating AS REAL)",
+        }
+    }
+}
+
+/// The download status of a library episode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LibraryEpisodeStatus {
+    /// The episode is not downloaded or queued.
+    Missing,
+
+    /// The episode is in the download queue.
+    Queued,
+
+    /// The episode is in the download queue, but paused.
+    Paused,
+
+    /// The episode is downloaded.
+    Downloaded,
+}
+
+/// An episode of an anime in the library
+#[derive(Debug, Clone)]
+pub struct LibraryEpisode {
+    /// The kitsu episode id
+    pub episode_id: NonZeroU64,
+
+    /// The episode number, in the current season.
+    pub number: u32,
+
+    /// The title
+    pub title: Option<String>,
+
+    /// The download status
+    pub status: LibraryEpisodeStatus,
+
+    /// The time the download completed, if it is downloaded.
+    ///
+    /// This is the number of seconds from the unix epoch.
+    pub download_time: Option<u64>,
+}
+
+/// A cached image
+#[derive(Debug, Clone)]
+pub struct ImageCacheEntry {
+    /// The remote url
+    pub url: String,
+
+    /// The path, relative to the data directory.
+    pub path: String,
+
+    /// The size, in bytes.
+    pub size: u64,
+
+    /// The time the image was last used.
+    ///
+    /// This is the number of seconds from the unix epoch.
+    pub last_access: u64,
+}
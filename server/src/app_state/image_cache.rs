@@ -0,0 +1,566 @@
+use super::database::ImageCacheEntry;
+use super::Database;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use bewu_util::AsyncTimedLruCache;
+use nd_util::ArcAnyhowError;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
+use tokio::task::JoinSet;
+use tracing::debug;
+use tracing::error;
+use tracing::warn;
+
+type ImageResult<E = ArcAnyhowError> = Result<Arc<str>, E>;
+type ImageRequestCache = AsyncTimedLruCache<Box<str>, ImageResult>;
+
+/// The maximum number of images downloaded at once to warm the cache.
+const MAX_WARM_DOWNLOADS: usize = 4;
+
+/// The kind of a cached image.
+///
+/// This decides the directory the image is stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageKind {
+    /// An anime poster
+    Poster,
+
+    /// An anime cover
+    Cover,
+
+    /// An episode thumbnail
+    Thumbnail,
+}
+
+impl ImageKind {
+    /// Get the directory of this kind of image, relative to the data directory.
+    fn directory(self) -> &'static str {
+        match self {
+            Self::Poster => "kistu/poster",
+            Self::Cover => "kistu/cover",
+            Self::Thumbnail => "kistu/thumbnail",
+        }
+    }
+}
+
+/// A kitsu image size variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageSize {
+    Tiny,
+    Small,
+    Medium,
+    Large,
+    Original,
+}
+
+impl ImageSize {
+    const ALL: [Self; 5] = [
+        Self::Tiny,
+        Self::Small,
+        Self::Medium,
+        Self::Large,
+        Self::Original,
+    ];
+
+    /// Get the name of this size, as used in kitsu image urls.
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Tiny => "tiny",
+            Self::Small => "small",
+            Self::Medium => "medium",
+            Self::Large => "large",
+            Self::Original => "original",
+        }
+    }
+}
+
+/// Get the url of a size variant of a kitsu image.
+///
+/// Kitsu image urls end in the size name, like `.../poster_images/1/large.jpg`.
+/// Urls that do not follow this pattern are returned unchanged.
+pub fn get_image_size_url(url: &str, size: ImageSize) -> String {
+    let (directory, file_name) = match url.rsplit_once('/') {
+        Some(parts) => parts,
+        None => return url.into(),
+    };
+    let (file_stem, rest) = match file_name.split_once('.') {
+        Some(parts) => parts,
+        None => return url.into(),
+    };
+
+    if !ImageSize::ALL.iter().any(|size| size.as_str() == file_stem) {
+        return url.into();
+    }
+
+    format!("{directory}/{}.{rest}", size.as_str())
+}
+
+#[derive(Debug)]
+enum ImageCacheTaskMessage {
+    Close {
+        tx: tokio::sync::oneshot::Sender<()>,
+    },
+    WarmImages {
+        images: Vec<(Box<str>, ImageKind)>,
+    },
+    CopyImage {
+        url: Box<str>,
+        kind: ImageKind,
+        path: PathBuf,
+        tx: tokio::sync::oneshot::Sender<anyhow::Result<()>>,
+    },
+}
+
+/// A task to download and cache remote images in the data directory.
+///
+/// Concurrent requests for the same image are deduplicated.
+/// When the cache grows past its maximum size,
+/// the least recently used images are evicted.
+#[derive(Debug)]
+pub struct ImageCacheTask {
+    tx: tokio::sync::mpsc::Sender<ImageCacheTaskMessage>,
+    handle: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>>,
+
+    database: Database,
+    data_directory: Arc<Path>,
+}
+
+impl ImageCacheTask {
+    pub fn new(database: Database, data_directory: &Path, max_size: u64) -> Self {
+        let (tx, rx) = tokio::sync::mpsc::channel(128);
+        let data_directory: Arc<Path> = data_directory.into();
+
+        let handle = tokio::spawn(image_cache_task_impl(
+            rx,
+            database.clone(),
+            data_directory.clone(),
+            max_size,
+        ));
+
+        Self {
+            tx,
+            handle: std::sync::Mutex::new(Some(handle)),
+
+            database,
+            data_directory,
+        }
+    }
+
+    /// Get the paths of many images, if they are cached.
+    ///
+    /// Paths are relative to the data directory.
+    /// Results are in the same order as the given images.
+    /// Images that are not cached are downloaded in the background,
+    /// so this never waits for a download.
+    pub async fn get_cached_images(
+        &self,
+        images: Vec<(Box<str>, ImageKind)>,
+    ) -> anyhow::Result<Vec<Option<Arc<str>>>> {
+        let now = SystemTime::UNIX_EPOCH.elapsed()?.as_secs();
+        let urls = images.iter().map(|(url, _kind)| url.clone()).collect();
+        let entries = self.database.touch_image_cache_entries(urls, now).await?;
+
+        let mut paths = Vec::with_capacity(images.len());
+        let mut missing = Vec::new();
+        for ((url, kind), entry) in images.into_iter().zip(entries) {
+            let path = match entry {
+                Some(entry) => {
+                    let exists = tokio::fs::try_exists(self.data_directory.join(&entry.path))
+                        .await
+                        .context("failed to check if image exists")?;
+                    exists.then(|| entry.path.into())
+                }
+                None => None,
+            };
+            if path.is_none() {
+                missing.push((url, kind));
+            }
+            paths.push(path);
+        }
+
+        if !missing.is_empty() {
+            self.tx
+                .send(ImageCacheTaskMessage::WarmImages { images: missing })
+                .await?;
+        }
+
+        Ok(paths)
+    }
+
+    /// Copy an image out of the cache to the given path, downloading it if needed.
+    ///
+    /// The image cannot be evicted while it is being copied.
+    pub async fn copy_image(
+        &self,
+        url: Box<str>,
+        kind: ImageKind,
+        path: PathBuf,
+    ) -> anyhow::Result<()> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.tx
+            .send(ImageCacheTaskMessage::CopyImage {
+                url,
+                kind,
+                path,
+                tx,
+            })
+            .await?;
+        rx.await?
+    }
+
+    async fn close(&self) -> anyhow::Result<()> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.tx.send(ImageCacheTaskMessage::Close { tx }).await?;
+        rx.await?;
+        Ok(())
+    }
+
+    async fn join(&self) -> anyhow::Result<()> {
+        let handle = self
+            .handle
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+            .context("missing handle")?;
+
+        handle.await?;
+
+        Ok(())
+    }
+
+    /// Close and join the task
+    pub async fn shutdown(&self) -> anyhow::Result<()> {
+        // If we failed to close,
+        // its probably because the task is dead.
+        // Therefore, it is safe to join.
+        let close_result = self.close().await;
+        let join_result = self.join().await;
+
+        join_result.or(close_result)
+    }
+}
+
+/// Shared state for image requests
+#[derive(Debug, Clone)]
+struct ImageCacheContext {
+    client: reqwest::Client,
+    database: Database,
+    data_directory: Arc<Path>,
+    max_size: u64,
+
+    /// Held while evicting, so only one eviction runs at a time.
+    eviction_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+async fn image_cache_task_impl(
+    mut rx: tokio::sync::mpsc::Receiver<ImageCacheTaskMessage>,
+    database: Database,
+    data_directory: Arc<Path>,
+    max_size: u64,
+) {
+    let context = ImageCacheContext {
+        client: reqwest::Client::new(),
+        database,
+        data_directory,
+        max_size,
+        eviction_lock: Arc::new(tokio::sync::Mutex::new(())),
+    };
+
+    let request_cache = Arc::new(AsyncTimedLruCache::new(128, Duration::from_secs(0)));
+    let mut join_set = JoinSet::new();
+
+    // Warming is best-effort, so it is aborted on shutdown instead of waited on.
+    let mut warm_queue: VecDeque<(Box<str>, ImageKind)> = VecDeque::new();
+    let mut warm_join_set = JoinSet::new();
+
+    loop {
+        while warm_join_set.len() < MAX_WARM_DOWNLOADS {
+            let (url, kind) = match warm_queue.pop_front() {
+                Some(image) => image,
+                None => break,
+            };
+            warm_join_set.spawn(get_image_deduplicated(
+                context.clone(),
+                request_cache.clone(),
+                url,
+                kind,
+            ));
+        }
+
+        tokio::select! {
+            message = rx.recv() => {
+                match message {
+                    Some(ImageCacheTaskMessage::Close { tx }) => {
+                        rx.close();
+                        let _ = tx.send(()).is_ok();
+                    }
+                    Some(ImageCacheTaskMessage::WarmImages { images }) => {
+                        warm_queue.extend(images);
+                    }
+                    Some(ImageCacheTaskMessage::CopyImage { url, kind, path, tx }) => {
+                        join_set.spawn(copy_image_task_impl(
+                            context.clone(),
+                            request_cache.clone(),
+                            url,
+                            kind,
+                            path,
+                            tx,
+                        ));
+                    }
+                    None => {
+                        break;
+                    }
+                }
+            }
+            Some(result) = join_set.join_next() => {
+                match result.context("failed to join task") {
+                    Ok(()) => {}
+                    Err(error) => {
+                        warn!("{error}");
+                    }
+                }
+            }
+            Some(result) = warm_join_set.join_next() => {
+                let result = result
+                    .context("failed to join task")
+                    .and_then(|result| result.context("failed to warm image"));
+                if let Err(error) = result {
+                    warn!("{error:?}");
+                }
+            }
+        }
+    }
+
+    warm_join_set.shutdown().await;
+    while let Some(result) = join_set.join_next().await {
+        if let Err(error) = result.context("failed to join task") {
+            warn!("{error}");
+        }
+    }
+}
+
+/// Get the path of a cached image, downloading it if needed.
+///
+/// Concurrent requests for the same image share one download.
+async fn get_image_deduplicated(
+    context: ImageCacheContext,
+    request_cache: Arc<ImageRequestCache>,
+    url: Box<str>,
+    kind: ImageKind,
+) -> anyhow::Result<Arc<str>> {
+    request_cache
+        .get(url.clone(), || async move {
+            get_image(&context, url, kind)
+                .await
+                .map_err(ArcAnyhowError::new)
+        })
+        .await
+        .map_err(anyhow::Error::from)
+}
+
+async fn copy_image_task_impl(
+    context: ImageCacheContext,
+    request_cache: Arc<ImageRequestCache>,
+    url: Box<str>,
+    kind: ImageKind,
+    path: PathBuf,
+    tx: tokio::sync::oneshot::Sender<anyhow::Result<()>>,
+) {
+    let result = async {
+        // The image may be evicted before the eviction lock is taken,
+        // in which case it is downloaded again.
+        for _ in 0..2 {
+            let cached_path =
+                get_image_deduplicated(context.clone(), request_cache.clone(), url.clone(), kind)
+                    .await?;
+
+            let _eviction_guard = context.eviction_lock.lock().await;
+            let cached_path = context.data_directory.join(&*cached_path);
+            let exists = tokio::fs::try_exists(&cached_path)
+                .await
+                .context("failed to check if image exists")?;
+            if exists {
+                return copy_image(&cached_path, &path).await;
+            }
+        }
+
+        bail!("image \"{url}\" was evicted before it could be copied")
+    }
+    .await;
+
+    let _ = tx.send(result).is_ok();
+}
+
+/// Get the path of a cached image, downloading it if needed.
+async fn get_image(
+    context: &ImageCacheContext,
+    url: Box<str>,
+    kind: ImageKind,
+) -> anyhow::Result<Arc<str>> {
+    let now = SystemTime::UNIX_EPOCH.elapsed()?.as_secs();
+
+    if let Some(entry) = context.database.get_image_cache_entry(url.clone()).await? {
+        let exists = tokio::fs::try_exists(context.data_directory.join(&entry.path))
+            .await
+            .context("failed to check if image exists")?;
+        if exists {
+            context
+                .database
+                .set_image_cache_entry_last_access(url, now)
+                .await?;
+            return Ok(entry.path.into());
+        }
+
+        warn!(
+            "cached image \"{}\" is missing, downloading again",
+            entry.path
+        );
+    }
+
+    let parsed_url = url::Url::parse(&url).context("invalid image url")?;
+    let path = format!(
+        "{}/{:016x}.{}",
+        kind.directory(),
+        hash_url(&url),
+        get_image_extension(&parsed_url)
+    );
+    debug!("caching image \"{url}\" at \"{path}\"");
+
+    let size = download_image(
+        &context.client,
+        &parsed_url,
+        &context.data_directory.join(&path),
+    )
+    .await?;
+    context
+        .database
+        .upsert_image_cache_entry(ImageCacheEntry {
+            url: url.into(),
+            path: path.clone(),
+            size,
+            last_access: now,
+        })
+        .await?;
+
+    if let Err(error) = evict_images(context)
+        .await
+        .context("failed to evict images")
+    {
+        error!("{error:?}");
+    }
+
+    Ok(path.into())
+}
+
+/// Evict the least recently used images until the cache is within its maximum size.
+async fn evict_images(context: &ImageCacheContext) -> anyhow::Result<()> {
+    let _eviction_guard = context.eviction_lock.lock().await;
+
+    let mut size = context.database.get_image_cache_size().await?;
+    if size <= context.max_size {
+        return Ok(());
+    }
+
+    for entry in context.database.get_image_cache_entries().await? {
+        if size <= context.max_size {
+            break;
+        }
+
+        debug!("evicting cached image \"{}\"", entry.path);
+
+        // Remove the entry first, so it never points to a missing file.
+        context
+            .database
+            .remove_image_cache_entry(entry.url.into())
+            .await?;
+        remove_file_if_exists(&context.data_directory.join(&entry.path)).await?;
+
+        size = size.saturating_sub(entry.size);
+    }
+
+    Ok(())
+}
+
+/// Hash a url with 64-bit FNV-1a, to make a stable file name.
+fn hash_url(url: &str) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    url.bytes().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+/// Get the file extension of an image url, defaulting to `jpg`.
+pub fn get_image_extension(url: &url::Url) -> &str {
+    Path::new(url.path())
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .filter(|extension| {
+            ["jpg", "jpeg", "png", "gif", "webp"]
+                .iter()
+                .any(|known| extension.eq_ignore_ascii_case(known))
+        })
+        .unwrap_or("jpg")
+}
+
+/// Download an image to the given path, returning its size in bytes.
+///
+/// The image is written to a temporary file first,
+/// so a partial image is never left at the path.
+pub async fn download_image(
+    client: &reqwest::Client,
+    url: &url::Url,
+    path: &Path,
+) -> anyhow::Result<u64> {
+    let response = client
+        .get(url.as_str())
+        .send()
+        .await
+        .with_context(|| format!("failed to fetch \"{url}\""))?
+        .error_for_status()?;
+    let bytes = response.bytes().await?;
+    ensure!(!bytes.is_empty(), "image \"{url}\" is empty");
+
+    let temp_path = path.with_extension("part");
+    tokio::fs::write(&temp_path, &bytes)
+        .await
+        .with_context(|| format!("failed to write \"{}\"", temp_path.display()))?;
+    tokio::fs::rename(&temp_path, path)
+        .await
+        .with_context(|| format!("failed to rename \"{}\"", temp_path.display()))?;
+
+    Ok(u64::try_from(bytes.len())?)
+}
+
+/// Copy an image to the given path.
+///
+/// The image is copied to a temporary file first,
+/// so a partial image is never left at the path.
+pub async fn copy_image(from: &Path, to: &Path) -> anyhow::Result<()> {
+    let temp_path = to.with_extension("part");
+    tokio::fs::copy(from, &temp_path)
+        .await
+        .with_context(|| format!("failed to copy \"{}\"", from.display()))?;
+    tokio::fs::rename(&temp_path, to)
+        .await
+        .with_context(|| format!("failed to rename \"{}\"", temp_path.display()))?;
+
+    Ok(())
+}
+
+/// Remove a file, ignoring it if it does not exist.
+pub async fn remove_file_if_exists(path: &Path) -> anyhow::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(error) => {
+            Err(error).with_context(|| format!("failed to remove \"{}\"", path.display()))
+        }
+    }
+}
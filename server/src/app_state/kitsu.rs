@@ -1,19 +1,42 @@
 use super::Database;
 use super::KitsuAnime;
+use super::KitsuAnimeEpisode;
 use anyhow::Context;
 use bewu_util::AsyncTimedLruCache;
 use nd_util::ArcAnyhowError;
+use std::collections::HashMap;
 use std::num::NonZeroU64;
 use std::sync::Arc;
 use std::time::Duration;
 use std::time::SystemTime;
 use tokio::task::JoinSet;
+use tokio_stream::StreamExt;
 use tracing::error;
 use tracing::info;
 use tracing::warn;
 
 type SearchCache = AsyncTimedLruCache<Box<str>, SearchResult>;
 type SearchResult<E = ArcAnyhowError> = Result<Arc<[KitsuAnime]>, E>;
+type AnimeCache = AsyncTimedLruCache<NonZeroU64, AnimeResult>;
+type AnimeResult<E = ArcAnyhowError> = Result<Arc<KitsuAnime>, E>;
+type EpisodesCache = AsyncTimedLruCache<NonZeroU64, EpisodesResult>;
+type EpisodesResult<E = ArcAnyhowError> = Result<Arc<[KitsuAnimeEpisode]>, E>;
+
+/// Where to search for kitsu anime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KitsuSearchMode {
+    /// Use local results if the query was searched recently,
+    /// otherwise search kitsu and merge in local results.
+    ///
+    /// Local results are used if kitsu cannot be reached.
+    Auto,
+
+    /// Only search cached anime
+    Local,
+
+    /// Only search kitsu
+    Remote,
+}
 
 #[derive(Debug)]
 enum KitsuTaskMessage {
@@ -26,10 +49,19 @@ enum KitsuTaskMessage {
     },
     GetAnime {
         id: NonZeroU64,
-        tx: tokio::sync::oneshot::Sender<anyhow::Result<Arc<KitsuAnime>>>,
+        tx: tokio::sync::oneshot::Sender<AnimeResult<anyhow::Error>>,
+    },
+    GetAnimeEpisodes {
+        anime_id: NonZeroU64,
+        tx: tokio::sync::oneshot::Sender<EpisodesResult<anyhow::Error>>,
     },
 }
 
+/// A task to fetch and cache kitsu metadata.
+///
+/// Cached metadata is served immediately.
+/// If it is older than the ttl, it is refreshed in the background.
+/// Airing anime in the library are also refreshed periodically.
 #[derive(Debug)]
 pub struct KitsuTask {
     tx: tokio::sync::mpsc::Sender<KitsuTaskMessage>,
@@ -37,10 +69,15 @@ pub struct KitsuTask {
 }
 
 impl KitsuTask {
-    pub fn new(database: Database) -> Self {
+    pub fn new(
+        client: kitsu::Client,
+        database: Database,
+        ttl: Duration,
+        refresh_interval: Duration,
+    ) -> Self {
         let (tx, rx) = tokio::sync::mpsc::channel(16);
 
-        let handle = tokio::spawn(kitsu_task_impl(rx, database));
+        let handle = tokio::spawn(kitsu_task_impl(rx, client, database, ttl, refresh_interval));
 
         Self {
             tx,
@@ -59,12 +96,20 @@ impl KitsuTask {
         rx.await?
     }
 
-    pub async fn get_anime(&self, id: NonZeroU64) -> anyhow::Result<Arc<KitsuAnime>> {
+    pub async fn get_anime(&self, id: NonZeroU64) -> AnimeResult<anyhow::Error> {
         let (tx, rx) = tokio::sync::oneshot::channel();
         self.tx.send(KitsuTaskMessage::GetAnime { id, tx }).await?;
         rx.await?
     }
 
+    pub async fn get_anime_episodes(&self, anime_id: NonZeroU64) -> EpisodesResult<anyhow::Error> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.tx
+            .send(KitsuTaskMessage::GetAnimeEpisodes { anime_id, tx })
+            .await?;
+        rx.await?
+    }
+
     async fn close(&self) -> anyhow::Result<()> {
         let (tx, rx) = tokio::sync::oneshot::channel();
         self.tx.send(KitsuTaskMessage::Close { tx }).await?;
@@ -97,16 +142,55 @@ impl KitsuTask {
     }
 }
 
+/// Shared state for anime and episode requests
+#[derive(Clone)]
+struct KitsuContext {
+    client: kitsu::Client,
+    database: Database,
+    ttl: Duration,
+
+    /// Deduplicates fetches of anime from kitsu.
+    refresh_anime_cache: Arc<AnimeCache>,
+
+    /// Deduplicates fetches of anime episodes from kitsu.
+    refresh_episodes_cache: Arc<EpisodesCache>,
+}
+
+impl KitsuContext {
+    /// Check if metadata updated at the given time is stale.
+    fn is_stale(&self, last_update: u64) -> bool {
+        let now = match SystemTime::UNIX_EPOCH.elapsed() {
+            Ok(now) => now.as_secs(),
+            Err(_) => return true,
+        };
+
+        now.saturating_sub(last_update) > self.ttl.as_secs()
+    }
+}
+
 async fn kitsu_task_impl(
     mut rx: tokio::sync::mpsc::Receiver<KitsuTaskMessage>,
+    client: kitsu::Client,
     database: Database,
+    ttl: Duration,
+    refresh_interval: Duration,
 ) {
-    let client = kitsu::Client::new();
+    let context = KitsuContext {
+        client: client.clone(),
+        database: database.clone(),
+        ttl,
+        refresh_anime_cache: Arc::new(AsyncTimedLruCache::new(128, Duration::from_secs(0))),
+        refresh_episodes_cache: Arc::new(AsyncTimedLruCache::new(128, Duration::from_secs(0))),
+    };
 
     let search_cache = Arc::new(AsyncTimedLruCache::new(128, Duration::from_secs(0)));
     let get_anime_cache = Arc::new(AsyncTimedLruCache::new(128, Duration::from_secs(0)));
+    let get_episodes_cache = Arc::new(AsyncTimedLruCache::new(128, Duration::from_secs(0)));
     let mut join_set = JoinSet::new();
 
+    let mut refresh_interval = tokio::time::interval(refresh_interval);
+    refresh_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
     loop {
         tokio::select! {
             message = rx.recv() => {
@@ -128,22 +212,29 @@ async fn kitsu_task_impl(
                         ));
                     }
                     Some(KitsuTaskMessage::GetAnime { id, tx }) => {
-                        let client = client.clone();
-                        let get_anime_cache = get_anime_cache.clone();
-                        let database = database.clone();
                         join_set.spawn(get_anime_task_impl(
-                            client,
-                            get_anime_cache,
-                            database,
+                            context.clone(),
+                            get_anime_cache.clone(),
                             id,
                             tx
                         ));
                     }
+                    Some(KitsuTaskMessage::GetAnimeEpisodes { anime_id, tx }) => {
+                        join_set.spawn(get_anime_episodes_task_impl(
+                            context.clone(),
+                            get_episodes_cache.clone(),
+                            anime_id,
+                            tx
+                        ));
+                    }
                     None => {
                         break;
                     }
                 }
             }
+            _ = refresh_interval.tick() => {
+                join_set.spawn(refresh_airing_anime_task_impl(context.clone()));
+            }
             Some(result) = join_set.join_next() => {
                 match result.context("failed to join task") {
                     Ok(()) => {}
@@ -172,7 +263,18 @@ async fn search_task_impl(
             if let Ok(anime) = anime_result.as_ref() {
                 let anime = anime.clone();
                 tokio::spawn(async move {
-                    let result = database.upsert_kitsu_anime(anime).await;
+                    let result = async {
+                        let last_update = anime.first().map(|anime| anime.last_update);
+                        database.upsert_kitsu_anime(anime).await?;
+
+                        // Record the search, so fresh results can be served locally.
+                        let last_update = match last_update {
+                            Some(last_update) => last_update,
+                            None => SystemTime::UNIX_EPOCH.elapsed()?.as_secs(),
+                        };
+                        database.upsert_kitsu_search(&query, last_update).await
+                    }
+                    .await;
 
                     match result.context("failed to cache search results") {
                         Ok(()) => {}
@@ -192,36 +294,76 @@ async fn search_task_impl(
 }
 
 async fn get_anime_task_impl(
-    client: kitsu::Client,
-    request_map: Arc<AsyncTimedLruCache<NonZeroU64, Result<Arc<KitsuAnime>, ArcAnyhowError>>>,
-    database: Database,
+    context: KitsuContext,
+    request_map: Arc<AnimeCache>,
     id: NonZeroU64,
-    tx: tokio::sync::oneshot::Sender<anyhow::Result<Arc<KitsuAnime>>>,
+    tx: tokio::sync::oneshot::Sender<AnimeResult<anyhow::Error>>,
 ) {
     let result = request_map
         .get(id, || async move {
-            let maybe_anime_result = database.get_kitsu_anime(id).await;
+            match context.database.get_kitsu_anime(id).await {
+                Ok(Some(anime)) => {
+                    if context.is_stale(anime.last_update) {
+                        let context = context.clone();
+                        tokio::spawn(async move {
+                            if let Err(error) = refresh_anime(&context, id)
+                                .await
+                                .context("failed to refresh stale anime")
+                            {
+                                warn!("{error:?}");
+                            }
+                        });
+                    }
 
-            if let Ok(Some(anime)) = maybe_anime_result {
-                return Ok(anime);
+                    return Ok(anime);
+                }
+                Ok(None) => {}
+                Err(error) => {
+                    warn!("{:?}", error.context("failed to get cached anime"));
+                }
             }
 
-            let result = kitsu_get_anime(&client, id)
-                .await
-                .map_err(ArcAnyhowError::new);
+            refresh_anime(&context, id).await
+        })
+        .await
+        .map_err(anyhow::Error::new);
 
-            if let Ok(anime) = result.as_ref() {
-                let result = database.upsert_kitsu_anime(anime.clone()).await;
+    let _ = tx.send(result).is_ok();
+}
 
-                match result.context("failed to cache search results") {
-                    Ok(()) => {}
-                    Err(error) => {
-                        error!("{error:?}");
+async fn get_anime_episodes_task_impl(
+    context: KitsuContext,
+    request_map: Arc<EpisodesCache>,
+    anime_id: NonZeroU64,
+    tx: tokio::sync::oneshot::Sender<EpisodesResult<anyhow::Error>>,
+) {
+    let result = request_map
+        .get(anime_id, || async move {
+            // Single episodes are cached on their own,
+            // so only serve from the database if the full list was fetched.
+            match get_cached_anime_episodes(&context, anime_id).await {
+                Ok(Some((episodes, last_update))) => {
+                    if context.is_stale(last_update) {
+                        let context = context.clone();
+                        tokio::spawn(async move {
+                            if let Err(error) = refresh_anime_episodes(&context, anime_id)
+                                .await
+                                .context("failed to refresh stale episodes")
+                            {
+                                warn!("{error:?}");
+                            }
+                        });
                     }
+
+                    return Ok(episodes.into());
+                }
+                Ok(None) => {}
+                Err(error) => {
+                    warn!("{:?}", error.context("failed to get cached episodes"));
                 }
             }
 
-            result
+            refresh_anime_episodes(&context, anime_id).await
         })
         .await
         .map_err(anyhow::Error::new);
@@ -229,6 +371,104 @@ async fn get_anime_task_impl(
     let _ = tx.send(result).is_ok();
 }
 
+/// Get the cached episodes of an anime, and when they were fetched.
+///
+/// Returns `None` if the full episode list was never fetched.
+async fn get_cached_anime_episodes(
+    context: &KitsuContext,
+    anime_id: NonZeroU64,
+) -> anyhow::Result<Option<(Vec<KitsuAnimeEpisode>, u64)>> {
+    let last_update = match context
+        .database
+        .get_kitsu_anime_episodes_last_update(anime_id)
+        .await?
+    {
+        Some(last_update) => last_update,
+        None => return Ok(None),
+    };
+    let episodes = context.database.get_kitsu_anime_episodes(anime_id).await?;
+
+    Ok(Some((episodes, last_update)))
+}
+
+/// Refresh the anime in the library that are currently airing.
+async fn refresh_airing_anime_task_impl(context: KitsuContext) {
+    let ids = match context.database.get_airing_library_anime_ids().await {
+        Ok(ids) => ids,
+        Err(error) => {
+            error!("{:?}", error.context("failed to get airing library anime"));
+            return;
+        }
+    };
+
+    for id in ids {
+        info!("refreshing airing anime \"{id}\"");
+
+        if let Err(error) = refresh_anime(&context, id)
+            .await
+            .with_context(|| format!("failed to refresh anime \"{id}\""))
+        {
+            warn!("{error:?}");
+        }
+
+        if let Err(error) = refresh_anime_episodes(&context, id)
+            .await
+            .with_context(|| format!("failed to refresh episodes of anime \"{id}\""))
+        {
+            warn!("{error:?}");
+        }
+    }
+}
+
+/// Fetch an anime from kitsu and cache it.
+async fn refresh_anime(context: &KitsuContext, id: NonZeroU64) -> AnimeResult {
+    context
+        .refresh_anime_cache
+        .get(id, || async move {
+            let anime = kitsu_get_anime(&context.client, id)
+                .await
+                .map_err(ArcAnyhowError::new)?;
+
+            let result = context.database.upsert_kitsu_anime(anime.clone()).await;
+            if let Err(error) = result.context("failed to cache anime") {
+                error!("{error:?}");
+            }
+
+            Ok(anime)
+        })
+        .await
+}
+
+/// Fetch the episodes of an anime from kitsu and cache them.
+async fn refresh_anime_episodes(context: &KitsuContext, anime_id: NonZeroU64) -> EpisodesResult {
+    context
+        .refresh_episodes_cache
+        .get(anime_id, || async move {
+            let episodes = kitsu_get_anime_episodes(&context.client, anime_id)
+                .await
+                .map_err(ArcAnyhowError::new)?;
+
+            let result = async {
+                context
+                    .database
+                    .upsert_kitsu_episodes(episodes.clone())
+                    .await?;
+                let last_update = SystemTime::UNIX_EPOCH.elapsed()?.as_secs();
+                context
+                    .database
+                    .set_kitsu_anime_episodes_last_update(anime_id, last_update)
+                    .await
+            }
+            .await;
+            if let Err(error) = result.context("failed to cache episodes") {
+                error!("{error:?}");
+            }
+
+            Ok(episodes)
+        })
+        .await
+}
+
 //
 // Fetch Wrappers
 //
@@ -245,21 +485,8 @@ async fn kitsu_search(client: &kitsu::Client, query: &str) -> SearchResult<anyho
         let attributes = item.attributes.context("missing attributes")?;
 
         let id: NonZeroU64 = item.id.as_deref().context("missing id")?.parse()?;
-        let slug = attributes.slug;
-        let synopsis = attributes.synopsis;
-        let title = attributes.canonical_title;
-        let rating = attributes.average_rating;
-        let poster_large = attributes.poster_image.large.into();
 
-        anime.push(KitsuAnime {
-            id,
-            slug,
-            synopsis,
-            title,
-            rating,
-            poster_large,
-            last_update,
-        });
+        anime.push(make_kitsu_anime(id, attributes, last_update));
     }
     let anime: Arc<[KitsuAnime]> = anime.into();
 
@@ -279,21 +506,421 @@ async fn kitsu_get_anime(
 
     let attributes = document_data.attributes.context("missing attributes")?;
     let id: NonZeroU64 = document_data.id.as_deref().context("missing id")?.parse()?;
-    let slug = attributes.slug;
-    let synopsis = attributes.synopsis;
+    let anime = Arc::new(make_kitsu_anime(id, attributes, last_update));
+
+    Ok(anime)
+}
+
+async fn kitsu_get_anime_episodes(
+    client: &kitsu::Client,
+    anime_id: NonZeroU64,
+) -> EpisodesResult<anyhow::Error> {
+    info!("getting episodes of anime \"{anime_id}\"");
+
+    let stream = client.get_anime_episodes_stream(anime_id);
+    tokio::pin!(stream);
+
+    let mut episodes = Vec::new();
+    let last_update = SystemTime::UNIX_EPOCH.elapsed()?.as_secs();
+    while let Some(item) = stream.next().await {
+        let item = item?;
+        let attributes = item.attributes.context("missing attributes")?;
+        let episode_id: NonZeroU64 = item.id.as_deref().context("missing id")?.parse()?;
+
+        let title = attributes.canonical_title;
+        let synopsis = attributes.synopsis;
+        let length_minutes: Option<u32> = attributes.length;
+        let number = attributes.number;
+        let thumbnail_original = attributes
+            .thumbnail
+            .map(|thumbnail| thumbnail.original.into());
+
+        episodes.push(KitsuAnimeEpisode {
+            anime_id,
+            episode_id,
+
+            title,
+            synopsis,
+            length_minutes,
+            number,
+
+            thumbnail_original,
+            last_update,
+        });
+    }
+    let episodes: Arc<[KitsuAnimeEpisode]> = episodes.into();
+
+    Ok(episodes)
+}
+
+/// Make a [`KitsuAnime`] from the attributes of a kitsu anime.
+fn make_kitsu_anime(id: NonZeroU64, attributes: kitsu::Anime, last_update: u64) -> KitsuAnime {
     let title = attributes.canonical_title;
-    let rating = attributes.average_rating;
-    let poster_large = attributes.poster_image.large.into();
+    let titles = get_alternate_titles(attributes.titles, &title);
 
-    let anime = Arc::new(KitsuAnime {
+    KitsuAnime {
         id,
-        slug,
-        synopsis,
+        slug: attributes.slug,
+        synopsis: attributes.synopsis,
         title,
-        rating,
-        poster_large,
+        titles,
+        abbreviated_titles: attributes.abbreviated_titles.unwrap_or_default(),
+        rating: attributes.average_rating,
+        poster_large: attributes.poster_image.large.into(),
+        cover_large: attributes
+            .cover_image
+            .map(|cover_image| cover_image.large.into()),
+        subtype: Some(attributes.subtype),
+        status: Some(attributes.status),
+        episode_count: attributes.episode_count,
+        episode_length: attributes.episode_length,
+        age_rating: attributes.age_rating,
+        age_rating_guide: attributes.age_rating_guide,
+        start_date: attributes.start_date,
+        end_date: attributes.end_date,
+        popularity_rank: Some(attributes.popularity_rank),
+        rating_rank: attributes.rating_rank,
+        user_count: Some(attributes.user_count),
+        favorites_count: Some(attributes.favorites_count),
+        youtube_video_id: attributes.youtube_video_id,
+        nsfw: attributes.nsfw,
         last_update,
-    });
+    }
+}
 
-    Ok(anime)
+/// Get the alternate titles of an anime, in a stable order.
+///
+/// Titles equal to the canonical title are skipped.
+fn get_alternate_titles(
+    titles: HashMap<String, Option<String>>,
+    canonical_title: &str,
+) -> Vec<String> {
+    let mut titles: Vec<(String, String)> = titles
+        .into_iter()
+        .filter_map(|(language, title)| Some((language, title?)))
+        .filter(|(_, title)| !title.is_empty() && title != canonical_title)
+        .collect();
+    titles.sort_unstable();
+
+    let mut alternate_titles: Vec<String> = Vec::with_capacity(titles.len());
+    for (_, title) in titles {
+        if !alternate_titles.contains(&title) {
+            alternate_titles.push(title);
+        }
+    }
+    alternate_titles
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use kitsu::MockServer;
+    use std::future::Future;
+    use std::path::PathBuf;
+
+    /// A kitsu task backed by a mock server and a temporary database.
+    struct TestContext {
+        server: MockServer,
+        database: Database,
+        task: KitsuTask,
+        directory: PathBuf,
+    }
+
+    impl TestContext {
+        async fn new(name: &str, ttl: Duration) -> Self {
+            let directory =
+                std::env::temp_dir().join(format!("bewu-kitsu-test-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&directory);
+            std::fs::create_dir_all(&directory).expect("failed to create directory");
+
+            let server = MockServer::start()
+                .await
+                .expect("failed to start mock server");
+            let database = Database::new(directory.join("database.db"))
+                .await
+                .expect("failed to open database");
+            let task = KitsuTask::new(
+                server.client(),
+                database.clone(),
+                ttl,
+                Duration::from_secs(60 * 60),
+            );
+
+            Self {
+                server,
+                database,
+                task,
+                directory,
+            }
+        }
+
+        async fn shutdown(self) {
+            self.task.shutdown().await.expect("failed to shutdown task");
+            self.database
+                .shutdown()
+                .await
+                .expect("failed to shutdown database");
+            std::fs::remove_dir_all(&self.directory).expect("failed to remove directory");
+        }
+    }
+
+    /// Wait until a condition is true, as results are cached in the background.
+    async fn wait_until<F, Fut>(mut f: F)
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = bool>,
+    {
+        for _ in 0..500 {
+            if f().await {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("timed out waiting for condition");
+    }
+
+    #[tokio::test]
+    async fn search_caches_results() {
+        let context = TestContext::new("search", Duration::from_secs(60)).await;
+
+        let anime = context
+            .task
+            .search("cowboy bebop")
+            .await
+            .expect("failed to search");
+        assert_eq!(anime.len(), 10);
+        assert_eq!(anime[0].id.get(), 1);
+        assert_eq!(anime[0].title, "Cowboy Bebop");
+
+        let database = &context.database;
+        wait_until(|| async {
+            database
+                .get_kitsu_search_last_update("cowboy bebop")
+                .await
+                .expect("failed to get search")
+                .is_some()
+        })
+        .await;
+
+        let local = database
+            .search_kitsu_anime("bebop", 20)
+            .await
+            .expect("failed to search database");
+        assert!(local.iter().any(|anime| anime.id.get() == 1));
+
+        context.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn get_anime_caches_in_database() {
+        let context = TestContext::new("get-anime", Duration::from_secs(60)).await;
+        let id = NonZeroU64::new(5).unwrap();
+
+        let anime = context
+            .task
+            .get_anime(id)
+            .await
+            .expect("failed to get anime");
+        assert_eq!(anime.title, "Beet the Vandel Buster");
+        assert_eq!(anime.subtype, Some(kitsu::Subtype::Tv));
+
+        let cached = context
+            .database
+            .get_kitsu_anime(id)
+            .await
+            .expect("failed to get cached anime")
+            .expect("anime was not cached");
+        assert_eq!(cached.title, anime.title);
+        assert_eq!(cached.last_update, anime.last_update);
+
+        // Fresh anime are served from the database.
+        let anime = context
+            .task
+            .get_anime(id)
+            .await
+            .expect("failed to get anime");
+        assert_eq!(anime.title, "Beet the Vandel Buster");
+        assert_eq!(context.server.request_count("/api/edge/anime/5"), 1);
+
+        let missing = NonZeroU64::new(1).unwrap();
+        context
+            .task
+            .get_anime(missing)
+            .await
+            .expect_err("missing anime was found");
+        assert!(context
+            .database
+            .get_kitsu_anime(missing)
+            .await
+            .expect("failed to get cached anime")
+            .is_none());
+
+        context.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn stale_anime_is_refreshed() {
+        let context = TestContext::new("stale-anime", Duration::from_secs(60)).await;
+        let id = NonZeroU64::new(5).unwrap();
+
+        let anime = context
+            .task
+            .get_anime(id)
+            .await
+            .expect("failed to get anime");
+        let mut stale_anime = KitsuAnime::clone(&anime);
+        stale_anime.last_update = 0;
+        context
+            .database
+            .upsert_kitsu_anime(stale_anime)
+            .await
+            .expect("failed to upsert anime");
+
+        // Stale anime are served immediately, then refreshed.
+        let anime = context
+            .task
+            .get_anime(id)
+            .await
+            .expect("failed to get anime");
+        assert_eq!(anime.last_update, 0);
+
+        let database = &context.database;
+        wait_until(|| async {
+            database
+                .get_kitsu_anime(id)
+                .await
+                .expect("failed to get cached anime")
+                .is_some_and(|anime| anime.last_update != 0)
+        })
+        .await;
+        assert_eq!(context.server.request_count("/api/edge/anime/5"), 2);
+
+        context.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_are_deduplicated() {
+        let context = TestContext::new("dedup", Duration::from_secs(60)).await;
+        let id = NonZeroU64::new(13401).unwrap();
+
+        let (first, second) = tokio::join!(context.task.get_anime(id), context.task.get_anime(id));
+        let first = first.expect("failed to get anime");
+        let second = second.expect("failed to get anime");
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(context.server.request_count("/api/edge/anime/13401"), 1);
+
+        context.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn get_anime_episodes_caches_in_database() {
+        let context = TestContext::new("episodes", Duration::from_secs(60)).await;
+        let id = NonZeroU64::new(5).unwrap();
+
+        // Episodes reference their anime.
+        context
+            .task
+            .get_anime(id)
+            .await
+            .expect("failed to get anime");
+
+        let episodes = context
+            .task
+            .get_anime_episodes(id)
+            .await
+            .expect("failed to get episodes");
+        assert_eq!(episodes.len(), 25);
+        assert_eq!(
+            context.server.request_count("/api/edge/anime/5/episodes"),
+            2
+        );
+
+        let cached = context
+            .database
+            .get_kitsu_anime_episodes(id)
+            .await
+            .expect("failed to get cached episodes");
+        assert_eq!(cached.len(), 25);
+        let episode = cached
+            .iter()
+            .find(|episode| episode.number == 10)
+            .expect("missing episode");
+        assert_eq!(episode.episode_id.get(), 89);
+        assert_eq!(
+            episode.title.as_deref(),
+            Some("Attacking Satoshi military officer")
+        );
+
+        // Fresh episodes are served from the database.
+        let episodes = context
+            .task
+            .get_anime_episodes(id)
+            .await
+            .expect("failed to get episodes");
+        assert_eq!(episodes.len(), 25);
+        assert_eq!(
+            context.server.request_count("/api/edge/anime/5/episodes"),
+            2
+        );
+
+        context.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn single_cached_episode_does_not_truncate_episodes() {
+        let context = TestContext::new("single-episode", Duration::from_secs(60)).await;
+        let id = NonZeroU64::new(5).unwrap();
+
+        context
+            .task
+            .get_anime(id)
+            .await
+            .expect("failed to get anime");
+
+        // Getting a single episode caches only that episode.
+        let last_update = SystemTime::UNIX_EPOCH.elapsed().unwrap().as_secs();
+        context
+            .database
+            .upsert_kitsu_episodes(KitsuAnimeEpisode {
+                anime_id: id,
+                episode_id: NonZeroU64::new(89).unwrap(),
+
+                title: Some("Attacking Satoshi military officer".into()),
+                synopsis: None,
+                length_minutes: None,
+                number: 10,
+
+                thumbnail_original: None,
+                last_update,
+            })
+            .await
+            .expect("failed to cache episode");
+
+        let episodes = context
+            .task
+            .get_anime_episodes(id)
+            .await
+            .expect("failed to get episodes");
+        assert_eq!(episodes.len(), 25);
+
+        context.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn errors_are_retried() {
+        let context = TestContext::new("retries", Duration::from_secs(60)).await;
+
+        context
+            .server
+            .fail_next(reqwest::StatusCode::TOO_MANY_REQUESTS, 2);
+        let anime = context
+            .task
+            .search("hello")
+            .await
+            .expect("failed to search");
+        assert!(!anime.is_empty());
+        assert_eq!(context.server.request_count("/api/edge/anime"), 3);
+
+        context.shutdown().await;
+    }
 }
@@ -0,0 +1,9 @@
+use super::image_cache::get_image_extension;
+use std::num::NonZeroU64;
+
+/// Get the path of a library image, relative to the data directory.
+///
+/// Library images are kept out of the image cache directories, so they are never evicted.
+pub fn get_library_image_path(id: NonZeroU64, name: &str, url: &url::Url) -> String {
+    format!("library/{id}-{name}.{}", get_image_extension(url))
+}
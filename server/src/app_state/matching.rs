@@ -0,0 +1,357 @@
+use super::Database;
+use super::KitsuAnime;
+use super::VidstreamingAnimeMapping;
+use anyhow::Context;
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::num::NonZeroU64;
+use tracing::debug;
+use vidstreaming::AnimeType;
+use vidstreaming::SearchEntry;
+
+/// The maximum number of titles to search for.
+const MAX_SEARCHES: usize = 3;
+
+/// The minimum score for a candidate to be accepted as a match.
+const MIN_MATCH_SCORE: f64 = 0.5;
+
+/// The weight of the title similarity in the score of a candidate.
+const TITLE_WEIGHT: f64 = 0.7;
+
+/// The weight of the episode count in the score of a candidate.
+const EPISODE_WEIGHT: f64 = 0.2;
+
+/// The weight of the subtype in the score of a candidate.
+const SUBTYPE_WEIGHT: f64 = 0.1;
+
+/// A kitsu anime to find on vidstreaming
+#[derive(Debug)]
+pub struct MatchQuery {
+    /// The titles of the anime, starting with the canonical title.
+    pub titles: Vec<String>,
+
+    /// The number of episodes, if known.
+    pub episode_count: Option<u64>,
+
+    /// The subtype of the anime, if known.
+    pub subtype: Option<kitsu::Subtype>,
+
+    /// The track to find
+    pub anime_type: AnimeType,
+}
+
+impl MatchQuery {
+    /// Make a [`MatchQuery`] from a cached kitsu anime.
+    pub fn from_kitsu_anime(anime: &KitsuAnime, anime_type: AnimeType) -> Self {
+        let mut titles = vec![anime.title.clone()];
+
+        // Vidstreaming only uses latin titles.
+        let alternate_titles = anime
+            .titles
+            .iter()
+            .chain(anime.abbreviated_titles.iter())
+            .filter(|title| title.is_ascii());
+        for title in alternate_titles {
+            if !titles
+                .iter()
+                .any(|existing| existing.eq_ignore_ascii_case(title))
+            {
+                titles.push(title.clone());
+            }
+        }
+
+        Self {
+            titles,
+            episode_count: anime.episode_count,
+            subtype: anime.subtype.clone(),
+            anime_type,
+        }
+    }
+}
+
+/// A vidstreaming anime that matched a kitsu anime
+#[derive(Debug)]
+pub struct Match {
+    /// The vidstreaming anime slug
+    pub slug: String,
+
+    /// The score, from 0 to 1.
+    pub score: f64,
+}
+
+/// Get the vidstreaming anime that a cached kitsu anime maps to.
+///
+/// If there is no saved mapping, vidstreaming is searched for the best match, which is saved.
+pub async fn get_vidstreaming_anime_mapping(
+    database: &Database,
+    client: &vidstreaming::Client,
+    anime_id: NonZeroU64,
+    anime_type: AnimeType,
+) -> anyhow::Result<VidstreamingAnimeMapping> {
+    if let Some(mapping) = database
+        .get_vidstreaming_anime_mapping(anime_id, anime_type)
+        .await?
+    {
+        return Ok(mapping);
+    }
+
+    let anime = database
+        .get_kitsu_anime(anime_id)
+        .await?
+        .with_context(|| format!("kitsu anime {anime_id} is not in the database"))?;
+    let query = MatchQuery::from_kitsu_anime(&anime, anime_type);
+    let found = find_match(client, &query)
+        .await?
+        .with_context(|| format!("no vidstreaming anime matches \"{}\"", query.titles[0]))?;
+    debug!(
+        "mapped kitsu anime {anime_id} to vidstreaming anime \"{}\" with score {}",
+        found.slug, found.score
+    );
+
+    let mapping = VidstreamingAnimeMapping {
+        anime_id,
+        anime_type,
+        slug: found.slug,
+        score: Some(found.score),
+        manual: false,
+    };
+    database
+        .upsert_vidstreaming_anime_mapping(mapping.clone())
+        .await?;
+
+    Ok(mapping)
+}
+
+/// Search vidstreaming for the anime that best matches the query.
+///
+/// Returns `None` if no candidate scored high enough.
+pub async fn find_match(
+    client: &vidstreaming::Client,
+    query: &MatchQuery,
+) -> anyhow::Result<Option<Match>> {
+    let mut candidates: HashMap<String, f64> = HashMap::new();
+    for title in query.titles.iter().take(MAX_SEARCHES) {
+        let results = client
+            .search(title, NonZeroU32::MIN)
+            .await
+            .with_context(|| format!("failed to search vidstreaming for \"{title}\""))?;
+
+        for entry in results.entries.iter() {
+            if entry.anime_type() != query.anime_type {
+                continue;
+            }
+            let slug = match entry.anime_slug() {
+                Some(slug) => slug,
+                None => continue,
+            };
+
+            let score = score_entry(query, entry);
+            debug!("scored vidstreaming candidate \"{slug}\" as {score}");
+
+            let candidate_score = candidates.entry(slug.to_string()).or_insert(score);
+            *candidate_score = candidate_score.max(score);
+        }
+    }
+
+    Ok(select_match(candidates))
+}
+
+/// Select the best scoring candidate.
+///
+/// Returns `None` if no candidate scored at least [`MIN_MATCH_SCORE`].
+fn select_match(candidates: HashMap<String, f64>) -> Option<Match> {
+    candidates
+        .into_iter()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .filter(|(_, score)| *score >= MIN_MATCH_SCORE)
+        .map(|(slug, score)| Match { slug, score })
+}
+
+/// Score a search entry against the query, from 0 to 1.
+fn score_entry(query: &MatchQuery, entry: &SearchEntry) -> f64 {
+    let entry_title = entry.anime_title();
+
+    let title_score = query
+        .titles
+        .iter()
+        .map(|title| get_title_similarity(title, entry_title))
+        .fold(0.0, f64::max);
+
+    // Search results list the latest episode,
+    // so airing anime may have fewer episodes than expected, but never more.
+    let episode_score = match (query.episode_count, entry.episode_number()) {
+        (Some(episode_count), Some(episode_number)) if episode_count > 0 => {
+            let episode_number = u64::from(episode_number);
+            if episode_number <= episode_count {
+                1.0
+            } else {
+                episode_count as f64 / episode_number as f64
+            }
+        }
+        _ => 0.5,
+    };
+
+    let entry_title = entry_title.to_lowercase();
+    let is_movie = entry_title.contains("movie") || entry_title.contains("film");
+    let is_extra = ["ova", "ona", "special"]
+        .iter()
+        .any(|word| entry_title.split_whitespace().any(|part| part == *word));
+    let subtype_score = match query.subtype {
+        Some(kitsu::Subtype::Movie) if is_movie => 1.0,
+        Some(kitsu::Subtype::Movie) => 0.0,
+        Some(kitsu::Subtype::Ova | kitsu::Subtype::Ona | kitsu::Subtype::Special) if is_extra => {
+            1.0
+        }
+        Some(kitsu::Subtype::Ova | kitsu::Subtype::Ona | kitsu::Subtype::Special) => 0.5,
+        Some(kitsu::Subtype::Tv) if is_movie || is_extra => 0.0,
+        Some(kitsu::Subtype::Tv) => 1.0,
+        _ => 0.5,
+    };
+
+    (TITLE_WEIGHT * title_score)
+        + (EPISODE_WEIGHT * episode_score)
+        + (SUBTYPE_WEIGHT * subtype_score)
+}
+
+/// Get the similarity of two titles, from 0 to 1.
+///
+/// This is the Sørensen–Dice coefficient of the character bigrams of the normalized titles.
+fn get_title_similarity(a: &str, b: &str) -> f64 {
+    let a = normalize_title(a);
+    let b = normalize_title(b);
+
+    if a == b {
+        return 1.0;
+    }
+
+    let a_bigrams = get_bigrams(&a);
+    let mut b_bigrams = get_bigrams(&b);
+    let total = a_bigrams.len() + b_bigrams.len();
+    if total == 0 {
+        return 0.0;
+    }
+
+    let mut matches = 0;
+    for bigram in a_bigrams {
+        if let Some(index) = b_bigrams.iter().position(|b_bigram| *b_bigram == bigram) {
+            b_bigrams.swap_remove(index);
+            matches += 1;
+        }
+    }
+
+    (2 * matches) as f64 / total as f64
+}
+
+/// Normalize a title for comparison.
+///
+/// Titles are lowercased, and punctuation is removed.
+fn normalize_title(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Get the character bigrams of a string.
+fn get_bigrams(value: &str) -> Vec<(char, char)> {
+    let chars: Vec<char> = value.chars().collect();
+    chars.windows(2).map(|pair| (pair[0], pair[1])).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn make_query(
+        title: &str,
+        episode_count: Option<u64>,
+        subtype: Option<kitsu::Subtype>,
+    ) -> MatchQuery {
+        MatchQuery {
+            titles: vec![title.to_string()],
+            episode_count,
+            subtype,
+            anime_type: AnimeType::Sub,
+        }
+    }
+
+    fn make_entry(name: &str, slug: &str, episode_number: u32) -> SearchEntry {
+        SearchEntry {
+            name: name.to_string(),
+            url: format!("https://example.com/videos/{slug}-episode-{episode_number}")
+                .parse()
+                .unwrap(),
+        }
+    }
+
+    fn score_slug(query: &MatchQuery, entry: &SearchEntry) -> HashMap<String, f64> {
+        let slug = entry.anime_slug().unwrap().to_string();
+        HashMap::from([(slug, score_entry(query, entry))])
+    }
+
+    #[test]
+    fn exact_title_match() {
+        assert!(get_title_similarity("Cowboy Bebop", "Cowboy Bebop") == 1.0);
+
+        let query = make_query("Cowboy Bebop", Some(26), Some(kitsu::Subtype::Tv));
+        let entry = make_entry("Cowboy Bebop Episode 26", "cowboy-bebop", 26);
+        assert!((score_entry(&query, &entry) - 1.0).abs() < 1e-9);
+
+        let best = select_match(score_slug(&query, &entry)).expect("missing match");
+        assert!(best.slug == "cowboy-bebop");
+    }
+
+    #[test]
+    fn title_normalization() {
+        assert!(
+            normalize_title("Re:ZERO -Starting Life in Another World-")
+                == "re zero starting life in another world"
+        );
+        assert!(
+            normalize_title("  FULLMETAL   Alchemist: Brotherhood!  ")
+                == "fullmetal alchemist brotherhood"
+        );
+        assert!(get_title_similarity("Steins;Gate", "steins gate") == 1.0);
+        assert!(get_title_similarity("Steins;Gate", "Cowboy Bebop") < 0.1);
+    }
+
+    #[test]
+    fn airing_episode_count() {
+        let query = make_query("One Piece", Some(1100), Some(kitsu::Subtype::Tv));
+
+        // An airing anime lists fewer episodes than expected.
+        let airing = make_entry("One Piece Episode 1000", "one-piece", 1000);
+        assert!((score_entry(&query, &airing) - 1.0).abs() < 1e-9);
+
+        // More episodes than expected is a different anime.
+        let longer = make_entry("One Piece Episode 2200", "one-piece", 2200);
+        let longer_score = score_entry(&query, &longer);
+        assert!((longer_score - (1.0 - EPISODE_WEIGHT / 2.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn subtype_penalty() {
+        let entry = make_entry("Cowboy Bebop Movie Episode 1", "cowboy-bebop-movie", 1);
+
+        let tv_query = make_query("Cowboy Bebop Movie", None, Some(kitsu::Subtype::Tv));
+        let movie_query = make_query("Cowboy Bebop Movie", None, Some(kitsu::Subtype::Movie));
+        let tv_score = score_entry(&tv_query, &entry);
+        let movie_score = score_entry(&movie_query, &entry);
+        assert!((movie_score - tv_score - SUBTYPE_WEIGHT).abs() < 1e-9);
+
+        let tv_entry = make_entry("Cowboy Bebop Episode 26", "cowboy-bebop", 26);
+        assert!(score_entry(&movie_query, &tv_entry) < score_entry(&tv_query, &tv_entry));
+    }
+
+    #[test]
+    fn reject_low_score() {
+        let query = make_query("Bleach", Some(366), Some(kitsu::Subtype::Tv));
+        let entry = make_entry("Naruto Shippuden Episode 500", "naruto-shippuden", 500);
+
+        let score = score_entry(&query, &entry);
+        assert!(score < MIN_MATCH_SCORE);
+        assert!(select_match(score_slug(&query, &entry)).is_none());
+    }
+}
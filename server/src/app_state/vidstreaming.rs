@@ -1,59 +1,184 @@
-use crate::util::AbortJoinHandle;
+use super::database::VidstreamingDownload;
+use super::database::VidstreamingDownloadQueueEntry;
+use super::Database;
 use anyhow::anyhow;
+use anyhow::bail;
 use anyhow::ensure;
 use anyhow::Context;
+use bewu_util::HostLimiter;
+use bewu_util::QualityPreference;
 use nd_util::ArcAnyhowError;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::num::NonZeroU64;
+use std::num::NonZeroUsize;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use tokio::task::JoinSet;
 use tokio_stream::Stream;
 use tokio_stream::StreamExt;
 use tracing::debug;
 use tracing::error;
+use tracing::info;
 use tracing::trace;
+use tracing::warn;
+use url::Url;
+use vidstreaming::AnimeType;
 
 #[derive(Debug)]
 pub struct VidstreamingEpisode {
+    /// The path of the episode file, relative to the vidstreaming directory.
     pub path: Option<String>,
 }
 
+/// An entry in the download queue
+#[derive(Debug, Clone)]
+pub struct DownloadQueueEntry {
+    /// The kitsu episode id
+    pub episode_id: NonZeroU64,
+
+    /// The track of the episode
+    pub anime_type: AnimeType,
+
+    /// The kitsu anime slug
+    pub anime_slug: Box<str>,
+
+    /// The episode number, in the current season.
+    pub episode_number: u32,
+
+    /// The download slot this entry is using.
+    ///
+    /// If this is `None`, the entry is waiting in the queue.
+    pub slot: Option<usize>,
+
+    /// Whether this entry is paused.
+    pub paused: bool,
+}
+
+/// A request to download an episode
+#[derive(Debug)]
+pub struct DownloadRequest {
+    /// The kitsu episode id
+    pub episode_id: NonZeroU64,
+
+    /// The kitsu anime id
+    pub anime_id: NonZeroU64,
+
+    /// The track of the episode
+    pub anime_type: AnimeType,
+
+    /// The kitsu anime slug
+    pub anime_slug: Box<str>,
+
+    /// The vidstreaming anime slug.
+    ///
+    /// If this is `None`, the slug is guessed from the kitsu anime slug.
+    pub vidstreaming_slug: Option<Box<str>>,
+
+    /// The episode number, in the current season.
+    pub episode_number: u32,
+
+    /// The expected length of the episode, in minutes.
+    pub length_minutes: Option<u32>,
+
+    /// The quality preference
+    pub quality: QualityPreference,
+}
+
 #[derive(Debug)]
 pub enum VidstreamingTaskMessage {
     Close {
         tx: tokio::sync::oneshot::Sender<()>,
     },
     StartEpisodeDownload {
-        anime_slug: Box<str>,
-        episode_number: u32,
+        request: DownloadRequest,
 
         tx: tokio::sync::oneshot::Sender<
             anyhow::Result<bewu_util::StateUpdateRx<CloneDownloadState>>,
         >,
     },
     GetEpisode {
+        anime_type: AnimeType,
         anime_slug: Box<str>,
         episode_number: u32,
 
         tx: tokio::sync::oneshot::Sender<anyhow::Result<VidstreamingEpisode>>,
     },
+    GetDownloadQueue {
+        tx: tokio::sync::oneshot::Sender<Vec<DownloadQueueEntry>>,
+    },
+    MoveDownloadQueueEntry {
+        episode_id: NonZeroU64,
+        anime_type: AnimeType,
+        position: usize,
+
+        tx: tokio::sync::oneshot::Sender<anyhow::Result<()>>,
+    },
+    CancelEpisodeDownload {
+        episode_id: NonZeroU64,
+        anime_type: AnimeType,
+
+        tx: tokio::sync::oneshot::Sender<anyhow::Result<()>>,
+    },
+    PauseEpisodeDownload {
+        episode_id: NonZeroU64,
+        anime_type: AnimeType,
+
+        tx: tokio::sync::oneshot::Sender<anyhow::Result<()>>,
+    },
+    ResumeEpisodeDownload {
+        episode_id: NonZeroU64,
+        anime_type: AnimeType,
+
+        tx: tokio::sync::oneshot::Sender<anyhow::Result<()>>,
+    },
 }
 
+/// The number of finished downloads to keep in the downloads state.
+const RECENT_DOWNLOADS_MAX: usize = 32;
+
 #[derive(Debug)]
 pub struct VidstreamingTask {
     tx: tokio::sync::mpsc::Sender<VidstreamingTaskMessage>,
     handle: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>>,
+
+    downloads_rx: bewu_util::StateUpdateRx<CloneDownloadsState>,
 }
 
 impl VidstreamingTask {
-    pub fn new<P>(path: P) -> Self
+    /// Make a new [`VidstreamingTask`].
+    ///
+    /// Up to `max_concurrent` episodes will be downloaded at once.
+    /// Episodes are stored in a sub directory of `path` for each anime type.
+    pub fn new<P>(
+        database: Database,
+        path: P,
+        max_concurrent: NonZeroUsize,
+        host_limiter: HostLimiter,
+    ) -> Self
     where
         P: AsRef<Path>,
     {
         let (tx, rx) = tokio::sync::mpsc::channel(32);
-        let handle = tokio::spawn(vidstreaming_task_impl(rx, path.as_ref().into()));
+        let (downloads_tx, downloads_rx) =
+            bewu_util::state_update_channel(128, CloneDownloadsState::new());
+        let handle = tokio::spawn(vidstreaming_task_impl(
+            rx,
+            downloads_tx,
+            database,
+            path.as_ref().into(),
+            max_concurrent,
+            host_limiter,
+        ));
 
         Self {
             tx,
             handle: std::sync::Mutex::new(Some(handle)),
+
+            downloads_rx,
         }
     }
 
@@ -63,18 +188,18 @@ impl VidstreamingTask {
         Ok(rx.await?)
     }
 
+    /// Queue an episode download.
+    ///
+    /// If the episode is already queued, this returns a stream for the existing download.
+    /// The episode length is used to estimate progress if the source does not report its duration.
+    /// The quality preference is used to select a source and a hls variant stream.
     pub async fn start_episode_download(
         &self,
-        anime_slug: &str,
-        episode_number: u32,
+        request: DownloadRequest,
     ) -> anyhow::Result<impl Stream<Item = bewu_util::StateUpdateItem<CloneDownloadState>>> {
         let (tx, rx) = tokio::sync::oneshot::channel();
         self.tx
-            .send(VidstreamingTaskMessage::StartEpisodeDownload {
-                anime_slug: anime_slug.into(),
-                episode_number,
-                tx,
-            })
+            .send(VidstreamingTaskMessage::StartEpisodeDownload { request, tx })
             .await?;
 
         Ok(rx.await??.into_stream())
@@ -82,12 +207,14 @@ impl VidstreamingTask {
 
     pub async fn get_episode(
         &self,
+        anime_type: AnimeType,
         anime_slug: &str,
         episode_number: u32,
     ) -> anyhow::Result<VidstreamingEpisode> {
         let (tx, rx) = tokio::sync::oneshot::channel();
         self.tx
             .send(VidstreamingTaskMessage::GetEpisode {
+                anime_type,
                 anime_slug: anime_slug.into(),
                 episode_number,
                 tx,
@@ -96,6 +223,109 @@ impl VidstreamingTask {
         rx.await?
     }
 
+    /// Get the download queue, starting with the active downloads.
+    pub async fn get_download_queue(&self) -> anyhow::Result<Vec<DownloadQueueEntry>> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.tx
+            .send(VidstreamingTaskMessage::GetDownloadQueue { tx })
+            .await?;
+        Ok(rx.await?)
+    }
+
+    /// Get a snapshot of all active, queued, and recently finished downloads.
+    pub fn get_downloads(&self) -> Vec<DownloadSummary> {
+        self.downloads_rx.state_ref().get_inner().downloads.clone()
+    }
+
+    /// Get a stream of updates for all downloads.
+    ///
+    /// The stream starts with the current state.
+    pub fn subscribe_downloads(
+        &self,
+    ) -> impl Stream<Item = bewu_util::StateUpdateItem<CloneDownloadsState>> {
+        self.downloads_rx.clone().into_stream()
+    }
+
+    /// Move a queued download to the given position in the queue.
+    ///
+    /// Positions start at 0, which is the next download to be started.
+    pub async fn move_download_queue_entry(
+        &self,
+        episode_id: NonZeroU64,
+        anime_type: AnimeType,
+        position: usize,
+    ) -> anyhow::Result<()> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.tx
+            .send(VidstreamingTaskMessage::MoveDownloadQueueEntry {
+                episode_id,
+                anime_type,
+                position,
+                tx,
+            })
+            .await?;
+        rx.await?
+    }
+
+    /// Cancel an episode download.
+    ///
+    /// This works for both queued and in-progress downloads.
+    /// Any partially downloaded files are removed.
+    pub async fn cancel_episode_download(
+        &self,
+        episode_id: NonZeroU64,
+        anime_type: AnimeType,
+    ) -> anyhow::Result<()> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.tx
+            .send(VidstreamingTaskMessage::CancelEpisodeDownload {
+                episode_id,
+                anime_type,
+                tx,
+            })
+            .await?;
+        rx.await?
+    }
+
+    /// Pause an episode download.
+    ///
+    /// In-progress downloads are stopped and give up their download slot.
+    /// Partially downloaded files are kept, so they can be reused when the download is resumed.
+    pub async fn pause_episode_download(
+        &self,
+        episode_id: NonZeroU64,
+        anime_type: AnimeType,
+    ) -> anyhow::Result<()> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.tx
+            .send(VidstreamingTaskMessage::PauseEpisodeDownload {
+                episode_id,
+                anime_type,
+                tx,
+            })
+            .await?;
+        rx.await?
+    }
+
+    /// Resume a paused episode download.
+    ///
+    /// The download keeps its position in the queue.
+    pub async fn resume_episode_download(
+        &self,
+        episode_id: NonZeroU64,
+        anime_type: AnimeType,
+    ) -> anyhow::Result<()> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.tx
+            .send(VidstreamingTaskMessage::ResumeEpisodeDownload {
+                episode_id,
+                anime_type,
+                tx,
+            })
+            .await?;
+        rx.await?
+    }
+
     pub async fn join(&self) -> anyhow::Result<()> {
         let handle = self
             .handle
@@ -113,74 +343,557 @@ impl VidstreamingTask {
     }
 }
 
+/// A download waiting in the queue
+#[derive(Debug)]
+struct QueuedDownload {
+    episode_id: NonZeroU64,
+    anime_id: NonZeroU64,
+    anime_type: AnimeType,
+    anime_slug: Box<str>,
+    episode_number: u32,
+    vidstreaming_slug: Option<Box<str>>,
+    length_minutes: Option<u32>,
+    quality: QualityPreference,
+    paused: bool,
+
+    /// The time this download was given a download slot.
+    started_at: Option<SystemTime>,
+
+    tx: DownloadStateTx,
+    rx: bewu_util::StateUpdateRx<CloneDownloadState>,
+}
+
+impl QueuedDownload {
+    /// Make a new [`QueuedDownload`] from its saved queue entry.
+    fn new(
+        entry: VidstreamingDownloadQueueEntry,
+        downloads_tx: bewu_util::StateUpdateTx<CloneDownloadsState>,
+    ) -> Self {
+        let (tx, rx) = bewu_util::state_update_channel(128, CloneDownloadState::new());
+        let tx = DownloadStateTx {
+            episode_id: entry.episode_id,
+            anime_type: entry.anime_type,
+            tx,
+            downloads_tx,
+        };
+
+        let paused = entry.paused;
+        let download = Self {
+            episode_id: entry.episode_id,
+            anime_id: entry.anime_id,
+            anime_type: entry.anime_type,
+            anime_slug: entry.anime_slug.into(),
+            episode_number: entry.episode_number,
+            vidstreaming_slug: entry.vidstreaming_slug.map(Into::into),
+            length_minutes: entry.length_minutes,
+            quality: QualityPreference {
+                max_resolution: entry.max_resolution,
+                max_bandwidth: entry.max_bandwidth,
+                preferred_codecs: split_preferred_codecs(&entry.preferred_codecs),
+            },
+            paused,
+
+            started_at: None,
+
+            tx,
+            rx,
+        };
+        download.send_status(if paused {
+            DownloadStatus::Paused
+        } else {
+            DownloadStatus::Queued
+        });
+        download.tx.send(if paused { "paused" } else { "queued" });
+
+        download
+    }
+
+    /// Check if this is the download of the given episode and anime type.
+    fn is_episode(&self, episode_id: NonZeroU64, anime_type: AnimeType) -> bool {
+        self.episode_id == episode_id && self.anime_type == anime_type
+    }
+
+    /// Publish the status of this download to the downloads state.
+    fn send_status(&self, status: DownloadStatus) {
+        self.tx.downloads_tx.send(DownloadsStateUpdate::Status {
+            download: DownloadSummary {
+                episode_id: self.episode_id,
+                anime_type: self.anime_type,
+                status,
+                started_at: self.started_at,
+                state: self.rx.state_ref().clone(),
+            },
+        });
+    }
+
+    fn to_entry(&self, slot: Option<usize>) -> DownloadQueueEntry {
+        DownloadQueueEntry {
+            episode_id: self.episode_id,
+            anime_type: self.anime_type,
+            anime_slug: self.anime_slug.clone(),
+            episode_number: self.episode_number,
+            slot,
+            paused: self.paused,
+        }
+    }
+}
+
+/// A download that is using a download slot
+#[derive(Debug)]
+struct ActiveDownload {
+    abort_handle: tokio::task::AbortHandle,
+    download: QueuedDownload,
+
+    /// Why the download task was aborted, if it was.
+    stop_reason: Option<StopReason>,
+}
+
+/// The reason an in-progress download was stopped
+#[derive(Debug, Clone, Copy)]
+enum StopReason {
+    Pause,
+    Cancel,
+}
+
 async fn vidstreaming_task_impl(
     mut rx: tokio::sync::mpsc::Receiver<VidstreamingTaskMessage>,
+    downloads_tx: bewu_util::StateUpdateTx<CloneDownloadsState>,
+    database: Database,
     path: Arc<Path>,
+    max_concurrent: NonZeroUsize,
+    host_limiter: HostLimiter,
 ) {
-    let client = vidstreaming::Client::new();
+    let context = DownloadContext {
+        client: vidstreaming::Client::new(),
+        host_limiter,
+        database: database.clone(),
+        path: path.clone(),
+    };
 
-    let mut download_task: Option<AbortJoinHandle<()>> = None;
+    let mut download_queue: VecDeque<QueuedDownload> = VecDeque::new();
+    let mut download_slots: Vec<Option<ActiveDownload>> = std::iter::repeat_with(|| None)
+        .take(max_concurrent.get())
+        .collect();
+    let mut join_set = JoinSet::new();
+    let mut recent_downloads: VecDeque<(NonZeroU64, AnimeType)> = VecDeque::new();
 
-    while let Some(message) = rx.recv().await {
-        match message {
-            VidstreamingTaskMessage::Close { tx } => {
-                rx.close();
-                let _ = tx.send(()).is_ok();
+    // Resume downloads that were queued before the last shutdown.
+    match database
+        .get_vidstreaming_download_queue()
+        .await
+        .context("failed to load download queue")
+    {
+        Ok(entries) => {
+            if !entries.is_empty() {
+                info!("resuming {} queued downloads", entries.len());
             }
-            VidstreamingTaskMessage::StartEpisodeDownload {
-                anime_slug,
-                episode_number,
-                tx,
-            } => {
-                let result = async {
-                    let (tx, rx) = bewu_util::state_update_channel(128, CloneDownloadState::new());
 
-                    if let Some(handle) = download_task.as_ref() {
-                        ensure!(
-                            handle.as_ref().is_finished(),
-                            "another download is already in progress"
-                        );
-                        let handle = download_task.take().unwrap();
-                        if let Err(e) = handle.into_inner().await.context("failed to join task") {
-                            error!("{e:?}");
-                        }
+            for entry in entries {
+                download_queue.push_back(QueuedDownload::new(entry, downloads_tx.clone()));
+            }
+        }
+        Err(error) => {
+            error!("{error:?}");
+        }
+    }
+
+    loop {
+        // Fill any empty download slots, skipping paused downloads
+        for slot in download_slots.iter_mut().filter(|slot| slot.is_none()) {
+            let index = match download_queue.iter().position(|download| !download.paused) {
+                Some(index) => index,
+                None => break,
+            };
+            let mut download = download_queue.remove(index).unwrap();
+            download.started_at = Some(SystemTime::now());
+            download.send_status(DownloadStatus::Active);
+
+            let episode = DownloadEpisode {
+                episode_id: download.episode_id,
+                anime_id: download.anime_id,
+                anime_type: download.anime_type,
+                anime_slug: download.anime_slug.clone(),
+                episode_number: download.episode_number,
+                vidstreaming_slug: download.vidstreaming_slug.clone(),
+                length_minutes: download.length_minutes,
+            };
+            let handle = join_set.spawn(download_task_impl(
+                context.clone(),
+                episode,
+                download.quality.clone(),
+                download.tx.clone(),
+            ));
+            *slot = Some(ActiveDownload {
+                abort_handle: handle,
+                download,
+                stop_reason: None,
+            });
+        }
+
+        tokio::select! {
+            message = rx.recv() => {
+                match message {
+                    Some(VidstreamingTaskMessage::Close { tx }) => {
+                        rx.close();
+                        let _ = tx.send(()).is_ok();
                     }
+                    Some(VidstreamingTaskMessage::StartEpisodeDownload { request, tx }) => {
+                        let result = async {
+                            let DownloadRequest {
+                                episode_id,
+                                anime_id,
+                                anime_type,
+                                anime_slug,
+                                vidstreaming_slug,
+                                episode_number,
+                                length_minutes,
+                                quality,
+                            } = request;
+
+                            let existing = download_slots
+                                .iter()
+                                .flatten()
+                                .map(|active| &active.download)
+                                .chain(download_queue.iter())
+                                .find(|download| download.is_episode(episode_id, anime_type));
+                            if let Some(download) = existing {
+                                return Ok(download.rx.clone());
+                            }
+
+                            let entry = VidstreamingDownloadQueueEntry {
+                                episode_id,
+                                anime_id,
+                                anime_type,
+                                anime_slug: anime_slug.into(),
+                                episode_number,
+                                vidstreaming_slug: vidstreaming_slug.map(Into::into),
+                                length_minutes,
+                                max_resolution: quality.max_resolution,
+                                max_bandwidth: quality.max_bandwidth,
+                                preferred_codecs: quality.preferred_codecs.join(","),
+                                paused: false,
+                            };
+                            database
+                                .push_vidstreaming_download_queue_entry(entry.clone())
+                                .await
+                                .context("failed to save download queue entry")?;
 
-                    let handle = tokio::spawn(download_task_impl(
-                        client.clone(),
+                            // A new download replaces the finished one in the downloads state.
+                            recent_downloads.retain(|id| *id != (episode_id, anime_type));
+
+                            let download = QueuedDownload::new(entry, downloads_tx.clone());
+                            let rx = download.rx.clone();
+                            download_queue.push_back(download);
+
+                            Ok(rx)
+                        }
+                        .await;
+
+                        let _ = tx.send(result).is_ok();
+                    }
+                    Some(VidstreamingTaskMessage::GetEpisode {
+                        anime_type,
                         anime_slug,
                         episode_number,
-                        path.clone(),
                         tx,
-                    ));
-                    download_task = Some(AbortJoinHandle::new(handle));
+                    }) => {
+                        let result = async {
+                            let episode_path =
+                                get_episode_path(anime_type, &anime_slug, episode_number);
+                            let path = path.join(&episode_path);
 
-                    Ok(rx)
-                }
-                .await;
+                            if tokio::fs::try_exists(&path).await? {
+                                Ok(VidstreamingEpisode {
+                                    path: Some(episode_path),
+                                })
+                            } else {
+                                Ok(VidstreamingEpisode { path: None })
+                            }
+                        }
+                        .await;
 
-                let _ = tx.send(result).is_ok();
-            }
-            VidstreamingTaskMessage::GetEpisode {
-                anime_slug,
-                episode_number,
-                tx,
-            } => {
-                let result = async {
-                    let file_name = get_episode_file_name(&anime_slug, episode_number);
-                    let path = path.join(&file_name);
-
-                    if tokio::fs::try_exists(&path).await? {
-                        Ok(VidstreamingEpisode {
-                            path: Some(file_name),
-                        })
-                    } else {
-                        Ok(VidstreamingEpisode { path: None })
+                        let _ = tx.send(result).is_ok();
+                    }
+                    Some(VidstreamingTaskMessage::GetDownloadQueue { tx }) => {
+                        let entries = download_slots
+                            .iter()
+                            .enumerate()
+                            .filter_map(|(slot, active)| {
+                                Some(active.as_ref()?.download.to_entry(Some(slot)))
+                            })
+                            .chain(download_queue.iter().map(|download| download.to_entry(None)))
+                            .collect();
+
+                        let _ = tx.send(entries).is_ok();
+                    }
+                    Some(VidstreamingTaskMessage::MoveDownloadQueueEntry {
+                        episode_id,
+                        anime_type,
+                        position,
+                        tx,
+                    }) => {
+                        let result = async {
+                            let index = match download_queue
+                                .iter()
+                                .position(|download| download.is_episode(episode_id, anime_type))
+                            {
+                                Some(index) => index,
+                                None if download_slots
+                                    .iter()
+                                    .flatten()
+                                    .any(|active| active.download.is_episode(episode_id, anime_type)) =>
+                                {
+                                    bail!("the download is already in progress");
+                                }
+                                None => bail!("the episode is not queued"),
+                            };
+
+                            let download = download_queue.remove(index).unwrap();
+                            let position = position.min(download_queue.len());
+                            download_queue.insert(position, download);
+
+                            let entries = download_slots
+                                .iter()
+                                .flatten()
+                                .map(|active| &active.download)
+                                .chain(download_queue.iter())
+                                .map(|download| (download.episode_id, download.anime_type))
+                                .collect();
+                            database
+                                .set_vidstreaming_download_queue_order(entries)
+                                .await
+                                .context("failed to save download queue order")
+                        }
+                        .await;
+
+                        let _ = tx.send(result).is_ok();
+                    }
+                    Some(VidstreamingTaskMessage::CancelEpisodeDownload {
+                        episode_id,
+                        anime_type,
+                        tx,
+                    }) => {
+                        let result = async {
+                            let active = download_slots
+                                .iter_mut()
+                                .flatten()
+                                .find(|active| active.download.is_episode(episode_id, anime_type));
+                            if let Some(active) = active {
+                                // The download is cleaned up when the task is joined.
+                                active.stop_reason = Some(StopReason::Cancel);
+                                active.abort_handle.abort();
+                                return Ok(());
+                            }
+
+                            let index = download_queue
+                                .iter()
+                                .position(|download| download.is_episode(episode_id, anime_type))
+                                .context("the episode is not queued")?;
+
+                            database
+                                .remove_vidstreaming_download_queue_entry(episode_id, anime_type)
+                                .await
+                                .context("failed to remove download queue entry")?;
+
+                            // Dropping the sender will close the streams of any listeners.
+                            let download = download_queue.remove(index).unwrap();
+
+                            // Paused downloads may have left temp files behind.
+                            remove_episode_temp_files(
+                                &path,
+                                anime_type,
+                                &download.anime_slug,
+                                download.episode_number,
+                            )
+                            .await?;
+
+                            download.tx.send("cancelled");
+                            downloads_tx.send(DownloadsStateUpdate::Removed {
+                                episode_id,
+                                anime_type,
+                            });
+
+                            Ok(())
+                        }
+                        .await;
+
+                        let _ = tx.send(result).is_ok();
+                    }
+                    Some(VidstreamingTaskMessage::PauseEpisodeDownload {
+                        episode_id,
+                        anime_type,
+                        tx,
+                    }) => {
+                        let result = async {
+                            let active = download_slots
+                                .iter_mut()
+                                .flatten()
+                                .find(|active| active.download.is_episode(episode_id, anime_type));
+                            if let Some(active) = active {
+                                if active.stop_reason.is_some() {
+                                    return Ok(());
+                                }
+
+                                database
+                                    .set_vidstreaming_download_queue_entry_paused(episode_id, anime_type, true)
+                                    .await
+                                    .context("failed to pause download queue entry")?;
+
+                                // The download is moved back to the queue when the task is joined.
+                                active.stop_reason = Some(StopReason::Pause);
+                                active.abort_handle.abort();
+                                return Ok(());
+                            }
+
+                            let download = download_queue
+                                .iter_mut()
+                                .find(|download| download.is_episode(episode_id, anime_type))
+                                .context("the episode is not queued")?;
+                            if download.paused {
+                                return Ok(());
+                            }
+
+                            database
+                                .set_vidstreaming_download_queue_entry_paused(episode_id, anime_type, true)
+                                .await
+                                .context("failed to pause download queue entry")?;
+
+                            download.paused = true;
+                            download.send_status(DownloadStatus::Paused);
+                            download.tx.send("paused");
+
+                            Ok(())
+                        }
+                        .await;
+
+                        let _ = tx.send(result).is_ok();
+                    }
+                    Some(VidstreamingTaskMessage::ResumeEpisodeDownload {
+                        episode_id,
+                        anime_type,
+                        tx,
+                    }) => {
+                        let result = async {
+                            let active = download_slots
+                                .iter()
+                                .flatten()
+                                .find(|active| active.download.is_episode(episode_id, anime_type));
+                            if let Some(active) = active {
+                                match active.stop_reason {
+                                    None => return Ok(()),
+                                    Some(StopReason::Pause) => bail!("the download is still pausing"),
+                                    Some(StopReason::Cancel) => bail!("the download was cancelled"),
+                                }
+                            }
+
+                            let download = download_queue
+                                .iter_mut()
+                                .find(|download| download.is_episode(episode_id, anime_type))
+                                .context("the episode is not queued")?;
+                            if !download.paused {
+                                return Ok(());
+                            }
+
+                            database
+                                .set_vidstreaming_download_queue_entry_paused(episode_id, anime_type, false)
+                                .await
+                                .context("failed to resume download queue entry")?;
+
+                            download.paused = false;
+                            download.send_status(DownloadStatus::Queued);
+                            download.tx.send("queued");
+
+                            Ok(())
+                        }
+                        .await;
+
+                        let _ = tx.send(result).is_ok();
+                    }
+                    None => {
+                        break;
                     }
                 }
-                .await;
+            }
+            Some(result) = join_set.join_next_with_id() => {
+                let (task_id, aborted) = match result {
+                    Ok((task_id, ())) => (task_id, false),
+                    Err(error) if error.is_cancelled() => (error.id(), true),
+                    Err(error) => {
+                        let task_id = error.id();
+                        warn!("{:?}", anyhow::Error::from(error).context("failed to join download task"));
+                        (task_id, false)
+                    }
+                };
 
-                let _ = tx.send(result).is_ok();
+                let active = download_slots
+                    .iter_mut()
+                    .find(|slot| {
+                        slot.as_ref()
+                            .is_some_and(|active| active.abort_handle.id() == task_id)
+                    })
+                    .and_then(|slot| slot.take());
+                if let Some(active) = active {
+                    // If the task finished before it could be aborted, treat it as finished.
+                    let stop_reason = if aborted { active.stop_reason } else { None };
+                    let mut download = active.download;
+
+                    match stop_reason {
+                        Some(StopReason::Pause) => {
+                            // The paused state was already persisted.
+                            // Put the download at the front, so it is next when resumed.
+                            download.paused = true;
+                            download.started_at = None;
+                            download.send_status(DownloadStatus::Paused);
+                            download.tx.send("paused");
+                            download_queue.push_front(download);
+                        }
+                        Some(StopReason::Cancel) => {
+                            if let Err(error) = remove_episode_temp_files(
+                                &path,
+                                download.anime_type,
+                                &download.anime_slug,
+                                download.episode_number,
+                            )
+                            .await
+                            {
+                                error!("{error:?}");
+                            }
+                            download.tx.send("cancelled");
+                            downloads_tx.send(DownloadsStateUpdate::Removed {
+                                episode_id: download.episode_id,
+                                anime_type: download.anime_type,
+                            });
+
+                            if let Err(error) = database
+                                .remove_vidstreaming_download_queue_entry(download.episode_id, download.anime_type)
+                                .await
+                                .context("failed to remove download queue entry")
+                            {
+                                error!("{error:?}");
+                            }
+                        }
+                        None => {
+                            download.send_status(DownloadStatus::Finished);
+                            recent_downloads.push_back((download.episode_id, download.anime_type));
+                            if recent_downloads.len() > RECENT_DOWNLOADS_MAX {
+                                if let Some((episode_id, anime_type)) = recent_downloads.pop_front() {
+                                    downloads_tx.send(DownloadsStateUpdate::Removed {
+                                        episode_id,
+                                        anime_type,
+                                    });
+                                }
+                            }
+
+                            if let Err(error) = database
+                                .remove_vidstreaming_download_queue_entry(download.episode_id, download.anime_type)
+                                .await
+                                .context("failed to remove download queue entry")
+                            {
+                                error!("{error:?}");
+                            }
+                        }
+                    }
+                }
             }
         }
     }
@@ -188,10 +901,29 @@ async fn vidstreaming_task_impl(
 
 #[derive(Debug, Clone)]
 pub enum DownloadStateUpdate {
-    Info { info: Arc<str> },
-    Progress { progress: f32 },
+    Info {
+        info: Arc<str>,
+    },
+    /// The percent complete, from 0 to 100.
+    Progress {
+        progress: f32,
+    },
+    TotalDuration {
+        total_duration: Duration,
+    },
+    Stats {
+        downloaded_bytes: u64,
+        throughput: f64,
+        eta: Option<Duration>,
+    },
+    /// The source that was downloaded.
+    Source {
+        source: DownloadSource,
+    },
 
-    Error { error: ArcAnyhowError },
+    Error {
+        error: ArcAnyhowError,
+    },
 }
 
 impl From<&str> for DownloadStateUpdate {
@@ -206,6 +938,12 @@ impl From<f32> for DownloadStateUpdate {
     }
 }
 
+impl From<Duration> for DownloadStateUpdate {
+    fn from(total_duration: Duration) -> Self {
+        Self::TotalDuration { total_duration }
+    }
+}
+
 impl From<anyhow::Error> for DownloadStateUpdate {
     fn from(error: anyhow::Error) -> Self {
         Self::Error {
@@ -214,12 +952,66 @@ impl From<anyhow::Error> for DownloadStateUpdate {
     }
 }
 
+/// The list a source came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceOrigin {
+    /// The `source` list.
+    Source,
+
+    /// The `source_bk` list.
+    SourceBk,
+
+    /// The video data of the `linkiframe` url.
+    LinkIframe,
+}
+
+impl SourceOrigin {
+    /// Get this as a str.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Source => "source",
+            Self::SourceBk => "source_bk",
+            Self::LinkIframe => "linkiframe",
+        }
+    }
+}
+
+/// A source that was downloaded
+#[derive(Debug, Clone)]
+pub struct DownloadSource {
+    /// The list the source came from
+    pub origin: SourceOrigin,
+
+    /// The source label
+    pub label: Arc<str>,
+
+    /// The source url
+    pub url: Arc<str>,
+}
+
 /// The state of a download
 #[derive(Debug)]
 pub struct DownloadState {
     pub info: Option<Arc<str>>,
+
+    /// The percent complete, from 0 to 100.
     pub progress: f32,
 
+    /// The duration of the episode, if known.
+    pub total_duration: Option<Duration>,
+
+    /// The number of bytes downloaded.
+    pub downloaded_bytes: u64,
+
+    /// The current throughput, in bytes per second.
+    pub throughput: f64,
+
+    /// The estimated time until the download completes.
+    pub eta: Option<Duration>,
+
+    /// The source that was downloaded.
+    pub source: Option<DownloadSource>,
+
     pub error: Option<ArcAnyhowError>,
 }
 
@@ -228,6 +1020,11 @@ impl DownloadState {
         Self {
             info: None,
             progress: 0.0,
+            total_duration: None,
+            downloaded_bytes: 0,
+            throughput: 0.0,
+            eta: None,
+            source: None,
 
             error: None,
         }
@@ -248,6 +1045,22 @@ impl DownloadState {
             DownloadStateUpdate::Progress { progress } => {
                 self.progress = *progress;
             }
+            DownloadStateUpdate::TotalDuration { total_duration } => {
+                // Later durations come from more accurate sources.
+                self.total_duration = Some(*total_duration);
+            }
+            DownloadStateUpdate::Stats {
+                downloaded_bytes,
+                throughput,
+                eta,
+            } => {
+                self.downloaded_bytes = *downloaded_bytes;
+                self.throughput = *throughput;
+                self.eta = *eta;
+            }
+            DownloadStateUpdate::Source { source } => {
+                self.source = Some(source.clone());
+            }
         }
     }
 }
@@ -277,23 +1090,348 @@ impl bewu_util::StateUpdateChannelState for CloneDownloadState {
     }
 }
 
-fn get_episode_file_name(anime_slug: &str, episode_number: u32) -> String {
-    format!("{anime_slug}-episode-{episode_number}.mp4")
+/// A sender for the updates of a single download.
+///
+/// Updates are also forwarded to the downloads state.
+#[derive(Debug, Clone)]
+struct DownloadStateTx {
+    episode_id: NonZeroU64,
+    anime_type: AnimeType,
+    tx: bewu_util::StateUpdateTx<CloneDownloadState>,
+    downloads_tx: bewu_util::StateUpdateTx<CloneDownloadsState>,
 }
 
-async fn download_task_impl(
+impl DownloadStateTx {
+    fn send(&self, update: impl Into<DownloadStateUpdate>) {
+        let update = update.into();
+
+        self.tx.send(update.clone());
+        self.downloads_tx.send(DownloadsStateUpdate::Download {
+            episode_id: self.episode_id,
+            anime_type: self.anime_type,
+            update,
+        });
+    }
+}
+
+/// Tracks the progress of a download, to calculate throughput and an ETA.
+#[derive(Debug)]
+struct DownloadProgressTracker {
+    start: Instant,
+
+    /// The total number of bytes downloaded, including resumed data.
+    downloaded_bytes: u64,
+
+    /// The number of bytes downloaded since the start, excluding resumed data.
+    transferred_bytes: u64,
+
+    /// The progress made by resumed data.
+    resumed_progress: f32,
+}
+
+impl DownloadProgressTracker {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            downloaded_bytes: 0,
+            transferred_bytes: 0,
+            resumed_progress: 0.0,
+        }
+    }
+
+    /// Add newly downloaded bytes.
+    fn add_bytes(&mut self, bytes: u64) {
+        self.downloaded_bytes += bytes;
+        self.transferred_bytes += bytes;
+    }
+
+    /// Add bytes from an earlier, interrupted download.
+    ///
+    /// These do not count towards the throughput or ETA.
+    fn add_resumed_bytes(&mut self, bytes: u64, progress: f32) {
+        self.downloaded_bytes += bytes;
+        self.resumed_progress += progress;
+    }
+
+    /// Set the total number of downloaded bytes.
+    fn set_bytes(&mut self, bytes: u64) {
+        self.downloaded_bytes = bytes;
+        self.transferred_bytes = bytes;
+    }
+
+    /// Send the progress, along with the throughput and ETA.
+    fn send_progress(&self, download_state: &DownloadStateTx, progress: f32) {
+        let elapsed = self.start.elapsed();
+
+        let throughput = if elapsed.is_zero() {
+            0.0
+        } else {
+            self.transferred_bytes as f64 / elapsed.as_secs_f64()
+        };
+
+        let progress_made = progress - self.resumed_progress;
+        let eta = if progress_made > 0.0 && progress < 100.0 {
+            let remaining = f64::from(100.0 - progress) / f64::from(progress_made);
+            Duration::try_from_secs_f64(elapsed.as_secs_f64() * remaining).ok()
+        } else {
+            None
+        };
+
+        download_state.send(progress);
+        download_state.send(DownloadStateUpdate::Stats {
+            downloaded_bytes: self.downloaded_bytes,
+            throughput,
+            eta,
+        });
+    }
+}
+
+/// The status of a download
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadStatus {
+    /// The download is waiting for a download slot.
+    Queued,
+
+    /// The download is waiting to be resumed.
+    Paused,
+
+    /// The download is using a download slot.
+    Active,
+
+    /// The download has finished, successfully or not.
+    Finished,
+}
+
+/// A summary of a download
+#[derive(Debug, Clone)]
+pub struct DownloadSummary {
+    /// The kitsu episode id
+    pub episode_id: NonZeroU64,
+
+    /// The track of the episode
+    pub anime_type: AnimeType,
+
+    /// The status of the download
+    pub status: DownloadStatus,
+
+    /// The time the download was given a download slot.
+    pub started_at: Option<SystemTime>,
+
+    /// The state of the download
+    pub state: CloneDownloadState,
+}
+
+#[derive(Debug, Clone)]
+pub enum DownloadsStateUpdate {
+    /// A download was added, or its status changed.
+    Status { download: DownloadSummary },
+
+    /// A download's state was updated.
+    Download {
+        episode_id: NonZeroU64,
+        anime_type: AnimeType,
+        update: DownloadStateUpdate,
+    },
+
+    /// A download was removed.
+    Removed {
+        episode_id: NonZeroU64,
+        anime_type: AnimeType,
+    },
+}
+
+/// The state of all downloads
+#[derive(Debug)]
+pub struct DownloadsState {
+    /// Downloads, in the order they were added.
+    pub downloads: Vec<DownloadSummary>,
+}
+
+impl DownloadsState {
+    fn new() -> Self {
+        Self {
+            downloads: Vec::new(),
+        }
+    }
+
+    fn apply_update(&mut self, update: &DownloadsStateUpdate) {
+        match update {
+            DownloadsStateUpdate::Status { download } => {
+                match self.downloads.iter_mut().find(|entry| {
+                    entry.episode_id == download.episode_id
+                        && entry.anime_type == download.anime_type
+                }) {
+                    Some(entry) => {
+                        *entry = download.clone();
+                    }
+                    None => {
+                        self.downloads.push(download.clone());
+                    }
+                }
+            }
+            DownloadsStateUpdate::Download { .. } => {
+                // The download state is shared with the download,
+                // so it is already updated.
+            }
+            DownloadsStateUpdate::Removed {
+                episode_id,
+                anime_type,
+            } => {
+                self.downloads.retain(|entry| {
+                    entry.episode_id != *episode_id || entry.anime_type != *anime_type
+                });
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CloneDownloadsState {
+    inner: Arc<std::sync::Mutex<DownloadsState>>,
+}
+
+impl CloneDownloadsState {
+    fn new() -> Self {
+        Self {
+            inner: Arc::new(std::sync::Mutex::new(DownloadsState::new())),
+        }
+    }
+
+    pub fn get_inner(&self) -> std::sync::MutexGuard<'_, DownloadsState> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl bewu_util::StateUpdateChannelState for CloneDownloadsState {
+    type Update = DownloadsStateUpdate;
+
+    fn apply_update(&self, update: &Self::Update) {
+        self.get_inner().apply_update(update);
+    }
+}
+
+/// Get the name of the directory that episodes of the given anime type are stored in.
+fn get_anime_type_directory_name(anime_type: AnimeType) -> &'static str {
+    match anime_type {
+        AnimeType::Sub => "sub",
+        AnimeType::Dub => "dub",
+        AnimeType::Raw => "raw",
+    }
+}
+
+/// Get the path of an episode file, relative to the vidstreaming directory.
+fn get_episode_path(anime_type: AnimeType, anime_slug: &str, episode_number: u32) -> String {
+    let directory_name = get_anime_type_directory_name(anime_type);
+    format!("{directory_name}/{anime_slug}-episode-{episode_number}.mp4")
+}
+
+/// Guess the vidstreaming url of an episode from the kitsu anime slug.
+///
+/// Dubs and raws are listed as separate anime, with a suffixed slug.
+fn guess_episode_url(anime_type: AnimeType, anime_slug: &str, episode_number: u32) -> String {
+    let suffix = match anime_type {
+        AnimeType::Sub => "",
+        AnimeType::Dub => "-dub",
+        AnimeType::Raw => "-raw",
+    };
+    format!("https://gogohd.net/videos/{anime_slug}{suffix}-episode-{episode_number}")
+}
+
+/// Split a comma-separated list of preferred codecs.
+pub fn split_preferred_codecs(preferred_codecs: &str) -> Vec<String> {
+    preferred_codecs
+        .split(',')
+        .map(|codec| codec.trim())
+        .filter(|codec| !codec.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Remove the temp files of a partially downloaded episode.
+async fn remove_episode_temp_files(
+    path: &Path,
+    anime_type: AnimeType,
+    anime_slug: &str,
+    episode_number: u32,
+) -> anyhow::Result<()> {
+    let out_path = path.join(get_episode_path(anime_type, anime_slug, episode_number));
+
+    // ffmpeg downloads to a single temp file.
+    let temp_path = out_path.with_added_extension("part");
+    match tokio::fs::remove_file(&temp_path).await {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => {
+            return Err(error).context("failed to remove temp file");
+        }
+    }
+
+    // HLS downloads keep their segments in a temp dir.
+    let temp_dir_path = out_path.with_added_extension("dir.part");
+    match tokio::fs::remove_dir_all(&temp_dir_path).await {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => {
+            return Err(error).context("failed to remove temp dir");
+        }
+    }
+
+    Ok(())
+}
+
+/// Resources shared by all download tasks
+#[derive(Debug, Clone)]
+struct DownloadContext {
     client: vidstreaming::Client,
+    host_limiter: HostLimiter,
+    database: Database,
+
+    /// The vidstreaming directory.
+    path: Arc<Path>,
+}
+
+/// The episode a download task downloads
+#[derive(Debug)]
+struct DownloadEpisode {
+    episode_id: NonZeroU64,
+    anime_id: NonZeroU64,
+    anime_type: AnimeType,
     anime_slug: Box<str>,
     episode_number: u32,
-    path: Arc<Path>,
-    download_state: bewu_util::StateUpdateTx<CloneDownloadState>,
+    vidstreaming_slug: Option<Box<str>>,
+    length_minutes: Option<u32>,
+}
+
+async fn download_task_impl(
+    context: DownloadContext,
+    episode: DownloadEpisode,
+    quality: QualityPreference,
+    download_state: DownloadStateTx,
 ) {
-    // Guess vidstreaming url
-    let url = format!("https://gogohd.net/videos/{anime_slug}-episode-{episode_number}");
-    debug!("using vidstreaming url \"{url}\"");
+    let DownloadContext {
+        client,
+        host_limiter,
+        database,
+        path,
+    } = context;
+    let DownloadEpisode {
+        episode_id,
+        anime_id,
+        anime_type,
+        anime_slug,
+        episode_number,
+        vidstreaming_slug,
+        length_minutes,
+    } = episode;
+
+    // Use the kitsu episode length until we have a more accurate duration.
+    let expected_duration =
+        length_minutes.map(|length_minutes| Duration::from_secs(u64::from(length_minutes) * 60));
+    if let Some(expected_duration) = expected_duration {
+        download_state.send(expected_duration);
+    }
 
-    let file_name = get_episode_file_name(&anime_slug, episode_number);
-    let out_path = path.join(file_name);
+    let out_path = path.join(get_episode_path(anime_type, &anime_slug, episode_number));
     match tokio::fs::try_exists(&out_path)
         .await
         .context("failed to check if episode exists")
@@ -308,29 +1446,50 @@ async fn download_task_impl(
         }
     }
 
-    let episode = match client
-        .get_episode(url.as_str())
-        .await
-        .context("failed to fetch episode")
-    {
-        Ok(episode) => {
-            download_state.send("fetched episode");
-            episode
+    // Anime without a saved mapping are mapped here,
+    // as searching vidstreaming is too slow to do before queueing.
+    let vidstreaming_slug = match vidstreaming_slug {
+        Some(vidstreaming_slug) => Some(vidstreaming_slug),
+        None => {
+            match super::matching::get_vidstreaming_anime_mapping(
+                &database, &client, anime_id, anime_type,
+            )
+            .await
+            {
+                Ok(mapping) => {
+                    download_state.send("mapped anime to vidstreaming");
+                    Some(mapping.slug.into())
+                }
+                Err(error) => {
+                    warn!(
+                        "{:?}",
+                        error.context("failed to map anime to vidstreaming, guessing the url")
+                    );
+                    None
+                }
+            }
         }
-        Err(e) => {
-            download_state.send(e);
-            return;
+    };
+
+    let url = match vidstreaming_slug {
+        Some(vidstreaming_slug) => {
+            format!("https://gogohd.net/videos/{vidstreaming_slug}-episode-{episode_number}")
         }
+        None => guess_episode_url(anime_type, &anime_slug, episode_number),
     };
+    debug!("using vidstreaming url \"{url}\"");
 
-    let video_player = match client
-        .get_video_player(episode.video_player_url.as_str())
-        .await
-        .context("failed to fetch video player")
+    let episode = match retry_transient(|| async {
+        client
+            .get_episode(url.as_str())
+            .await
+            .context("failed to fetch episode")
+    })
+    .await
     {
-        Ok(video_player) => {
-            download_state.send("fetched video player");
-            video_player
+        Ok(episode) => {
+            download_state.send("fetched episode");
+            episode
         }
         Err(e) => {
             download_state.send(e);
@@ -338,11 +1497,7 @@ async fn download_task_impl(
         }
     };
 
-    let video_data = match client
-        .get_video_player_video_data(&video_player)
-        .await
-        .context("failed to fetch video data")
-    {
+    let video_data = match get_video_data(&client, episode.video_player_url.as_str()).await {
         Ok(video_data) => {
             download_state.send("fetched video data");
             video_data
@@ -369,54 +1524,398 @@ async fn download_task_impl(
         );
     }
 
-    let best_source = match video_data
-        .get_best_source()
-        .context("failed to select a source")
-    {
-        Ok(source) => {
-            download_state.send("selected video source");
+    // Try each source in order, then each backup source.
+    // The linkiframe is only resolved if all of those fail.
+    let source_quality = vidstreaming::QualityPreference {
+        max_resolution: quality.max_resolution,
+    };
+    let mut candidates: VecDeque<_> = video_data
+        .get_ranked_sources(&source_quality)
+        .into_iter()
+        .map(|source| (SourceOrigin::Source, source.clone()))
+        .chain(
+            video_data
+                .get_ranked_backup_sources(&source_quality)
+                .into_iter()
+                .map(|source| (SourceOrigin::SourceBk, source.clone())),
+        )
+        .collect();
+    let mut linkiframe = Some(video_data.linkiframe);
+    let mut last_error = None;
+    loop {
+        let (origin, source) = match candidates.pop_front() {
+            Some(candidate) => candidate,
+            None => {
+                let linkiframe = match linkiframe.take() {
+                    Some(linkiframe) => linkiframe,
+                    None => break,
+                };
+
+                download_state.send("trying linkiframe");
+                match get_video_data(&client, linkiframe.as_str())
+                    .await
+                    .context("failed to resolve linkiframe")
+                {
+                    Ok(video_data) => {
+                        candidates.extend(
+                            video_data
+                                .get_ranked_sources(&source_quality)
+                                .into_iter()
+                                .chain(video_data.get_ranked_backup_sources(&source_quality))
+                                .map(|source| (SourceOrigin::LinkIframe, source.clone())),
+                        );
+                    }
+                    Err(error) => {
+                        warn!("{error:?}");
+                        last_error = Some(error);
+                    }
+                }
+
+                continue;
+            }
+        };
+
+        debug!(
+            "trying source: (origin={}, url={}, label={}, kind={})",
+            origin.as_str(),
+            source.file,
+            source.label,
+            source.kind
+        );
+        download_state.send(format!("trying {} \"{}\"", origin.as_str(), source.label).as_str());
+
+        let result = retry_transient(|| async {
+            if source.is_hls() {
+                download_hls_source(
+                    &client,
+                    host_limiter.clone(),
+                    quality.clone(),
+                    source.file.as_str(),
+                    &out_path,
+                    &download_state,
+                )
+                .await
+            } else {
+                download_ffmpeg_source(
+                    host_limiter.clone(),
+                    &source.file,
+                    &out_path,
+                    expected_duration,
+                    &download_state,
+                )
+                .await
+            }
+        })
+        .await;
+
+        match result {
+            Ok(()) => {
+                download_state.send(DownloadStateUpdate::Source {
+                    source: DownloadSource {
+                        origin,
+                        label: source.label.as_str().into(),
+                        url: source.file.as_str().into(),
+                    },
+                });
 
-            source
+                let download_time = match SystemTime::UNIX_EPOCH.elapsed() {
+                    Ok(download_time) => download_time.as_secs(),
+                    Err(error) => {
+                        error!("{error:?}");
+                        return;
+                    }
+                };
+                if let Err(error) = database
+                    .upsert_vidstreaming_download(VidstreamingDownload {
+                        episode_id,
+                        anime_type,
+                        source_origin: origin.as_str().into(),
+                        source_label: source.label,
+                        source_url: source.file.into(),
+                        download_time,
+                    })
+                    .await
+                    .context("failed to save download")
+                {
+                    error!("{error:?}");
+                }
+
+                return;
+            }
+            Err(error) => {
+                let error = error.context(format!(
+                    "failed to download {} \"{}\"",
+                    origin.as_str(),
+                    source.label
+                ));
+                warn!("{error:?}");
+                last_error = Some(error);
+            }
         }
-        Err(e) => {
-            download_state.send(e);
-            return;
+    }
+
+    let error = last_error
+        .unwrap_or_else(|| anyhow!("no sources"))
+        .context("all sources failed");
+    download_state.send(error);
+}
+
+/// Fetch a video player and its video data.
+async fn get_video_data(
+    client: &vidstreaming::Client,
+    video_player_url: &str,
+) -> anyhow::Result<vidstreaming::VideoData> {
+    retry_transient(|| async {
+        let video_player = client
+            .get_video_player(video_player_url)
+            .await
+            .context("failed to fetch video player")?;
+
+        client
+            .get_video_player_video_data(&video_player)
+            .await
+            .context("failed to fetch video data")
+    })
+    .await
+}
+
+/// The maximum number of attempts for an operation with transient errors.
+const MAX_ATTEMPTS: u32 = 4;
+
+/// The delay before the first retry.
+///
+/// This is doubled for each retry after.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Run a fallible operation, retrying with a backoff if it fails with a transient error.
+async fn retry_transient<F, Fut, T>(mut func: F) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match func().await {
+            Ok(value) => return Ok(value),
+            Err(error) if attempt < MAX_ATTEMPTS && is_transient_error(&error) => {
+                let delay = RETRY_DELAY * 2_u32.pow(attempt - 1);
+                warn!("{error:?}");
+                debug!("retrying in {delay:?}");
+
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(error) => return Err(error),
         }
+    }
+}
+
+/// Check if an error is likely to be temporary, like a timeout or a server error.
+fn is_transient_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|error| {
+        let error = if let Some(vidstreaming::Error::Reqwest(error)) = error.downcast_ref() {
+            error
+        } else if let Some(error) = error.downcast_ref::<reqwest::Error>() {
+            error
+        } else {
+            return false;
+        };
+
+        if error.is_timeout() || error.is_connect() {
+            return true;
+        }
+
+        error.status().is_some_and(|status| {
+            status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        })
+    })
+}
+
+/// The share of the progress used for downloading media segments in a hls download.
+const HLS_DOWNLOAD_PROGRESS: f32 = 90.0;
+
+/// The share of the progress used for concatenating media segments in a hls download.
+///
+/// Remuxing uses the rest.
+const HLS_CONCAT_PROGRESS: f32 = 5.0;
+
+/// Download a hls source with parallel segment downloads.
+///
+/// Media segments are kept in a temp dir until the download completes,
+/// so an interrupted download can be resumed.
+async fn download_hls_source(
+    client: &vidstreaming::Client,
+    host_limiter: HostLimiter,
+    quality: QualityPreference,
+    url: &str,
+    out_path: &Path,
+    download_state: &DownloadStateTx,
+) -> anyhow::Result<()> {
+    let options = bewu_util::DownloadHlsOptions {
+        host_limiter: Some(host_limiter),
+        quality,
+        ..Default::default()
     };
+    let stream = bewu_util::download_hls(client.client.clone(), url, out_path, options)
+        .context("failed to start hls download")?;
+    tokio::pin!(stream);
+    download_state.send("started download");
+
+    let mut tracker = DownloadProgressTracker::new();
+    let mut num_media_segments = 0;
+    let mut num_downloaded = 0;
+    let mut num_concatenated = 0;
+    let mut total_duration = Duration::ZERO;
+    let mut done = false;
+    while let Some(message) = stream.next().await {
+        let message = message.context("failed to download hls stream")?;
+
+        match message {
+            bewu_util::DownloadHlsMessage::DownloadedMediaPlaylist { media_playlist } => {
+                num_media_segments = media_playlist.media_segments.len();
+                total_duration = media_playlist
+                    .media_segments
+                    .iter()
+                    .map(|segment| segment.duration)
+                    .sum();
+
+                download_state.send("downloaded media playlist");
+                download_state.send(total_duration);
+            }
+            bewu_util::DownloadHlsMessage::DownloadedRenditionPlaylist {
+                media_playlist, ..
+            } => {
+                // Rendition media segments are downloaded and concatenated along with the others.
+                num_media_segments += media_playlist.media_segments.len();
+
+                download_state.send("downloaded rendition playlist");
+            }
+            bewu_util::DownloadHlsMessage::DownloadedMediaSegment { size, resumed } => {
+                num_downloaded += 1;
+
+                if resumed {
+                    let segment_progress =
+                        get_fraction(1, num_media_segments) * HLS_DOWNLOAD_PROGRESS;
+                    tracker.add_resumed_bytes(size, segment_progress);
+                } else {
+                    tracker.add_bytes(size);
+                }
+
+                let fraction = get_fraction(num_downloaded, num_media_segments);
+                tracker.send_progress(download_state, fraction * HLS_DOWNLOAD_PROGRESS);
+            }
+            bewu_util::DownloadHlsMessage::DownloadedAllMediaSegments => {
+                download_state.send("downloaded media segments");
+                tracker.send_progress(download_state, HLS_DOWNLOAD_PROGRESS);
+            }
+            bewu_util::DownloadHlsMessage::ConcatenatedMediaSegment => {
+                num_concatenated += 1;
+
+                let fraction = get_fraction(num_concatenated, num_media_segments);
+                tracker.send_progress(
+                    download_state,
+                    HLS_DOWNLOAD_PROGRESS + (fraction * HLS_CONCAT_PROGRESS),
+                );
+            }
+            bewu_util::DownloadHlsMessage::ConcatenatedAllMediaSegments => {
+                download_state.send("concatenated media segments");
+                tracker.send_progress(download_state, HLS_DOWNLOAD_PROGRESS + HLS_CONCAT_PROGRESS);
+            }
+            bewu_util::DownloadHlsMessage::FfmpegProgress { out_time } => {
+                let fraction = if total_duration.is_zero() {
+                    0.0
+                } else {
+                    (out_time as f32 / total_duration.as_secs_f32()).min(1.0)
+                };
+
+                let remux_progress = 100.0 - HLS_DOWNLOAD_PROGRESS - HLS_CONCAT_PROGRESS;
+                tracker.send_progress(
+                    download_state,
+                    HLS_DOWNLOAD_PROGRESS + HLS_CONCAT_PROGRESS + (fraction * remux_progress),
+                );
+            }
+            bewu_util::DownloadHlsMessage::Done => {
+                tracker.send_progress(download_state, 100.0);
+                download_state.send("finished download");
+                done = true;
+            }
+        }
+    }
+
+    ensure!(done, "the hls download ended early");
+
+    Ok(())
+}
+
+/// Get `n / total` as a float, or 0 if `total` is 0.
+fn get_fraction(n: usize, total: usize) -> f32 {
+    if total == 0 {
+        return 0.0;
+    }
 
-    debug!(
-        "selected source: (url={}, label={}, kind={})",
-        best_source.file, best_source.label, best_source.kind
-    );
+    n as f32 / total as f32
+}
 
+/// Download a source by passing it directly to ffmpeg.
+///
+/// The duration is probed from the source, falling back to the expected duration.
+async fn download_ffmpeg_source(
+    host_limiter: HostLimiter,
+    url: &Url,
+    out_path: &Path,
+    expected_duration: Option<Duration>,
+    download_state: &DownloadStateTx,
+) -> anyhow::Result<()> {
     let temp_path = out_path.with_added_extension("part");
 
-    let mut download_stream = match tokio_ffmpeg_cli::Builder::new()
+    // ffmpeg cannot resume a partial download,
+    // so remove any temp file left by a paused or interrupted download.
+    match tokio::fs::remove_file(&temp_path).await {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => {
+            return Err(error).context("failed to remove temp file");
+        }
+    }
+
+    // Hold a permit for the source host for the entire download.
+    let _host_permit = host_limiter
+        .acquire(url.host_str().unwrap_or_default())
+        .await;
+
+    let total_duration = match bewu_util::probe_url(url.as_str())
+        .await
+        .context("failed to probe source")
+        .and_then(|probe_result| probe_result.format.parse_duration())
+    {
+        Ok(total_duration) => {
+            download_state.send(total_duration);
+            Some(total_duration)
+        }
+        Err(error) => {
+            warn!("{error:?}");
+            expected_duration
+        }
+    };
+
+    let mut download_stream = tokio_ffmpeg_cli::Builder::new()
         .audio_codec("copy")
         .video_codec("copy")
-        .input(best_source.file.as_str())
+        .input(url.as_str())
         .output_format("mp4")
         .output(&temp_path)
         .overwrite(false)
         .spawn()
-        .context("failed to spawn \"ffmpeg\"")
-    {
-        Ok(stream) => {
-            download_state.send("started download");
-            stream
-        }
-        Err(e) => {
-            download_state.send(e);
-            return;
-        }
-    };
+        .context("failed to spawn \"ffmpeg\"")?;
+    download_state.send("started download");
 
-    let mut exit_success = true;
+    let mut tracker = DownloadProgressTracker::new();
+    let mut last_error = Ok(());
     while let Some(event) = download_stream.next().await {
         let event = match event.context("download stream error") {
             Ok(event) => event,
             Err(e) => {
-                download_state.send(e);
+                last_error = Err(e);
                 continue;
             }
         };
@@ -428,17 +1927,27 @@ async fn download_task_impl(
                 {
                     Ok(out_time) => out_time,
                     Err(e) => {
-                        download_state.send(e);
+                        warn!("{e:?}");
                         continue;
                     }
                 };
 
-                download_state.send(out_time as f32);
+                // The temp file may not exist yet.
+                if let Ok(metadata) = tokio::fs::metadata(&temp_path).await {
+                    tracker.set_bytes(metadata.len());
+                }
+
+                let progress = match total_duration {
+                    Some(total_duration) if !total_duration.is_zero() => {
+                        (out_time as f32 / total_duration.as_secs_f32()).min(1.0) * 100.0
+                    }
+                    _ => 0.0,
+                };
+                tracker.send_progress(download_state, progress);
             }
             tokio_ffmpeg_cli::Event::ExitStatus(exit_status) => {
                 if !exit_status.success() {
-                    download_state.send(anyhow!("ffmpeg exit status was \"{exit_status:?}\""));
-                    exit_success = false;
+                    last_error = Err(anyhow!("ffmpeg exit status was \"{exit_status:?}\""));
                 }
             }
             tokio_ffmpeg_cli::Event::Unknown(line) => {
@@ -447,17 +1956,20 @@ async fn download_task_impl(
         }
     }
 
-    if exit_success {
-        if let Err(e) = tokio::fs::rename(temp_path, out_path)
+    if let Err(error) = last_error {
+        if let Err(e) = tokio::fs::remove_file(&temp_path)
             .await
-            .context("failed to rename")
+            .context("failed to remove temp file")
         {
-            download_state.send(e);
+            warn!("{e:?}");
         }
-    } else if let Err(e) = tokio::fs::remove_file(temp_path)
-        .await
-        .context("failed to remove temp file")
-    {
-        download_state.send(e);
+
+        return Err(error);
     }
+
+    tokio::fs::rename(temp_path, out_path)
+        .await
+        .context("failed to rename")?;
+
+    Ok(())
 }
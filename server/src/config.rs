@@ -1,6 +1,8 @@
 use anyhow::ensure;
 use anyhow::Context;
 use std::net::SocketAddr;
+use std::num::NonZeroU64;
+use std::num::NonZeroUsize;
 use std::path::Path;
 use std::path::PathBuf;
 
@@ -17,6 +19,15 @@ pub struct Config {
 
     #[serde(default)]
     pub logging: ConfigLogging,
+
+    #[serde(default)]
+    pub downloads: ConfigDownloads,
+
+    #[serde(rename = "image-cache", default)]
+    pub image_cache: ConfigImageCache,
+
+    #[serde(default)]
+    pub kitsu: ConfigKitsu,
 }
 
 impl Config {
@@ -55,3 +66,118 @@ pub struct ConfigLogging {
     #[serde(default)]
     pub directives: Vec<String>,
 }
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ConfigDownloads {
+    /// The maximum number of episodes to download at once.
+    #[serde(
+        rename = "max-concurrent",
+        default = "ConfigDownloads::default_max_concurrent"
+    )]
+    pub max_concurrent: NonZeroUsize,
+
+    /// The maximum number of concurrent requests to make to a single host.
+    #[serde(
+        rename = "max-per-host",
+        default = "ConfigDownloads::default_max_per_host"
+    )]
+    pub max_per_host: NonZeroUsize,
+
+    /// The default quality preference for downloads.
+    #[serde(default)]
+    pub quality: ConfigQuality,
+}
+
+impl ConfigDownloads {
+    fn default_max_concurrent() -> NonZeroUsize {
+        NonZeroUsize::new(1).unwrap()
+    }
+
+    fn default_max_per_host() -> NonZeroUsize {
+        NonZeroUsize::new(4).unwrap()
+    }
+}
+
+impl Default for ConfigDownloads {
+    fn default() -> Self {
+        Self {
+            max_concurrent: Self::default_max_concurrent(),
+            max_per_host: Self::default_max_per_host(),
+            quality: ConfigQuality::default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct ConfigQuality {
+    /// The maximum vertical resolution, like 720.
+    #[serde(rename = "max-resolution")]
+    pub max_resolution: Option<u32>,
+
+    /// The maximum bandwidth of a hls stream, in bits per second.
+    #[serde(rename = "max-bandwidth")]
+    pub max_bandwidth: Option<u64>,
+
+    /// Codecs to prefer for hls streams, from most to least preferred.
+    #[serde(rename = "preferred-codecs", default)]
+    pub preferred_codecs: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ConfigImageCache {
+    /// The maximum size of cached images, in bytes.
+    ///
+    /// Images of installed library anime do not count towards this.
+    #[serde(rename = "max-size", default = "ConfigImageCache::default_max_size")]
+    pub max_size: u64,
+}
+
+impl ConfigImageCache {
+    fn default_max_size() -> u64 {
+        256 * 1024 * 1024
+    }
+}
+
+impl Default for ConfigImageCache {
+    fn default() -> Self {
+        Self {
+            max_size: Self::default_max_size(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ConfigKitsu {
+    /// How long cached kitsu metadata and searches are fresh, in seconds.
+    ///
+    /// Stale metadata is still served, but is refreshed in the background.
+    /// Stale searches are sent to kitsu again.
+    #[serde(default = "ConfigKitsu::default_ttl")]
+    pub ttl: u64,
+
+    /// How often to refresh airing anime in the library, in seconds.
+    #[serde(
+        rename = "refresh-interval",
+        default = "ConfigKitsu::default_refresh_interval"
+    )]
+    pub refresh_interval: NonZeroU64,
+}
+
+impl ConfigKitsu {
+    fn default_ttl() -> u64 {
+        24 * 60 * 60
+    }
+
+    fn default_refresh_interval() -> NonZeroU64 {
+        NonZeroU64::new(6 * 60 * 60).unwrap()
+    }
+}
+
+impl Default for ConfigKitsu {
+    fn default() -> Self {
+        Self {
+            ttl: Self::default_ttl(),
+            refresh_interval: Self::default_refresh_interval(),
+        }
+    }
+}
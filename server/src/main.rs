@@ -24,7 +24,15 @@ fn main() -> anyhow::Result<()> {
 }
 
 async fn async_main(config: Config) -> anyhow::Result<()> {
-    let app_state = Arc::new(AppState::new(&config.data_directory).await?);
+    let app_state = Arc::new(
+        AppState::new(
+            &config.data_directory,
+            &config.downloads,
+            &config.image_cache,
+            &config.kitsu,
+        )
+        .await?,
+    );
     let app = self::routes::routes(&config, app_state.clone())?;
     let server_listener = tokio::net::TcpListener::bind(&config.bind_address)
         .await
@@ -1,4 +1,21 @@
+use crate::app_state::get_image_size_url;
+use crate::app_state::ImageKind;
+use crate::app_state::ImageSize;
+use crate::app_state::KitsuAnime;
+use crate::app_state::KitsuExternalSite;
+use crate::app_state::KitsuSearchMode;
+use crate::app_state::LibraryAnime;
+use crate::app_state::LibraryEpisode;
+use crate::app_state::LibraryEpisodeStatus;
+use crate::app_state::LibrarySort;
+use crate::app_state::QualityPreferenceOverride;
+use crate::app_state::VidstreamingAnimeMapping;
+use crate::app_state::VidstreamingDownloadSource;
+use crate::app_state::VidstreamingDownloadState;
 use crate::app_state::VidstreamingDownloadStateUpdate;
+use crate::app_state::VidstreamingDownloadStatus;
+use crate::app_state::VidstreamingDownloadSummary;
+use crate::app_state::VidstreamingDownloadsStateUpdate;
 use crate::AppState;
 use anyhow::Context;
 use axum::extract::Path;
@@ -8,14 +25,19 @@ use axum::http::StatusCode;
 use axum::response::sse;
 use axum::response::IntoResponse;
 use axum::response::Sse;
+use axum::routing::delete;
 use axum::routing::get;
+use axum::routing::post;
 use axum::Json;
 use axum::Router;
 use bewu_util::StateUpdateItem;
 use std::num::NonZeroU64;
 use std::sync::Arc;
+use std::time::SystemTime;
 use tokio_stream::StreamExt;
 use tracing::error;
+use tracing::warn;
+use vidstreaming::AnimeType;
 
 #[derive(Debug, serde::Serialize)]
 struct ApiError {
@@ -44,6 +66,14 @@ where
 pub fn routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/anime", get(api_anime_get))
+        .route(
+            "/anime/{id}",
+            get(api_anime_id)
+                .put(api_anime_id_put)
+                .delete(api_anime_id_delete),
+        )
+        .route("/downloads", get(api_downloads))
+        .route("/downloads/events", get(api_downloads_events))
         .route("/kitsu/anime", get(api_kitsu_anime))
         .route("/kitsu/anime/{id}", get(api_kitsu_anime_id))
         .route(
@@ -54,27 +84,434 @@ pub fn routes() -> Router<Arc<AppState>> {
         .route("/vidstreaming/{id}", get(api_vidstreaming_id))
         .route(
             "/vidstreaming/{id}/download",
-            get(api_vidstreaming_id_download),
+            get(api_vidstreaming_id_download).delete(api_vidstreaming_id_download_delete),
+        )
+        .route(
+            "/vidstreaming/{id}/download/pause",
+            post(api_vidstreaming_id_download_pause),
+        )
+        .route(
+            "/vidstreaming/{id}/download/resume",
+            post(api_vidstreaming_id_download_resume),
+        )
+        .route("/vidstreaming/queue", get(api_vidstreaming_queue))
+        .route(
+            "/vidstreaming/queue/{id}",
+            delete(api_vidstreaming_queue_id_delete).put(api_vidstreaming_queue_id_put),
         )
+        .route(
+            "/vidstreaming/mappings/{id}",
+            get(api_vidstreaming_mappings_id)
+                .put(api_vidstreaming_mappings_id_put)
+                .delete(api_vidstreaming_mappings_id_delete),
+        )
+}
+
+/// The default number of anime in a library page.
+const DEFAULT_LIBRARY_PAGE_LIMIT: u64 = 50;
+
+/// The maximum number of anime in a library page.
+const MAX_LIBRARY_PAGE_LIMIT: u64 = 100;
+
+#[derive(Debug, Default, serde::Deserialize)]
+enum ApiLibrarySort {
+    #[default]
+    #[serde(rename = "title")]
+    Title,
+    #[serde(rename = "added-time")]
+    AddedTime,
+    #[serde(rename = "rating")]
+    Rating,
+}
+
+impl From<ApiLibrarySort> for LibrarySort {
+    fn from(sort: ApiLibrarySort) -> Self {
+        match sort {
+            ApiLibrarySort::Title => Self::Title,
+            ApiLibrarySort::AddedTime => Self::AddedTime,
+            ApiLibrarySort::Rating => Self::Rating,
+        }
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+enum ApiSortOrder {
+    #[default]
+    #[serde(rename = "asc")]
+    Ascending,
+    #[serde(rename = "desc")]
+    Descending,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct LibraryParams {
+    #[serde(default)]
+    sort: ApiLibrarySort,
+
+    #[serde(default)]
+    order: ApiSortOrder,
+
+    #[serde(default)]
+    offset: u64,
+
+    limit: Option<u64>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ApiLibraryAnime {
+    id: NonZeroU64,
+    installed: bool,
+
+    /// The number of seconds from the unix epoch.
+    added_time: u64,
+
+    slug: String,
+    title: String,
+    rating: Option<String>,
+    poster: Option<String>,
+    cover: Option<String>,
+    downloaded_episodes: u64,
+}
+
+impl From<LibraryAnime> for ApiLibraryAnime {
+    fn from(anime: LibraryAnime) -> Self {
+        Self {
+            id: anime.id,
+            installed: anime.installed,
+            added_time: anime.added_time,
+            slug: anime.slug,
+            title: anime.title,
+            rating: anime.rating,
+            poster: anime.poster_path.map(|path| format!("/data/{path}")),
+            cover: anime.cover_path.map(|path| format!("/data/{path}")),
+            downloaded_episodes: anime.downloaded_episodes,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ApiLibraryPage {
+    total: u64,
+    offset: u64,
+    limit: u64,
+    anime: Vec<ApiLibraryAnime>,
+}
+
+async fn api_anime_get(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<LibraryParams>,
+) -> impl IntoResponse {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_LIBRARY_PAGE_LIMIT)
+        .min(MAX_LIBRARY_PAGE_LIMIT);
+    let descending = matches!(params.order, ApiSortOrder::Descending);
+
+    let result = app_state
+        .get_library_anime_page(params.sort.into(), descending, params.offset, limit)
+        .await
+        .map(|(anime, total)| ApiLibraryPage {
+            total,
+            offset: params.offset,
+            limit,
+            anime: anime.into_iter().map(ApiLibraryAnime::from).collect(),
+        })
+        .map_err(|error| {
+            error!("{error:?}");
+            ApiError::from_anyhow(error)
+        });
+
+    match result {
+        Ok(result) => (StatusCode::OK, Json(result)).into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response(),
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+enum ApiLibraryEpisodeStatus {
+    #[serde(rename = "missing")]
+    Missing,
+    #[serde(rename = "queued")]
+    Queued,
+    #[serde(rename = "paused")]
+    Paused,
+    #[serde(rename = "downloaded")]
+    Downloaded,
+}
+
+impl From<LibraryEpisodeStatus> for ApiLibraryEpisodeStatus {
+    fn from(status: LibraryEpisodeStatus) -> Self {
+        match status {
+            LibraryEpisodeStatus::Missing => Self::Missing,
+            LibraryEpisodeStatus::Queued => Self::Queued,
+            LibraryEpisodeStatus::Paused => Self::Paused,
+            LibraryEpisodeStatus::Downloaded => Self::Downloaded,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ApiLibraryEpisode {
+    episode_id: NonZeroU64,
+    number: u32,
+    title: Option<String>,
+    status: ApiLibraryEpisodeStatus,
+
+    /// The number of seconds from the unix epoch.
+    download_time: Option<u64>,
+}
+
+impl From<LibraryEpisode> for ApiLibraryEpisode {
+    fn from(episode: LibraryEpisode) -> Self {
+        Self {
+            episode_id: episode.episode_id,
+            number: episode.number,
+            title: episode.title,
+            status: episode.status.into(),
+            download_time: episode.download_time,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ApiLibraryAnimeEpisodes {
+    #[serde(flatten)]
+    anime: ApiLibraryAnime,
+    anime_type: ApiAnimeType,
+    episodes: Vec<ApiLibraryEpisode>,
+}
+
+async fn api_anime_id(
+    State(app_state): State<Arc<AppState>>,
+    Path(id): Path<NonZeroU64>,
+    Query(params): Query<VidstreamingAnimeTypeParams>,
+) -> impl IntoResponse {
+    let result = app_state
+        .get_library_anime(id, params.anime_type.into())
+        .await
+        .map(|(anime, episodes)| ApiLibraryAnimeEpisodes {
+            anime: anime.into(),
+            anime_type: params.anime_type,
+            episodes: episodes.into_iter().map(ApiLibraryEpisode::from).collect(),
+        })
+        .map_err(|error| {
+            error!("{error:?}");
+            ApiError::from_anyhow(error)
+        });
+
+    match result {
+        Ok(result) => (StatusCode::OK, Json(result)).into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response(),
+    }
+}
+
+async fn api_anime_id_put(
+    State(app_state): State<Arc<AppState>>,
+    Path(id): Path<NonZeroU64>,
+) -> impl IntoResponse {
+    let result = app_state
+        .add_library_anime(id)
+        .await
+        .map(ApiLibraryAnime::from)
+        .map_err(|error| {
+            error!("{error:?}");
+            ApiError::from_anyhow(error)
+        });
+
+    match result {
+        Ok(result) => (StatusCode::OK, Json(result)).into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response(),
+    }
+}
+
+async fn api_anime_id_delete(
+    State(app_state): State<Arc<AppState>>,
+    Path(id): Path<NonZeroU64>,
+) -> impl IntoResponse {
+    let result = app_state.remove_library_anime(id).await.map_err(|error| {
+        error!("{error:?}");
+        ApiError::from_anyhow(error)
+    });
+
+    match result {
+        Ok(()) => (StatusCode::OK, Json(())).into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response(),
+    }
+}
+
+/// A kitsu image size
+#[derive(Debug, Default, Clone, Copy, serde::Deserialize)]
+enum ApiImageSize {
+    #[serde(rename = "tiny")]
+    Tiny,
+    #[serde(rename = "small")]
+    Small,
+    #[serde(rename = "medium")]
+    Medium,
+    #[default]
+    #[serde(rename = "large")]
+    Large,
+    #[serde(rename = "original")]
+    Original,
+}
+
+impl From<ApiImageSize> for ImageSize {
+    fn from(size: ApiImageSize) -> Self {
+        match size {
+            ApiImageSize::Tiny => Self::Tiny,
+            ApiImageSize::Small => Self::Small,
+            ApiImageSize::Medium => Self::Medium,
+            ApiImageSize::Large => Self::Large,
+            ApiImageSize::Original => Self::Original,
+        }
+    }
+}
+
+/// Get urls for kitsu images.
+///
+/// Cached images get local `/data/...` urls.
+/// Other images keep their remote url, and are cached in the background for later requests.
+async fn get_image_urls(
+    app_state: &AppState,
+    images: Vec<(&str, ImageKind, ImageSize)>,
+) -> Vec<String> {
+    let remote_urls: Vec<String> = images
+        .iter()
+        .map(|(url, _kind, size)| get_image_size_url(url, *size))
+        .collect();
+
+    match app_state.get_cached_kitsu_images(images).await {
+        Ok(images) => images
+            .into_iter()
+            .map(|(url, path)| match path {
+                Some(path) => format!("/data/{path}"),
+                None => url,
+            })
+            .collect(),
+        Err(error) => {
+            warn!("{:?}", error.context("failed to get cached images"));
+            remote_urls
+        }
+    }
 }
 
-async fn api_anime_get(State(_app_state): State<Arc<AppState>>) -> impl IntoResponse {
-    Json("WIP")
+/// Where to search for kitsu anime
+#[derive(Debug, Default, Clone, Copy, serde::Deserialize)]
+enum ApiKitsuSearchMode {
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "local")]
+    Local,
+    #[serde(rename = "remote")]
+    Remote,
+}
+
+impl From<ApiKitsuSearchMode> for KitsuSearchMode {
+    fn from(mode: ApiKitsuSearchMode) -> Self {
+        match mode {
+            ApiKitsuSearchMode::Auto => Self::Auto,
+            ApiKitsuSearchMode::Local => Self::Local,
+            ApiKitsuSearchMode::Remote => Self::Remote,
+        }
+    }
 }
 
 #[derive(Debug, serde::Deserialize)]
 struct KitsuSearchParams {
     text: Option<String>,
+
+    /// A MyAnimeList anime id to look up, instead of searching.
+    mal: Option<String>,
+
+    /// An AniList anime id to look up, instead of searching.
+    anilist: Option<String>,
+
+    /// An AniDB anime id to look up, instead of searching.
+    anidb: Option<String>,
+
+    #[serde(default)]
+    mode: ApiKitsuSearchMode,
+
+    #[serde(rename = "image-size", default)]
+    image_size: ApiImageSize,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct KitsuImageSizeParams {
+    #[serde(rename = "image-size", default)]
+    image_size: ApiImageSize,
 }
 
 #[derive(Debug, serde::Serialize)]
 struct ApiKitsuAnime {
     id: NonZeroU64,
+    slug: String,
     synopsis: Option<String>,
     title: String,
+    titles: Vec<String>,
+    abbreviated_titles: Vec<String>,
     rating: Option<String>,
 
-    poster_large: String,
+    subtype: Option<String>,
+    status: Option<String>,
+    episode_count: Option<u64>,
+    episode_length: Option<u64>,
+    age_rating: Option<String>,
+    age_rating_guide: Option<String>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    popularity_rank: Option<u64>,
+    rating_rank: Option<u64>,
+    user_count: Option<u64>,
+    favorites_count: Option<u64>,
+    youtube_video_id: Option<String>,
+    nsfw: bool,
+
+    poster: String,
+
+    /// The cover image.
+    ///
+    /// This is only fetched when getting a single anime.
+    cover: Option<String>,
+}
+
+impl ApiKitsuAnime {
+    fn new(anime: &KitsuAnime, poster: String, cover: Option<String>) -> Self {
+        Self {
+            id: anime.id,
+            slug: anime.slug.clone(),
+            synopsis: anime.synopsis.clone(),
+            title: anime.title.clone(),
+            titles: anime.titles.clone(),
+            abbreviated_titles: anime.abbreviated_titles.clone(),
+            rating: anime.rating.clone(),
+
+            subtype: anime
+                .subtype
+                .as_ref()
+                .map(|subtype| subtype.as_str().into()),
+            status: anime.status.as_ref().map(|status| status.as_str().into()),
+            episode_count: anime.episode_count,
+            episode_length: anime.episode_length,
+            age_rating: anime
+                .age_rating
+                .as_ref()
+                .map(|age_rating| age_rating.as_str().into()),
+            age_rating_guide: anime.age_rating_guide.clone(),
+            start_date: anime.start_date.clone(),
+            end_date: anime.end_date.clone(),
+            popularity_rank: anime.popularity_rank,
+            rating_rank: anime.rating_rank,
+            user_count: anime.user_count,
+            favorites_count: anime.favorites_count,
+            youtube_video_id: anime.youtube_video_id.clone(),
+            nsfw: anime.nsfw,
+
+            poster,
+            cover,
+        }
+    }
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -83,31 +520,59 @@ struct ApiKitsuEpisode {
 
     title: Option<String>,
 
-    thumbnail_original: Option<String>,
+    thumbnail: Option<String>,
 }
 
 async fn api_kitsu_anime(
     State(app_state): State<Arc<AppState>>,
     Query(params): Query<KitsuSearchParams>,
 ) -> impl IntoResponse {
-    let result = async move {
-        let text = params.text.context("missing `text` query param")?;
-        app_state.search_kitsu(&text).await
+    let result = async {
+        let external_id = [
+            (KitsuExternalSite::MyAnimeListAnime, params.mal.as_deref()),
+            (KitsuExternalSite::AniListAnime, params.anilist.as_deref()),
+            (KitsuExternalSite::AniDb, params.anidb.as_deref()),
+        ]
+        .into_iter()
+        .find_map(|(external_site, external_id)| Some((external_site, external_id?)));
+
+        let anime: Arc<[KitsuAnime]> = match external_id {
+            Some((external_site, external_id)) => app_state
+                .get_kitsu_anime_by_external_id(&external_site, external_id)
+                .await?
+                .map(|anime| KitsuAnime::clone(&anime))
+                .into_iter()
+                .collect(),
+            None => {
+                let text = params.text.context("missing `text` query param")?;
+                app_state.search_kitsu(&text, params.mode.into()).await?
+            }
+        };
+
+        let posters = get_image_urls(
+            &app_state,
+            anime
+                .iter()
+                .map(|anime| {
+                    (
+                        anime.poster_large.as_str(),
+                        ImageKind::Poster,
+                        params.image_size.into(),
+                    )
+                })
+                .collect(),
+        )
+        .await;
+
+        anyhow::Ok(
+            anime
+                .iter()
+                .zip(posters)
+                .map(|(anime, poster)| ApiKitsuAnime::new(anime, poster, None))
+                .collect::<Vec<_>>(),
+        )
     }
     .await
-    .map(|anime| {
-        anime
-            .iter()
-            .map(|anime| ApiKitsuAnime {
-                id: anime.id,
-                synopsis: anime.synopsis.clone(),
-                title: anime.title.clone(),
-                rating: anime.rating.clone(),
-
-                poster_large: anime.poster_large.clone(),
-            })
-            .collect::<Vec<_>>()
-    })
     .map_err(|error| {
         error!("{error:?}");
         ApiError::from_anyhow(error)
@@ -122,22 +587,30 @@ async fn api_kitsu_anime(
 async fn api_kitsu_anime_id(
     State(app_state): State<Arc<AppState>>,
     Path(id): Path<NonZeroU64>,
+    Query(params): Query<KitsuImageSizeParams>,
 ) -> impl IntoResponse {
-    let result = app_state
-        .get_kitsu_anime(id)
-        .await
-        .map(|anime| ApiKitsuAnime {
-            id: anime.id,
-            synopsis: anime.synopsis.clone(),
-            title: anime.title.clone(),
-            rating: anime.rating.clone(),
+    let result = async {
+        let anime = app_state.get_kitsu_anime(id).await?;
 
-            poster_large: anime.poster_large.clone(),
-        })
-        .map_err(|error| {
-            error!("{error:?}");
-            ApiError::from_anyhow(error)
-        });
+        let mut images = vec![(
+            anime.poster_large.as_str(),
+            ImageKind::Poster,
+            params.image_size.into(),
+        )];
+        if let Some(cover_large) = anime.cover_large.as_deref() {
+            images.push((cover_large, ImageKind::Cover, params.image_size.into()));
+        }
+        let mut images = get_image_urls(&app_state, images).await.into_iter();
+        let poster = images.next().context("missing poster")?;
+        let cover = images.next();
+
+        anyhow::Ok(ApiKitsuAnime::new(&anime, poster, cover))
+    }
+    .await
+    .map_err(|error| {
+        error!("{error:?}");
+        ApiError::from_anyhow(error)
+    });
 
     match result {
         Ok(result) => (StatusCode::OK, Json(result)).into_response(),
@@ -149,26 +622,39 @@ async fn api_kitsu_anime_id_episodes(
     State(app_state): State<Arc<AppState>>,
     Path(id): Path<NonZeroU64>,
 ) -> impl IntoResponse {
-    let result = app_state
-        .get_kitsu_anime_episodes(id)
+    let result = async {
+        let episodes = app_state.get_kitsu_anime_episodes(id).await?;
+
+        let mut thumbnails = get_image_urls(
+            &app_state,
+            episodes
+                .iter()
+                .filter_map(|episode| episode.thumbnail_original.as_deref())
+                .map(|url| (url, ImageKind::Thumbnail, ImageSize::Original))
+                .collect(),
+        )
         .await
-        .map(|episodes| {
+        .into_iter();
+
+        anyhow::Ok(
             episodes
                 .iter()
                 .map(|episode| ApiKitsuEpisode {
                     id: episode.episode_id,
                     title: episode.title.as_ref().map(ToString::to_string),
-                    thumbnail_original: episode
+                    thumbnail: episode
                         .thumbnail_original
                         .as_ref()
-                        .map(ToString::to_string),
+                        .and_then(|_| thumbnails.next()),
                 })
-                .collect::<Vec<_>>()
-        })
-        .map_err(|error| {
-            error!("{error:?}");
-            ApiError::from_anyhow(error)
-        });
+                .collect::<Vec<_>>(),
+        )
+    }
+    .await
+    .map_err(|error| {
+        error!("{error:?}");
+        ApiError::from_anyhow(error)
+    });
 
     match result {
         Ok(result) => (StatusCode::OK, Json(result)).into_response(),
@@ -180,18 +666,30 @@ async fn api_kitsu_episodes_id(
     State(app_state): State<Arc<AppState>>,
     Path(id): Path<NonZeroU64>,
 ) -> impl IntoResponse {
-    let result = app_state
-        .get_kitsu_episode(id)
-        .await
-        .map(|episode| ApiKitsuEpisode {
+    let result = async {
+        let episode = app_state.get_kitsu_episode(id).await?;
+
+        let thumbnail = match episode.thumbnail_original.as_deref() {
+            Some(url) => get_image_urls(
+                &app_state,
+                vec![(url, ImageKind::Thumbnail, ImageSize::Original)],
+            )
+            .await
+            .pop(),
+            None => None,
+        };
+
+        anyhow::Ok(ApiKitsuEpisode {
             id: episode.episode_id,
             title: episode.title.as_ref().map(ToString::to_string),
-            thumbnail_original: episode.thumbnail_original.as_ref().map(ToString::to_string),
+            thumbnail,
         })
-        .map_err(|error| {
-            error!("{error:?}");
-            ApiError::from_anyhow(error)
-        });
+    }
+    .await
+    .map_err(|error| {
+        error!("{error:?}");
+        ApiError::from_anyhow(error)
+    });
 
     match result {
         Ok(result) => (StatusCode::OK, Json(result)).into_response(),
@@ -199,6 +697,45 @@ async fn api_kitsu_episodes_id(
     }
 }
 
+/// The track of an episode
+#[derive(Debug, Default, Clone, Copy, serde::Serialize, serde::Deserialize)]
+enum ApiAnimeType {
+    #[default]
+    #[serde(rename = "sub")]
+    Sub,
+    #[serde(rename = "dub")]
+    Dub,
+    #[serde(rename = "raw")]
+    Raw,
+}
+
+impl From<ApiAnimeType> for AnimeType {
+    fn from(anime_type: ApiAnimeType) -> Self {
+        match anime_type {
+            ApiAnimeType::Sub => Self::Sub,
+            ApiAnimeType::Dub => Self::Dub,
+            ApiAnimeType::Raw => Self::Raw,
+        }
+    }
+}
+
+impl From<AnimeType> for ApiAnimeType {
+    fn from(anime_type: AnimeType) -> Self {
+        match anime_type {
+            AnimeType::Sub => Self::Sub,
+            AnimeType::Dub => Self::Dub,
+            AnimeType::Raw => Self::Raw,
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct VidstreamingAnimeTypeParams {
+    /// The track of the episode, defaults to sub.
+    #[serde(rename = "type", default)]
+    anime_type: ApiAnimeType,
+}
+
 #[derive(Debug, serde::Serialize)]
 struct ApiVidstreamingEpisode {
     url: Option<String>,
@@ -207,14 +744,15 @@ struct ApiVidstreamingEpisode {
 async fn api_vidstreaming_id(
     State(app_state): State<Arc<AppState>>,
     Path(id): Path<NonZeroU64>,
+    Query(params): Query<VidstreamingAnimeTypeParams>,
 ) -> impl IntoResponse {
     let result = app_state
-        .get_vidstreaming_episode(id)
+        .get_vidstreaming_episode(id, params.anime_type.into())
         .await
         .map(|episode| ApiVidstreamingEpisode {
             url: episode
                 .path
-                .map(|path| format!("/data/vidstreaming/sub/{path}")),
+                .map(|path| format!("/data/vidstreaming/{path}")),
         })
         .map_err(|error| {
             error!("{error:?}");
@@ -233,9 +771,65 @@ struct ApiVidstreamingDownloadState {
     info: Option<Arc<str>>,
 
     progress: f32,
+
+    /// The total duration, in seconds.
+    total_duration: Option<f64>,
+
+    downloaded_bytes: u64,
+
+    /// The throughput, in bytes per second.
+    throughput: f64,
+
+    /// The estimated time remaining, in seconds.
+    eta: Option<f64>,
+
+    source: Option<ApiVidstreamingDownloadSource>,
+
     error: Option<ApiError>,
 }
 
+#[derive(Debug, serde::Serialize)]
+struct ApiVidstreamingDownloadSource {
+    origin: &'static str,
+
+    #[serde(serialize_with = "serialize_arc_str")]
+    label: Arc<str>,
+    #[serde(serialize_with = "serialize_arc_str")]
+    url: Arc<str>,
+}
+
+impl ApiVidstreamingDownloadSource {
+    fn from_source(source: &VidstreamingDownloadSource) -> Self {
+        Self {
+            origin: source.origin.as_str(),
+            label: source.label.clone(),
+            url: source.url.clone(),
+        }
+    }
+}
+
+impl ApiVidstreamingDownloadState {
+    fn from_state(state: &VidstreamingDownloadState) -> Self {
+        let state = state.get_inner();
+
+        Self {
+            info: state.info.clone(),
+            progress: state.progress,
+            total_duration: state
+                .total_duration
+                .map(|total_duration| total_duration.as_secs_f64()),
+            downloaded_bytes: state.downloaded_bytes,
+            throughput: state.throughput,
+            eta: state.eta.map(|eta| eta.as_secs_f64()),
+            source: state
+                .source
+                .as_ref()
+                .map(ApiVidstreamingDownloadSource::from_source),
+            error: state.error.as_ref().map(ApiError::from),
+        }
+    }
+}
+
 #[derive(Debug, serde::Serialize)]
 #[serde(tag = "type")]
 enum ApiVidstreamingDownloadStateUpdate {
@@ -246,10 +840,51 @@ enum ApiVidstreamingDownloadStateUpdate {
     },
     #[serde(rename = "progress")]
     Progress { progress: f32 },
+    #[serde(rename = "total-duration")]
+    TotalDuration { total_duration: f64 },
+    #[serde(rename = "stats")]
+    Stats {
+        downloaded_bytes: u64,
+        throughput: f64,
+        eta: Option<f64>,
+    },
+    #[serde(rename = "source")]
+    Source {
+        source: ApiVidstreamingDownloadSource,
+    },
     #[serde(rename = "error")]
     Error { error: ApiError },
 }
 
+impl From<VidstreamingDownloadStateUpdate> for ApiVidstreamingDownloadStateUpdate {
+    fn from(update: VidstreamingDownloadStateUpdate) -> Self {
+        match update {
+            VidstreamingDownloadStateUpdate::Info { info } => Self::Info { info },
+            VidstreamingDownloadStateUpdate::Progress { progress } => Self::Progress { progress },
+            VidstreamingDownloadStateUpdate::TotalDuration { total_duration } => {
+                Self::TotalDuration {
+                    total_duration: total_duration.as_secs_f64(),
+                }
+            }
+            VidstreamingDownloadStateUpdate::Stats {
+                downloaded_bytes,
+                throughput,
+                eta,
+            } => Self::Stats {
+                downloaded_bytes,
+                throughput,
+                eta: eta.map(|eta| eta.as_secs_f64()),
+            },
+            VidstreamingDownloadStateUpdate::Source { source } => Self::Source {
+                source: ApiVidstreamingDownloadSource::from_source(&source),
+            },
+            VidstreamingDownloadStateUpdate::Error { error } => Self::Error {
+                error: ApiError::from(&error),
+            },
+        }
+    }
+}
+
 fn serialize_optional_arc_str<S>(v: &Option<Arc<str>>, s: S) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
@@ -266,12 +901,33 @@ where
     s.serialize_str(v)
 }
 
+#[derive(Debug, serde::Deserialize)]
+struct VidstreamingDownloadParams {
+    #[serde(rename = "type", default)]
+    anime_type: ApiAnimeType,
+
+    #[serde(rename = "max-resolution")]
+    max_resolution: Option<u32>,
+
+    #[serde(rename = "max-bandwidth")]
+    max_bandwidth: Option<u64>,
+
+    #[serde(rename = "preferred-codecs")]
+    preferred_codecs: Option<String>,
+}
+
 async fn api_vidstreaming_id_download(
     State(app_state): State<Arc<AppState>>,
     Path(id): Path<NonZeroU64>,
+    Query(params): Query<VidstreamingDownloadParams>,
 ) -> impl IntoResponse {
+    let quality = QualityPreferenceOverride {
+        max_resolution: params.max_resolution,
+        max_bandwidth: params.max_bandwidth,
+        preferred_codecs: params.preferred_codecs,
+    };
     let result = app_state
-        .start_vidstreaming_episode_download(id)
+        .start_vidstreaming_episode_download(id, params.anime_type.into(), quality)
         .await
         .map_err(|error| {
             error!("{error:?}");
@@ -282,33 +938,10 @@ async fn api_vidstreaming_id_download(
         Ok(result) => Sse::new(
             result
                 .map(|event| match event {
-                    StateUpdateItem::State(state) => {
-                        let state = state.get_inner();
-
-                        let state = ApiVidstreamingDownloadState {
-                            info: state.info.clone(),
-                            progress: state.progress,
-                            error: state.error.as_ref().map(ApiError::from),
-                        };
-
-                        sse::Event::default().json_data(state)
-                    }
-                    StateUpdateItem::Update(update) => match update {
-                        VidstreamingDownloadStateUpdate::Info { info } => {
-                            let update = ApiVidstreamingDownloadStateUpdate::Info { info };
-                            sse::Event::default().json_data(update)
-                        }
-                        VidstreamingDownloadStateUpdate::Progress { progress } => {
-                            let update = ApiVidstreamingDownloadStateUpdate::Progress { progress };
-                            sse::Event::default().json_data(update)
-                        }
-                        VidstreamingDownloadStateUpdate::Error { error } => {
-                            let update = ApiVidstreamingDownloadStateUpdate::Error {
-                                error: ApiError::from(&error),
-                            };
-                            sse::Event::default().json_data(update)
-                        }
-                    },
+                    StateUpdateItem::State(state) => sse::Event::default()
+                        .json_data(ApiVidstreamingDownloadState::from_state(&state)),
+                    StateUpdateItem::Update(update) => sse::Event::default()
+                        .json_data(ApiVidstreamingDownloadStateUpdate::from(update)),
                 })
                 .chain(tokio_stream::once(
                     sse::Event::default().event("close").json_data("close"),
@@ -318,3 +951,358 @@ async fn api_vidstreaming_id_download(
         Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response(),
     }
 }
+
+async fn api_vidstreaming_id_download_delete(
+    State(app_state): State<Arc<AppState>>,
+    Path(id): Path<NonZeroU64>,
+    Query(params): Query<VidstreamingAnimeTypeParams>,
+) -> impl IntoResponse {
+    let result = app_state
+        .cancel_vidstreaming_episode_download(id, params.anime_type.into())
+        .await
+        .map_err(|error| {
+            error!("{error:?}");
+            ApiError::from_anyhow(error)
+        });
+
+    match result {
+        Ok(()) => (StatusCode::OK, Json(())).into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response(),
+    }
+}
+
+async fn api_vidstreaming_id_download_pause(
+    State(app_state): State<Arc<AppState>>,
+    Path(id): Path<NonZeroU64>,
+    Query(params): Query<VidstreamingAnimeTypeParams>,
+) -> impl IntoResponse {
+    let result = app_state
+        .pause_vidstreaming_episode_download(id, params.anime_type.into())
+        .await
+        .map_err(|error| {
+            error!("{error:?}");
+            ApiError::from_anyhow(error)
+        });
+
+    match result {
+        Ok(()) => (StatusCode::OK, Json(())).into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response(),
+    }
+}
+
+async fn api_vidstreaming_id_download_resume(
+    State(app_state): State<Arc<AppState>>,
+    Path(id): Path<NonZeroU64>,
+    Query(params): Query<VidstreamingAnimeTypeParams>,
+) -> impl IntoResponse {
+    let result = app_state
+        .resume_vidstreaming_episode_download(id, params.anime_type.into())
+        .await
+        .map_err(|error| {
+            error!("{error:?}");
+            ApiError::from_anyhow(error)
+        });
+
+    match result {
+        Ok(()) => (StatusCode::OK, Json(())).into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response(),
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+enum ApiDownloadStatus {
+    #[serde(rename = "queued")]
+    Queued,
+    #[serde(rename = "paused")]
+    Paused,
+    #[serde(rename = "active")]
+    Active,
+    #[serde(rename = "finished")]
+    Finished,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ApiDownload {
+    episode_id: NonZeroU64,
+    anime_type: ApiAnimeType,
+    status: ApiDownloadStatus,
+
+    /// The number of seconds from the unix epoch.
+    started_at: Option<u64>,
+
+    state: ApiVidstreamingDownloadState,
+}
+
+impl ApiDownload {
+    fn from_summary(download: &VidstreamingDownloadSummary) -> Self {
+        let status = match download.status {
+            VidstreamingDownloadStatus::Queued => ApiDownloadStatus::Queued,
+            VidstreamingDownloadStatus::Paused => ApiDownloadStatus::Paused,
+            VidstreamingDownloadStatus::Active => ApiDownloadStatus::Active,
+            VidstreamingDownloadStatus::Finished => ApiDownloadStatus::Finished,
+        };
+        let started_at = download
+            .started_at
+            .and_then(|started_at| started_at.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|started_at| started_at.as_secs());
+
+        Self {
+            episode_id: download.episode_id,
+            anime_type: download.anime_type.into(),
+            status,
+            started_at,
+            state: ApiVidstreamingDownloadState::from_state(&download.state),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "type")]
+enum ApiDownloadsStateUpdate {
+    #[serde(rename = "status")]
+    Status { download: ApiDownload },
+    #[serde(rename = "download")]
+    Download {
+        episode_id: NonZeroU64,
+        anime_type: ApiAnimeType,
+        update: ApiVidstreamingDownloadStateUpdate,
+    },
+    #[serde(rename = "removed")]
+    Removed {
+        episode_id: NonZeroU64,
+        anime_type: ApiAnimeType,
+    },
+}
+
+async fn api_downloads(State(app_state): State<Arc<AppState>>) -> impl IntoResponse {
+    let downloads: Vec<_> = app_state
+        .get_vidstreaming_downloads()
+        .iter()
+        .map(ApiDownload::from_summary)
+        .collect();
+
+    (StatusCode::OK, Json(downloads))
+}
+
+async fn api_downloads_events(State(app_state): State<Arc<AppState>>) -> impl IntoResponse {
+    let stream = app_state
+        .subscribe_vidstreaming_downloads()
+        .map(|event| match event {
+            StateUpdateItem::State(state) => {
+                let downloads: Vec<_> = state
+                    .get_inner()
+                    .downloads
+                    .iter()
+                    .map(ApiDownload::from_summary)
+                    .collect();
+
+                sse::Event::default().json_data(downloads)
+            }
+            StateUpdateItem::Update(update) => {
+                let update = match update {
+                    VidstreamingDownloadsStateUpdate::Status { download } => {
+                        ApiDownloadsStateUpdate::Status {
+                            download: ApiDownload::from_summary(&download),
+                        }
+                    }
+                    VidstreamingDownloadsStateUpdate::Download {
+                        episode_id,
+                        anime_type,
+                        update,
+                    } => ApiDownloadsStateUpdate::Download {
+                        episode_id,
+                        anime_type: anime_type.into(),
+                        update: update.into(),
+                    },
+                    VidstreamingDownloadsStateUpdate::Removed {
+                        episode_id,
+                        anime_type,
+                    } => ApiDownloadsStateUpdate::Removed {
+                        episode_id,
+                        anime_type: anime_type.into(),
+                    },
+                };
+
+                sse::Event::default().json_data(update)
+            }
+        })
+        .chain(tokio_stream::once(
+            sse::Event::default().event("close").json_data("close"),
+        ));
+
+    Sse::new(stream)
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ApiVidstreamingDownloadQueueEntry {
+    episode_id: NonZeroU64,
+    anime_type: ApiAnimeType,
+    episode_number: u32,
+    slot: Option<usize>,
+    paused: bool,
+}
+
+async fn api_vidstreaming_queue(State(app_state): State<Arc<AppState>>) -> impl IntoResponse {
+    let result = app_state
+        .get_vidstreaming_download_queue()
+        .await
+        .map(|entries| {
+            entries
+                .iter()
+                .map(|entry| ApiVidstreamingDownloadQueueEntry {
+                    episode_id: entry.episode_id,
+                    anime_type: entry.anime_type.into(),
+                    episode_number: entry.episode_number,
+                    slot: entry.slot,
+                    paused: entry.paused,
+                })
+                .collect::<Vec<_>>()
+        })
+        .map_err(|error| {
+            error!("{error:?}");
+            ApiError::from_anyhow(error)
+        });
+
+    match result {
+        Ok(result) => (StatusCode::OK, Json(result)).into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response(),
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct VidstreamingQueueMoveParams {
+    position: usize,
+
+    #[serde(rename = "type", default)]
+    anime_type: ApiAnimeType,
+}
+
+async fn api_vidstreaming_queue_id_put(
+    State(app_state): State<Arc<AppState>>,
+    Path(id): Path<NonZeroU64>,
+    Query(params): Query<VidstreamingQueueMoveParams>,
+) -> impl IntoResponse {
+    let result = app_state
+        .move_vidstreaming_download_queue_entry(id, params.anime_type.into(), params.position)
+        .await
+        .map_err(|error| {
+            error!("{error:?}");
+            ApiError::from_anyhow(error)
+        });
+
+    match result {
+        Ok(()) => (StatusCode::OK, Json(())).into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response(),
+    }
+}
+
+async fn api_vidstreaming_queue_id_delete(
+    State(app_state): State<Arc<AppState>>,
+    Path(id): Path<NonZeroU64>,
+    Query(params): Query<VidstreamingAnimeTypeParams>,
+) -> impl IntoResponse {
+    let result = app_state
+        .cancel_vidstreaming_episode_download(id, params.anime_type.into())
+        .await
+        .map_err(|error| {
+            error!("{error:?}");
+            ApiError::from_anyhow(error)
+        });
+
+    match result {
+        Ok(()) => (StatusCode::OK, Json(())).into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response(),
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ApiVidstreamingAnimeMapping {
+    anime_id: NonZeroU64,
+    anime_type: ApiAnimeType,
+    slug: String,
+
+    /// The match score, from 0 to 1.
+    ///
+    /// This is null for manual mappings.
+    score: Option<f64>,
+    manual: bool,
+}
+
+impl From<VidstreamingAnimeMapping> for ApiVidstreamingAnimeMapping {
+    fn from(mapping: VidstreamingAnimeMapping) -> Self {
+        Self {
+            anime_id: mapping.anime_id,
+            anime_type: mapping.anime_type.into(),
+            slug: mapping.slug,
+            score: mapping.score,
+            manual: mapping.manual,
+        }
+    }
+}
+
+async fn api_vidstreaming_mappings_id(
+    State(app_state): State<Arc<AppState>>,
+    Path(id): Path<NonZeroU64>,
+    Query(params): Query<VidstreamingAnimeTypeParams>,
+) -> impl IntoResponse {
+    let result = app_state
+        .get_vidstreaming_anime_mapping(id, params.anime_type.into())
+        .await
+        .map(ApiVidstreamingAnimeMapping::from)
+        .map_err(|error| {
+            error!("{error:?}");
+            ApiError::from_anyhow(error)
+        });
+
+    match result {
+        Ok(result) => (StatusCode::OK, Json(result)).into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response(),
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct VidstreamingMappingParams {
+    slug: String,
+
+    #[serde(rename = "type", default)]
+    anime_type: ApiAnimeType,
+}
+
+async fn api_vidstreaming_mappings_id_put(
+    State(app_state): State<Arc<AppState>>,
+    Path(id): Path<NonZeroU64>,
+    Query(params): Query<VidstreamingMappingParams>,
+) -> impl IntoResponse {
+    let result = app_state
+        .set_vidstreaming_anime_mapping(id, params.anime_type.into(), &params.slug)
+        .await
+        .map(ApiVidstreamingAnimeMapping::from)
+        .map_err(|error| {
+            error!("{error:?}");
+            ApiError::from_anyhow(error)
+        });
+
+    match result {
+        Ok(result) => (StatusCode::OK, Json(result)).into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response(),
+    }
+}
+
+async fn api_vidstreaming_mappings_id_delete(
+    State(app_state): State<Arc<AppState>>,
+    Path(id): Path<NonZeroU64>,
+    Query(params): Query<VidstreamingAnimeTypeParams>,
+) -> impl IntoResponse {
+    let result = app_state
+        .remove_vidstreaming_anime_mapping(id, params.anime_type.into())
+        .await
+        .map_err(|error| {
+            error!("{error:?}");
+            ApiError::from_anyhow(error)
+        });
+
+    match result {
+        Ok(()) => (StatusCode::OK, Json(())).into_response(),
+        Err(error) => (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response(),
+    }
+}
@@ -0,0 +1,94 @@
+-- A database at schema version 0, as created before migrations were added.
+
+CREATE TABLE IF NOT EXISTS anime (
+    id INTEGER NOT NULL UNIQUE PRIMARY KEY,
+    
+    -- 0: Not Installed | The anime is considered not downloaded, though metadata may still exist.
+    -- 1: Installed     | The anime is considered downloaded, and metadata will exist. Thumbnails/covers must exist.
+    status INTEGER NOT NULL
+) STRICT;
+
+CREATE TABLE IF NOT EXISTS kitsu_anime (
+    id INTEGER NOT NULL UNIQUE PRIMARY KEY,
+    
+    slug TEXT NOT NULL UNIQUE,
+    synopsis TEXT,
+    title TEXT NOT NULL,
+    rating TEXT,
+    
+    poster_large TEXT NOT NULL,
+    
+    last_update INTEGER NOT NULL
+) STRICT;
+
+CREATE TABLE IF NOT EXISTS kitsu_episodes (
+    episode_id INTEGER NOT NULL UNIQUE PRIMARY KEY,
+    anime_id INTEGER NOT NULL,
+    
+    title TEXT,
+    synopsis TEXT,
+    length_minutes INTEGER,
+    
+    number INTEGER NOT NULL,
+    
+    thumbnail_original TEXT,
+    
+    last_update INTEGER NOT NULL,
+    
+    FOREIGN KEY (anime_id) REFERENCES kitsu_anime (id)
+) STRICT;
+
+INSERT INTO kitsu_anime (
+    id,
+    slug,
+    synopsis,
+    title,
+    rating,
+    poster_large,
+    last_update
+) VALUES (
+    1,
+    'cowboy-bebop',
+    'In the year 2071, humanity has colonized several of the planets and moons of the solar system.',
+    'Cowboy Bebop',
+    '82.23',
+    'https://media.kitsu.app/anime/poster_images/1/large.jpg',
+    1700000000
+);
+
+INSERT INTO kitsu_episodes (
+    episode_id,
+    anime_id,
+    title,
+    synopsis,
+    length_minutes,
+    number,
+    thumbnail_original,
+    last_update
+) VALUES (
+    1,
+    1,
+    'Asteroid Blues',
+    NULL,
+    25,
+    1,
+    'https://media.kitsu.app/episodes/thumbnails/1/original.jpg',
+    1700000000
+), (
+    2,
+    1,
+    'Stray Dog Strut',
+    NULL,
+    25,
+    2,
+    NULL,
+    1700000000
+);
+
+INSERT INTO anime (
+    id,
+    status
+) VALUES (
+    1,
+    1
+);